@@ -10,13 +10,33 @@ pub enum KernelTypeConfig {
 }
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
 #[serde(deny_unknown_fields)]
+pub enum BlockAggregationConfig {
+    // every block snapshot counts the same
+    Mean,
+    Median,
+    // a snapshot counts for the blocks until the next one
+    TimeWeighted,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
 pub struct PoxConfig {
     pub rayon_num_threads: usize,
+    // threads of the pool every request is evaluated in, 0 for the number of cores
+    pub request_num_threads: usize,
     pub coordinate_precision_bigint: u32,
     pub rspr_precision_bigint: u32,
 
     pub penalty: PenaltyConfig,
     pub kernel: KernelConfig,
+    pub zk: ZkConfig,
+    // how the results of the blocks in the requested range are combined
+    pub block_aggregation: BlockAggregationConfig,
+    pub anomaly: AnomalyConfig,
+    pub path_loss: PathLossConfig,
+    pub orbit: OrbitConfig,
+    pub service_quality: ServiceQualityConfig,
+    pub pof: PoFConfig,
+    pub pob: PoBConfig,
 
     pub pod_max_value: Decimal,
 }
@@ -33,7 +53,7 @@ impl Default for PoxConfig {
         Self {
             coordinate_precision_bigint: 3,
             rspr_precision_bigint: 4,
-            penalty: PenaltyConfig { max_diff: dec!(10) },
+            penalty: PenaltyConfig::default(),
             kernel: KernelConfig {
                 kernel_type: KernelTypeConfig::GaussianTaylor,
                 // kernel_type: KernelTypeConfig::Quadratic,
@@ -41,23 +61,253 @@ impl Default for PoxConfig {
                     sigma: dec!(500),
                     vanilla: GaussianVanillaConfig { use_coef: false },
                     taylor: GaussianTaylorConfig {
-                        max_order: 20,
-                        sigma_range: dec!(3.0),
+                        max_order: 4,
+                        sigma_range: dec!(2.0),
                     },
                 },
                 quadratic: QuadraticConfig {
                     max_dis_sqr: dec!(10000),
                 },
             },
+            zk: ZkConfig {
+                max_k: 14,
+                aggregate: true,
+                debug: false,
+            },
+            block_aggregation: BlockAggregationConfig::TimeWeighted,
+            anomaly: AnomalyConfig::default(),
+            path_loss: PathLossConfig::default(),
+            orbit: OrbitConfig::default(),
+            service_quality: ServiceQualityConfig::default(),
+            pof: PoFConfig::default(),
+            pob: PoBConfig::default(),
             pod_max_value: dec!(-100),
             rayon_num_threads: 0,
+            request_num_threads: 0,
         }
     }
 }
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
 #[serde(deny_unknown_fields)]
+pub enum PenaltyTypeConfig {
+    Linear,
+    Quadratic,
+    ExponentialDecay,
+    Huber,
+    Step,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
 pub struct PenaltyConfig {
+    pub penalty_type: PenaltyTypeConfig,
+    // weight of a terminal that agrees with its neighbours, no penalty gives more
     pub max_diff: Decimal,
+    pub exponential: ExponentialPenaltyConfig,
+    pub huber: HuberPenaltyConfig,
+    pub step: StepPenaltyConfig,
+    // score the terminals without proving their weights, the verifier recomputes them from
+    // the snapshot; only the Linear penalty has a circuit, the others need it
+    pub unproven: bool,
+}
+impl Default for PenaltyConfig {
+    fn default() -> Self {
+        Self {
+            penalty_type: PenaltyTypeConfig::Linear,
+            max_diff: dec!(10),
+            exponential: ExponentialPenaltyConfig {
+                decay: dec!(5),
+                max_order: 10,
+                range: dec!(3),
+            },
+            huber: HuberPenaltyConfig { delta: dec!(5) },
+            step: StepPenaltyConfig { threshold: dec!(5) },
+            unproven: false,
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct ExponentialPenaltyConfig {
+    // max_diff * exp(-diff / decay), with a taylor series of max_order
+    pub decay: Decimal,
+    pub max_order: usize,
+    // the weight is zero beyond range * decay, where the series diverges
+    pub range: Decimal,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct HuberPenaltyConfig {
+    // quadratic below delta, linear above
+    pub delta: Decimal,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct StepPenaltyConfig {
+    // full weight up to threshold, zero above
+    pub threshold: Decimal,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub enum AnomalyPolicyConfig {
+    // flag the terminals only
+    Report,
+    // their weight counts for down_weight_percent in the score
+    DownWeight,
+    // drop them before scoring, like the duplicates
+    Exclude,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct AnomalyConfig {
+    pub policy: AnomalyPolicyConfig,
+    pub down_weight_percent: u32,
+    // terminals closer than colocation_radius reporting rspr within rspr_tolerance are
+    // co-located, clusters of at least min_cluster_size of them are flagged
+    pub colocation_radius: Decimal,
+    pub rspr_tolerance: Decimal,
+    pub min_cluster_size: usize,
+    // free space bound, rspr is at most
+    // max_rspr_at_reference - 20 * log10(distance / reference_distance)
+    pub max_rspr_at_reference: Decimal,
+    pub reference_distance: Decimal,
+    // smaller sets of dropped packets are not compared between terminals
+    pub min_dropped_packets: usize,
+}
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            policy: AnomalyPolicyConfig::Report,
+            down_weight_percent: 50,
+            colocation_radius: dec!(1),
+            rspr_tolerance: dec!(0.5),
+            min_cluster_size: 3,
+            // far above any legal transmitter, only impossible readings are flagged
+            max_rspr_at_reference: dec!(60),
+            reference_distance: dec!(1),
+            min_dropped_packets: 10,
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct PathLossConfig {
+    // scale the PoD weight of a terminal by how well its rspr matches the model
+    pub enabled: bool,
+    // expected rspr = tx_power_dbm - extra_loss_db
+    //     - (10 * exponent * log10(d_km) + 20 * log10(frequency_mhz) + 32.44)
+    // with d the distance in meters from the remote to the terminal on the ground,
+    // exponent 2 is the free space
+    pub tx_power_dbm: Decimal,
+    pub frequency_mhz: Decimal,
+    pub exponent: Decimal,
+    pub extra_loss_db: Decimal,
+    // full weight within tolerance_db of the model, none beyond max_deviation_db
+    pub tolerance_db: Decimal,
+    pub max_deviation_db: Decimal,
+}
+impl Default for PathLossConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tx_power_dbm: dec!(20),
+            frequency_mhz: dec!(2400),
+            exponent: dec!(2),
+            extra_loss_db: dec!(0),
+            tolerance_db: dec!(10),
+            max_deviation_db: dec!(30),
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct OrbitConfig {
+    // scale the PoD score of a block by how well the reported track of the remote matches
+    // its TLE propagated to the time of the block
+    pub enabled: bool,
+    // unix time of block 0 and seconds between blocks, the time of block h is
+    // genesis_time + h * block_interval
+    pub genesis_time: i64,
+    pub block_interval: Decimal,
+    // heights and deviations in km, speeds in km/s; full score within the tolerance,
+    // none beyond the max deviation
+    pub position_tolerance: Decimal,
+    pub max_position_deviation: Decimal,
+    pub speed_tolerance: Decimal,
+    pub max_speed_deviation: Decimal,
+}
+impl Default for OrbitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            genesis_time: 0,
+            block_interval: dec!(6),
+            position_tolerance: dec!(10),
+            max_position_deviation: dec!(100),
+            speed_tolerance: dec!(0.1),
+            max_speed_deviation: dec!(1),
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceQualityConfig {
+    // scale the PoF packets of a terminal by the quality of its session with the remote
+    pub enabled: bool,
+    // in seconds, shorter sessions count in proportion, open ones count fully
+    pub full_session_duration: u64,
+    // in ms, a latency below the round trip to the height of the remote minus the slack
+    // is impossible and the terminal counts for nothing
+    pub latency_slack: Decimal,
+}
+impl Default for ServiceQualityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            full_session_duration: 3600,
+            latency_slack: dec!(1),
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub enum PoFModeConfig {
+    // open every dropped packet, the proof grows with the drops
+    Full,
+    // open sample_count packets challenged by a hash of the roots of both trees, their size
+    // and the statement, the drop rate is estimated from them
+    Sampled,
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct PoFConfig {
+    pub mode: PoFModeConfig,
+    pub sample_count: usize,
+    // z score of the confidence bounds of the drop rate, 1.96 for 95%, the valid packets
+    // credited are the ones at the upper bound
+    pub confidence_z: Decimal,
+}
+impl Default for PoFConfig {
+    fn default() -> Self {
+        Self {
+            mode: PoFModeConfig::Full,
+            sample_count: 64,
+            confidence_z: dec!(1.96),
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct PoBConfig {
+    // the ground bandwidth a remote claims may exceed the traffic its terminals observed
+    // by this much, beyond it the remote earns no PoB value; PoB is not served by the RPC
+    pub overclaim_tolerance_percent: u32,
+}
+impl Default for PoBConfig {
+    fn default() -> Self {
+        Self {
+            overclaim_tolerance_percent: 20,
+        }
+    }
 }
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
 #[serde(deny_unknown_fields)]
@@ -89,3 +339,15 @@ pub struct GaussianConfig {
 pub struct QuadraticConfig {
     pub max_dis_sqr: Decimal,
 }
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct ZkConfig {
+    // upper bound of the circuit size (2^max_k rows), bigger inputs are rejected
+    pub max_k: u32,
+    // prove all the terminals of a remote in a single proof, one proof per terminal
+    // is used when they do not fit in 2^max_k rows
+    pub aggregate: bool,
+    // check every circuit with the MockProver before proving, slow but the errors name the
+    // failing gate, region and row
+    pub debug: bool,
+}
@@ -2,7 +2,7 @@
 
 use rust_decimal::Decimal;
 use std::future::Future;
-use types::Remote;
+use types::{Remote, RemoteOrbit};
 mod error;
 use error::*;
 mod mock;
@@ -18,4 +18,11 @@ pub trait DaLayerTrait {
         block_height_from: u64,
         block_height_to: u64,
     ) -> impl std::future::Future<Output = Result<Vec<(usize, Remote<Decimal>)>, Error>>;
+    /// TLE of the remote with its reported tracks in the range, None if it has no TLE.
+    fn fetch_remote_orbit_block_from_to(
+        &self,
+        remote_address: &str,
+        block_height_from: u64,
+        block_height_to: u64,
+    ) -> impl std::future::Future<Output = Result<Option<RemoteOrbit>, Error>>;
 }
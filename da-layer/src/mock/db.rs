@@ -1,5 +1,5 @@
 use crate::error::Error;
-use crate::{ip_packets, p2p, remote_track};
+use crate::{ip_packets, p2p, remote, remote_track};
 use config::MySQLConfig;
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, ConnectOptions, EntityTrait, QueryFilter, QueryOrder, Set,
@@ -88,6 +88,16 @@ impl Db {
             .map_err(|e| Error::DbErr("remove_p2p_by_address error".to_string(), e))?;
         Ok(())
     }
+    pub async fn find_remote_by_address(
+        &self,
+        remote_address: &str,
+    ) -> Result<Option<remote::Model>, Error> {
+        remote::Entity::find()
+            .filter(remote::Column::ValidatorAddress.eq(remote_address))
+            .one(&self.db)
+            .await
+            .map_err(|e| Error::DbErr("find_remote_by_address error".to_string(), e))
+    }
     pub async fn find_all_remote_track_with_single_remote_block_from_to(
         &self,
         remote_address: &str,
@@ -7,7 +7,7 @@ use flat_projection::*;
 pub use models::*;
 use rust_decimal::Decimal;
 use tracing::{debug, error, warn};
-use types::{CompletePackets, Packet, Packets,  Pos3D, Remote, Terminal};
+use types::{Bandwidth, CompletePackets, Packet, Packets,  Pos3D, Remote, RemoteOrbit, RemoteTrack, ServiceQuality, Terminal, Tle};
 use crate::{DaLayerTrait, Error};
 // use proj::{Coord, Proj};
 
@@ -114,31 +114,31 @@ impl DaLayerTrait for MockLocalDB {
                 let remote_packets =
                     remote_packets
                         .get(&blocknum_saddress)
-                        .and_then(|packet_model| {
-                            let mut packet_model = packet_model
+                        .map(|packet_model| {
+                            let packet_model = packet_model
                                 .iter()
+                                .filter(|packet_model| packet_model.ip_sequence >= 0)
                                 .map(|packet_model| {
                                     (
-                                        packet_model.ip_sequence as usize,
+                                        packet_model.ip_sequence as u64,
                                         Packet {
                                             data: packet_model.ip_packet_data.clone(),
                                         },
                                     )
                                 })
                                 .collect::<Vec<_>>();
-                            packet_model.sort_by_key(|(ip_sequence, _)| *ip_sequence);
-                            let (ip_sequence, data): (Vec<_>, Vec<_>) =
-                                packet_model.into_iter().unzip();
-                            //check ip_sequence is continuous
-                            if ip_sequence.iter().enumerate().all(|(i, v)| i == *v) {
-                                Some(CompletePackets { data })
-                            } else {
+                            // log rows may be lost, the remote attests to the sequences it has
+                            let packets = CompletePackets::from_sequenced(packet_model);
+                            if packets.span() != packets.data.len() as u64 {
                                 warn!(
-                                    "ip_sequence is not continuous for remote {}",
-                                    remote_address
+                                    "ip_sequence is not continuous for remote {}, {} of {} packets in {} ranges",
+                                    remote_address,
+                                    packets.data.len(),
+                                    packets.span(),
+                                    packets.ranges.len()
                                 );
-                                None
                             }
+                            packets
                             //     .max_by_key(|m| m.ip_sequence)
                             //     .and_then(|max_seq| {
                             //         packet_model.iter().all(|packet_model| {
@@ -198,14 +198,23 @@ impl DaLayerTrait for MockLocalDB {
                                                 .collect::<Result<Vec<_>, _>>()
                                                 .ok()?
                                             };
-                                        let mut p = remote_packets
-                                            .clone()
-                                            .data
-                                            .into_iter()
-                                            .map(Some)
-                                            .collect::<Vec<_>>();
+                                        // by sequence number, the gaps of the remote are None
+                                        let mut p = vec![None; remote_packets.span() as usize];
+                                        remote_packets
+                                            .sequences()
+                                            .zip(&remote_packets.data)
+                                            .for_each(|(sequence, packet)| {
+                                                p[sequence as usize] = Some(packet.clone());
+                                            });
                                         dropped_indices.iter().for_each(|i| {
-                                            p[*i] = None;
+                                            if let Some(packet) = p.get_mut(*i) {
+                                                *packet = None;
+                                            } else {
+                                                warn!(
+                                                    "dropped index {} is beyond the packets of remote {}",
+                                                    i, remote_address
+                                                );
+                                            }
                                         });
                                         Some(Packets { data: p })
                                     })
@@ -220,6 +229,16 @@ impl DaLayerTrait for MockLocalDB {
                                     terminal_track.signal_strength as f64,
                                     terminal_packets,
                                 )
+                                .map(|terminal| Terminal {
+                                    service: Some(ServiceQuality {
+                                        connect_time: terminal_track.connect_time,
+                                        disconnect_time: terminal_track.disconnect_time,
+                                        net_latency: terminal_track.net_latency,
+                                        net_bandwidth: terminal_track.net_bandwidth,
+                                        net_traffic: terminal_track.net_traffic,
+                                    }),
+                                    ..terminal
+                                })
                                 .map_err(|e| {
                                     let err = crate::Error::TypesError(e);
                                     error!("{}", err);
@@ -236,6 +255,10 @@ impl DaLayerTrait for MockLocalDB {
                     position: remote_position,
                     terminals,
                     remote_packets,
+                    bandwidth: Some(Bandwidth {
+                        ground: remote_track.bandwidth_ground as i64,
+                        space: remote_track.bandwidth_space as i64,
+                    }),
                 })
             })
             .fold(HashMap::new(), |mut acc, remote|{
@@ -253,6 +276,46 @@ impl DaLayerTrait for MockLocalDB {
         Ok(remotes)
     }
 
+    async fn fetch_remote_orbit_block_from_to(
+        &self,
+        remote_address: &str,
+        block_height_from: u64,
+        block_height_to: u64,
+    ) -> Result<Option<RemoteOrbit>, crate::Error> {
+        let Some(remote) = self.db.find_remote_by_address(remote_address).await? else {
+            debug!(message = "remote has no TLE", remote_address);
+            return Ok(None);
+        };
+        let mut tracks: Vec<RemoteTrack> = self
+            .db
+            .find_all_remote_track_with_single_remote_block_from_to(
+                remote_address,
+                block_height_from,
+                block_height_to,
+            )
+            .await?
+            .into_iter()
+            .map(|track| RemoteTrack {
+                block_height: track.block_number as u64,
+                longitude: track.x as f64,
+                latitude: track.y as f64,
+                height: track.height as f64,
+                speed: track.speed as f64,
+            })
+            .collect();
+        // the first track of a block wins, as for the snapshots
+        tracks.dedup_by_key(|track| track.block_height);
+        Ok(Some(RemoteOrbit {
+            address: remote.validator_address,
+            tle: Tle {
+                line0: remote.tle_l0,
+                line1: remote.tle_l1,
+                line2: remote.tle_l2,
+            },
+            tracks,
+        }))
+    }
+
     async fn new(cfg: &config::DaLayerConfig) -> Result<Self, crate::Error> {
         // let from = "EPSG:4326";
         // let to = "EPSG:3309";
@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+
+use config::{BlockAggregationConfig, PoxConfig};
+use halo2_proofs::pasta::Fp;
+use num_bigint::BigInt;
+use num_rational::Ratio;
+use rust_decimal::prelude::Zero;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use types::{Error, Remote, RemoteOrbit};
+use util::serde_bin::SerdeBinTrait;
+
+use crate::{
+    check_orbit, count_status, merge_status, CancellationToken, OrbitCheck, PoDRemoteResult,
+    PoDVerify, PoFRemoteResult, PoFVerify, PoX, TerminalStatus,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockResult<R> {
+    pub block_height: u64,
+    pub result: R,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalAggregate {
+    pub terminal_address: String,
+    pub value: BigInt,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoDRangeResult {
+    pub score: BigInt,
+    // weight of every terminal seen in the range after the anomaly policy, zero in the
+    // blocks it is missing from
+    pub terminal_weights: Vec<TerminalAggregate>,
+    pub blocks: Vec<BlockResult<PoDRemoteResult<BigInt>>>,
+    // the score of a block is scaled by the check of its height, empty if disabled
+    pub orbit: Vec<BlockResult<OrbitCheck>>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoFRangeResult {
+    pub value: BigInt,
+    // valid and invalid packets of every terminal seen in the range
+    pub terminal_packets: Vec<TerminalAggregate>,
+    pub blocks: Vec<BlockResult<PoFRemoteResult<BigInt>>>,
+}
+impl SerdeBinTrait for PoDRangeResult {}
+impl SerdeBinTrait for PoFRangeResult {}
+
+/// The proofs of a block are bound to its own height, the range of the request is checked
+/// against the heights of the blocks.
+pub(crate) fn block_statement(statement: &zkt::Statement, block_height: u64) -> zkt::Statement {
+    zkt::Statement {
+        block_from: block_height,
+        block_to: block_height,
+        ..statement.clone()
+    }
+}
+/// Number of blocks every snapshot stands for, from its height to the next one. The first
+/// one also covers the start of the range, the last one its end.
+fn block_spans(heights: &[u64], block_from: u64, block_to: u64) -> Vec<u64> {
+    heights
+        .iter()
+        .enumerate()
+        .map(|(i, &height)| {
+            let start = if i == 0 {
+                block_from.min(height)
+            } else {
+                height
+            };
+            let end = match heights.get(i + 1) {
+                Some(&next) => next,
+                None => block_to.max(height).saturating_add(1),
+            };
+            end.saturating_sub(start).max(1)
+        })
+        .collect()
+}
+/// Heights of the range of the statement in order, once each.
+fn range_heights(heights: impl Iterator<Item = u64>, statement: &zkt::Statement) -> Vec<u64> {
+    let mut heights: Vec<u64> = heights
+        .filter(|h| (statement.block_from..=statement.block_to).contains(h))
+        .collect();
+    heights.sort_unstable();
+    heights.dedup();
+    heights
+}
+/// The blocks of a result are exactly the expected ones, in order: leaving one out would
+/// change the aggregation.
+fn check_heights(heights: &[u64], expected: &[u64]) -> Result<(), String> {
+    if expected.is_empty() {
+        return Err("no block in the range".to_owned());
+    }
+    if heights != expected {
+        return Err(format!(
+            "block heights {:?} are not the heights {:?} of the range",
+            heights, expected
+        ));
+    }
+    Ok(())
+}
+/// Combines the values of the blocks, spans[i] is the number of blocks values[i] stands for.
+fn aggregate(method: &BlockAggregationConfig, values: &[BigInt], spans: &[u64]) -> BigInt {
+    if values.is_empty() {
+        return BigInt::zero();
+    }
+    match method {
+        BlockAggregationConfig::Mean => {
+            Ratio::new(values.iter().sum::<BigInt>(), BigInt::from(values.len())).to_integer()
+        }
+        BlockAggregationConfig::Median => {
+            let mut sorted = values.to_vec();
+            sorted.sort();
+            let mid = sorted.len() / 2;
+            if sorted.len() % 2 == 1 {
+                sorted[mid].clone()
+            } else {
+                Ratio::new(&sorted[mid - 1] + &sorted[mid], BigInt::from(2)).to_integer()
+            }
+        }
+        BlockAggregationConfig::TimeWeighted => {
+            let total = values
+                .iter()
+                .zip(spans)
+                .map(|(v, s)| v * BigInt::from(*s))
+                .sum::<BigInt>();
+            let span = spans.iter().map(|s| BigInt::from(*s)).sum::<BigInt>();
+            Ratio::new(total, span).to_integer()
+        }
+    }
+}
+/// Aggregates the values of every terminal by address, a terminal missing from a block
+/// counts as zero there.
+fn aggregate_terminals(
+    method: &BlockAggregationConfig,
+    spans: &[u64],
+    blocks: Vec<Vec<(String, BigInt)>>,
+) -> Vec<TerminalAggregate> {
+    let mut values: BTreeMap<String, Vec<BigInt>> = BTreeMap::new();
+    for (i, terminals) in blocks.into_iter().enumerate() {
+        for (address, value) in terminals {
+            values
+                .entry(address)
+                .or_insert_with(|| vec![BigInt::zero(); spans.len()])[i] = value;
+        }
+    }
+    values
+        .into_iter()
+        .map(|(address, values)| TerminalAggregate {
+            terminal_address: address,
+            value: aggregate(method, &values, spans),
+        })
+        .collect()
+}
+
+impl PoDRangeResult {
+    pub fn new(
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        blocks: Vec<BlockResult<PoDRemoteResult<BigInt>>>,
+        orbit: Vec<BlockResult<OrbitCheck>>,
+    ) -> Self {
+        let (score, terminal_weights) = Self::aggregate(cfg, statement, &blocks, &orbit);
+        debug!(message = "PoD range result", blocks = blocks.len(), ?score);
+        Self {
+            score,
+            terminal_weights,
+            blocks,
+            orbit,
+        }
+    }
+    fn aggregate(
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        blocks: &[BlockResult<PoDRemoteResult<BigInt>>],
+        orbit: &[BlockResult<OrbitCheck>],
+    ) -> (BigInt, Vec<TerminalAggregate>) {
+        let heights: Vec<u64> = blocks.iter().map(|b| b.block_height).collect();
+        let spans = block_spans(&heights, statement.block_from, statement.block_to);
+        let scores: Vec<BigInt> = blocks
+            .iter()
+            .map(|b| {
+                let percent = orbit
+                    .iter()
+                    .find(|o| o.block_height == b.block_height)
+                    .map_or(100, |o| o.result.weight_percent);
+                Ratio::new(b.result.score.clone() * percent, BigInt::from(100)).to_integer()
+            })
+            .collect();
+        let terminals = blocks
+            .iter()
+            .map(|b| {
+                b.result
+                    .terminal_results
+                    .iter()
+                    .map(|t| (t.terminal_address.clone(), b.result.effective_weight(t)))
+                    .collect()
+            })
+            .collect();
+        (
+            aggregate(&cfg.block_aggregation, &scores, &spans),
+            aggregate_terminals(&cfg.block_aggregation, &spans, terminals),
+        )
+    }
+    /// Verifies the proofs of every block against its snapshot, and that the claimed score
+    /// and weights are the aggregation of the blocks. Every snapshot in the range without a
+    /// block adds a failure, and so do blocks that are not the snapshots of the range or a
+    /// wrong aggregation at the end.
+    pub fn verify(
+        &self,
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        snapshots: &[(u64, Remote<BigInt>)],
+    ) -> Vec<PoDVerify> {
+        let mut results: Vec<PoDVerify> = self
+            .blocks
+            .iter()
+            .flat_map(|b| {
+                match snapshots
+                    .iter()
+                    .find(|(height, _)| *height == b.block_height)
+                {
+                    Some((_, remote)) => {
+                        b.result
+                            .verify(cfg, &block_statement(statement, b.block_height), remote)
+                    }
+                    None => vec![PoDVerify::Fail(format!(
+                        "PoD: block {} verify failed: no snapshot of the block",
+                        b.block_height
+                    ))],
+                }
+            })
+            .collect();
+        let heights: Vec<u64> = self.blocks.iter().map(|b| b.block_height).collect();
+        let expected = range_heights(snapshots.iter().map(|(height, _)| *height), statement);
+        results.extend(
+            expected
+                .iter()
+                .filter(|height| !heights.contains(height))
+                .map(|height| {
+                    PoDVerify::Fail(format!(
+                        "PoD: block {} verify failed: no block for the snapshot",
+                        height
+                    ))
+                }),
+        );
+        let checked = check_heights(&heights, &expected).and_then(|_| {
+            let orbit_heights: Vec<u64> = self.orbit.iter().map(|o| o.block_height).collect();
+            if cfg.orbit.enabled && orbit_heights != heights {
+                return Err(format!(
+                    "orbit checked at {:?}, not at the blocks {:?}",
+                    orbit_heights, heights
+                ));
+            }
+            if (self.score.clone(), self.terminal_weights.clone())
+                != Self::aggregate(cfg, statement, &self.blocks, &self.orbit)
+            {
+                return Err(
+                    "claimed score and weights are not the aggregation of the blocks".to_owned(),
+                );
+            }
+            Ok(())
+        });
+        if let Err(e) = checked {
+            results.push(PoDVerify::Fail(format!("PoD: range verify failed: {}", e)));
+        }
+        results
+    }
+    /// Checks the claimed orbit checks against the TLE and tracks of the remote.
+    pub fn verify_orbit(&self, orbit: Option<&RemoteOrbit>, cfg: &PoxConfig) -> PoDVerify {
+        let heights: Vec<u64> = self.blocks.iter().map(|b| b.block_height).collect();
+        match check_orbit(orbit, &heights, cfg) {
+            Ok(checks) if checks == self.orbit => PoDVerify::Success,
+            Ok(_) => PoDVerify::Fail(
+                "PoD: claimed orbit checks do not match the TLE of the remote".to_owned(),
+            ),
+            Err(e) => PoDVerify::Fail(format!("PoD: orbit check failed: {}", e)),
+        }
+    }
+    /// Status of every terminal seen in the range, the first one of its blocks that is
+    /// not Ok.
+    pub fn terminal_status(&self) -> BTreeMap<String, TerminalStatus> {
+        merge_status(self.blocks.iter().flat_map(|b| {
+            b.result
+                .terminal_results
+                .iter()
+                .map(|t| (&t.terminal_address, t.status))
+        }))
+    }
+    /// Number of terminal results of every status over the blocks.
+    pub fn status_counts(&self) -> BTreeMap<TerminalStatus, usize> {
+        count_status(
+            self.blocks
+                .iter()
+                .flat_map(|b| b.result.terminal_results.iter().map(|t| t.status)),
+        )
+    }
+}
+
+impl PoFRangeResult {
+    pub fn new(
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        blocks: Vec<BlockResult<PoFRemoteResult<BigInt>>>,
+    ) -> Self {
+        let (value, terminal_packets) = Self::aggregate(cfg, statement, &blocks);
+        debug!(message = "PoF range result", blocks = blocks.len(), ?value);
+        Self {
+            value,
+            terminal_packets,
+            blocks,
+        }
+    }
+    fn aggregate(
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        blocks: &[BlockResult<PoFRemoteResult<BigInt>>],
+    ) -> (BigInt, Vec<TerminalAggregate>) {
+        let heights: Vec<u64> = blocks.iter().map(|b| b.block_height).collect();
+        let spans = block_spans(&heights, statement.block_from, statement.block_to);
+        let values: Vec<BigInt> = blocks.iter().map(|b| b.result.value.clone()).collect();
+        let terminals = blocks
+            .iter()
+            .map(|b| {
+                b.result
+                    .terminal_results
+                    .iter()
+                    .map(|t| {
+                        (
+                            t.terminal_address.clone(),
+                            t.service_scaled(
+                                &(t.valid_packets_num.clone() + t.invalid_packets_num.clone()),
+                            ),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        (
+            aggregate(&cfg.block_aggregation, &values, &spans),
+            aggregate_terminals(&cfg.block_aggregation, &spans, terminals),
+        )
+    }
+    /// Verifies the merkle proofs of every block, and that the claimed value and packets are
+    /// the aggregation of the blocks. heights are the ones of the snapshots of the remote,
+    /// every one in the range without a block adds a failure, and so do blocks that are not
+    /// at those heights or a wrong aggregation at the end.
+    pub fn verify(
+        &self,
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        heights: &[u64],
+    ) -> Vec<PoFVerify> {
+        let mut results: Vec<PoFVerify> = self
+            .blocks
+            .iter()
+            .flat_map(|b| {
+                b.result
+                    .verify(cfg, &block_statement(statement, b.block_height))
+            })
+            .collect();
+        let blocks: Vec<u64> = self.blocks.iter().map(|b| b.block_height).collect();
+        let expected = range_heights(heights.iter().copied(), statement);
+        results.extend(
+            expected
+                .iter()
+                .filter(|height| !blocks.contains(height))
+                .map(|height| {
+                    PoFVerify::Fail(format!(
+                        "PoF: block {} verify failed: no block for the snapshot",
+                        height
+                    ))
+                }),
+        );
+        let checked = check_heights(&blocks, &expected).and_then(|_| {
+            if (self.value.clone(), self.terminal_packets.clone())
+                != Self::aggregate(cfg, statement, &self.blocks)
+            {
+                return Err(
+                    "claimed value and packets are not the aggregation of the blocks".to_owned(),
+                );
+            }
+            Ok(())
+        });
+        if let Err(e) = checked {
+            results.push(PoFVerify::Fail(format!("PoF: range verify failed: {}", e)));
+        }
+        results
+    }
+    /// Status of every terminal seen in the range, the first one of its blocks that is
+    /// not Ok.
+    pub fn terminal_status(&self) -> BTreeMap<String, TerminalStatus> {
+        merge_status(self.blocks.iter().flat_map(|b| {
+            b.result
+                .terminal_results
+                .iter()
+                .map(|t| (&t.terminal_address, t.status))
+        }))
+    }
+    /// Number of terminal results of every status over the blocks.
+    pub fn status_counts(&self) -> BTreeMap<TerminalStatus, usize> {
+        count_status(
+            self.blocks
+                .iter()
+                .flat_map(|b| b.result.terminal_results.iter().map(|t| t.status)),
+        )
+    }
+}
+
+/// The snapshots in the range of the statement by height, one per block.
+pub(crate) fn range_snapshots(
+    mut snapshots: Vec<(u64, Remote<BigInt>)>,
+    statement: &zkt::Statement,
+) -> Result<Vec<(u64, Remote<BigInt>)>, Error> {
+    snapshots.sort_by_key(|(height, _)| *height);
+    snapshots.dedup_by_key(|(height, _)| *height);
+    snapshots.retain(|(height, _)| (statement.block_from..=statement.block_to).contains(height));
+    if snapshots.is_empty() {
+        return Err(Error::EmptyBlockRangeErr(
+            statement.block_from,
+            statement.block_to,
+        ));
+    }
+    Ok(snapshots)
+}
+/// Evaluates PoD and PoF for every block snapshot in the range of the statement, and
+/// combines them with the block aggregation of cfg. The track of the remote is checked
+/// against orbit if the check is enabled. It fails with CancelledErr once cancel is
+/// cancelled.
+pub fn eval_range<ZK>(
+    snapshots: Vec<(u64, Remote<BigInt>)>,
+    orbit: Option<&RemoteOrbit>,
+    zkp: ZK,
+    cfg: &PoxConfig,
+    statement: &zkt::Statement,
+    cancel: &CancellationToken,
+) -> Result<(PoDRangeResult, PoFRangeResult), Error>
+where
+    ZK: zkt::ZkTraitHalo2<F = Fp> + Clone,
+{
+    let _span = tracing::debug_span!("eval_range").entered();
+    let snapshots = range_snapshots(snapshots, statement)?;
+    let heights: Vec<u64> = snapshots.iter().map(|(height, _)| *height).collect();
+    let orbit = check_orbit(orbit, &heights, cfg)?;
+    let mut pod_blocks = Vec::with_capacity(snapshots.len());
+    let mut pof_blocks = Vec::with_capacity(snapshots.len());
+    for (block_height, remote) in snapshots {
+        cancel.check()?;
+        let pox = PoX::new(
+            remote,
+            zkp.clone(),
+            cfg,
+            block_statement(statement, block_height),
+        )?
+        .with_cancellation(cancel.clone());
+        let (pod, pof) = (pox.eval_pod(), pox.eval_pof());
+        // the results of a cancelled block are incomplete
+        cancel.check()?;
+        pod_blocks.push(BlockResult {
+            block_height,
+            result: pod,
+        });
+        pof_blocks.push(BlockResult {
+            block_height,
+            result: pof,
+        });
+    }
+    Ok((
+        PoDRangeResult::new(cfg, statement, pod_blocks, orbit),
+        PoFRangeResult::new(cfg, statement, pof_blocks),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(values: &[i64]) -> Vec<BigInt> {
+        values.iter().map(|v| BigInt::from(*v)).collect()
+    }
+
+    #[test]
+    fn test_block_spans() {
+        assert_eq!(block_spans(&[10, 12, 15], 10, 20), vec![2, 3, 6]);
+        // the first snapshot covers the start of the range
+        assert_eq!(block_spans(&[13, 15], 10, 20), vec![5, 6]);
+        assert_eq!(block_spans(&[20], 10, 20), vec![11]);
+        assert_eq!(block_spans(&[u64::MAX], 0, u64::MAX), vec![u64::MAX]);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let values = ints(&[10, -4, 30, 1]);
+        let spans = [1, 1, 6, 2];
+        assert_eq!(
+            aggregate(&BlockAggregationConfig::Mean, &values, &spans),
+            BigInt::from(9)
+        );
+        assert_eq!(
+            aggregate(&BlockAggregationConfig::Median, &values, &spans),
+            BigInt::from(5)
+        );
+        assert_eq!(
+            aggregate(&BlockAggregationConfig::Median, &values[..3], &spans[..3]),
+            BigInt::from(10)
+        );
+        // (10 - 4 + 180 + 2) / 10
+        assert_eq!(
+            aggregate(&BlockAggregationConfig::TimeWeighted, &values, &spans),
+            BigInt::from(18)
+        );
+        assert_eq!(
+            aggregate(&BlockAggregationConfig::Mean, &[], &[]),
+            BigInt::zero()
+        );
+    }
+
+    #[test]
+    fn test_aggregate_terminals() {
+        let blocks = vec![
+            vec![
+                ("0x1".to_owned(), BigInt::from(4)),
+                ("0x2".to_owned(), BigInt::from(8)),
+            ],
+            vec![("0x1".to_owned(), BigInt::from(2))],
+        ];
+        assert_eq!(
+            aggregate_terminals(&BlockAggregationConfig::TimeWeighted, &[1, 3], blocks),
+            vec![
+                TerminalAggregate {
+                    terminal_address: "0x1".to_owned(),
+                    value: BigInt::from(2),
+                },
+                TerminalAggregate {
+                    terminal_address: "0x2".to_owned(),
+                    value: BigInt::from(2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_heights() {
+        let statement = zkt::Statement {
+            block_from: 10,
+            block_to: 20,
+            ..Default::default()
+        };
+        let expected = range_heights([20, 9, 15, 10, 15, 21].into_iter(), &statement);
+        assert_eq!(expected, vec![10, 15, 20]);
+        assert!(check_heights(&[10, 15, 20], &expected).is_ok());
+        assert!(check_heights(&[], &[]).is_err());
+        assert!(check_heights(&[], &expected).is_err());
+        // a block left out, repeated or out of order
+        assert!(check_heights(&[10, 20], &expected).is_err());
+        assert!(check_heights(&[10, 15, 15, 20], &expected).is_err());
+        assert!(check_heights(&[15, 10, 20], &expected).is_err());
+        assert!(check_heights(&[10, 15, 20, 21], &expected).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{BTreeMap, HashMap};
+
+use config::{AnomalyPolicyConfig, PoxConfig};
+use num_bigint::BigInt;
+use rust_decimal::prelude::Signed;
+use serde::{Deserialize, Serialize};
+use types::{CompletePackets, Error, FixedPointInteger, Remote, Terminal};
+
+use crate::{GridIndex, PathLoss, PosTrait};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum AnomalyKind {
+    // one of a cluster of terminals at the same place reporting the same rspr
+    Colocated,
+    // rspr stronger than the free space bound at its distance to the remote
+    ImplausibleRspr,
+    // the same dropped packets as another terminal
+    ReusedDropPattern,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalAnomaly {
+    pub terminal_address: String,
+    pub kinds: Vec<AnomalyKind>,
+    // dropped before scoring
+    pub excluded: bool,
+    // share of its weight that counts in the score
+    pub weight_percent: u32,
+}
+
+/// Flags the terminals of the remote and applies the policy of cfg, sorted by address.
+pub(crate) fn detect_anomalies(
+    remote: &Remote<BigInt>,
+    cfg: &PoxConfig,
+) -> Result<Vec<TerminalAnomaly>, Error> {
+    let (excluded, weight_percent) = match cfg.anomaly.policy {
+        AnomalyPolicyConfig::Report => (false, 100),
+        AnomalyPolicyConfig::DownWeight if cfg.anomaly.down_weight_percent > 100 => {
+            return Err(Error::InvalidAnomalyErr(format!(
+                "down_weight_percent {} is over 100",
+                cfg.anomaly.down_weight_percent
+            )))
+        }
+        AnomalyPolicyConfig::DownWeight => (false, cfg.anomaly.down_weight_percent),
+        AnomalyPolicyConfig::Exclude => (true, 0),
+    };
+    let mut kinds: BTreeMap<usize, Vec<AnomalyKind>> = BTreeMap::new();
+    let mut flag = |indices: Vec<usize>, kind: AnomalyKind| {
+        indices
+            .into_iter()
+            .for_each(|i| kinds.entry(i).or_default().push(kind))
+    };
+    flag(colocated(&remote.terminals, cfg)?, AnomalyKind::Colocated);
+    flag(implausible_rspr(remote, cfg)?, AnomalyKind::ImplausibleRspr);
+    flag(
+        reused_drop_patterns(
+            &remote.terminals,
+            remote.remote_packets.as_ref(),
+            cfg.anomaly.min_dropped_packets,
+        ),
+        AnomalyKind::ReusedDropPattern,
+    );
+    let mut anomalies: Vec<TerminalAnomaly> = kinds
+        .into_iter()
+        .map(|(i, kinds)| TerminalAnomaly {
+            terminal_address: remote.terminals[i].address.clone(),
+            kinds,
+            excluded,
+            weight_percent,
+        })
+        .collect();
+    anomalies.sort_by(|a, b| a.terminal_address.cmp(&b.terminal_address));
+    Ok(anomalies)
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+/// Terminals in clusters of at least min_cluster_size, linked by being closer than the
+/// colocation radius with rspr within the tolerance.
+fn colocated(terminals: &[Terminal<BigInt>], cfg: &PoxConfig) -> Result<Vec<usize>, Error> {
+    let radius = BigInt::fixed_from_decimal(
+        cfg.anomaly.colocation_radius,
+        cfg.coordinate_precision_bigint,
+    )?;
+    let tolerance =
+        BigInt::fixed_from_decimal(cfg.anomaly.rspr_tolerance, cfg.rspr_precision_bigint)?;
+    let radius_sqr = &radius * &radius;
+    let index = GridIndex::new(terminals.iter().map(|t| &t.position), &radius_sqr);
+    let mut parent: Vec<usize> = (0..terminals.len()).collect();
+    for (i, t1) in terminals.iter().enumerate() {
+        for j in index.candidates(&t1.position) {
+            let t2 = &terminals[j];
+            if j > i
+                && t1.position.dist_sqr(&t2.position) < radius_sqr
+                && (&t1.alpha.rspr - &t2.alpha.rspr).abs() <= tolerance
+            {
+                let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let roots: Vec<usize> = (0..terminals.len())
+        .map(|i| find_root(&mut parent, i))
+        .collect();
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    roots
+        .iter()
+        .for_each(|r| *sizes.entry(*r).or_default() += 1);
+    let min_size = cfg.anomaly.min_cluster_size.max(2);
+    Ok((0..terminals.len())
+        .filter(|i| sizes[&roots[*i]] >= min_size)
+        .collect())
+}
+/// Terminals reporting a stronger rspr than the free space bound at their distance to the
+/// remote.
+fn implausible_rspr(remote: &Remote<BigInt>, cfg: &PoxConfig) -> Result<Vec<usize>, Error> {
+    let bound = PathLoss::free_space(
+        cfg.anomaly.max_rspr_at_reference,
+        cfg.anomaly.reference_distance,
+        cfg,
+    )
+    .map_err(|e| Error::InvalidAnomalyErr(e.to_string()))?;
+    let mut flagged = Vec::new();
+    for (i, t) in remote.terminals.iter().enumerate() {
+        if bound.rspr(&t.alpha.rspr)? > bound.expected_rspr_at(&remote.position, &t.position)? {
+            flagged.push(i);
+        }
+    }
+    Ok(flagged)
+}
+/// Terminals that dropped exactly the same packets as another one, smaller sets than
+/// min_dropped are ignored. The gaps in the packets of the remote are not drops.
+fn reused_drop_patterns(
+    terminals: &[Terminal<BigInt>],
+    reference: Option<&CompletePackets>,
+    min_dropped: usize,
+) -> Vec<usize> {
+    let mut patterns: HashMap<Vec<usize>, Vec<usize>> = HashMap::new();
+    for (i, t) in terminals.iter().enumerate() {
+        if let Some(packets) = &t.terminal_packets {
+            let dropped: Vec<usize> = packets
+                .data
+                .iter()
+                .enumerate()
+                .filter(|(j, p)| p.is_none() && reference.is_none_or(|r| r.attests(*j as u64)))
+                .map(|(j, _)| j)
+                .collect();
+            if !dropped.is_empty() && dropped.len() >= min_dropped {
+                patterns.entry(dropped).or_default().push(i);
+            }
+        }
+    }
+    let mut flagged: Vec<usize> = patterns
+        .into_values()
+        .filter(|terminals| terminals.len() > 1)
+        .flatten()
+        .collect();
+    flagged.sort();
+    flagged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use types::{Alpha, Packet, Packets, Pos3D};
+
+    fn terminal(address: &str, x: i64, y: i64, rspr: i64, dropped: &[usize]) -> Terminal<BigInt> {
+        // coordinate precision 3, rspr precision 4 as in the default config
+        let packets = (!dropped.is_empty()).then(|| Packets {
+            data: (0..20)
+                .map(|i| {
+                    (!dropped.contains(&i)).then(|| Packet {
+                        data: vec![i as u8],
+                    })
+                })
+                .collect(),
+        });
+        Terminal::new(
+            address.to_owned(),
+            BigInt::from(x * 1000),
+            BigInt::from(y * 1000),
+            Alpha::new(BigInt::from(rspr * 10000)),
+            packets,
+        )
+    }
+
+    fn remote(terminals: Vec<Terminal<BigInt>>) -> Remote<BigInt> {
+        Remote {
+            epoch: 1,
+            address: "0xr".to_owned(),
+            position: Pos3D {
+                x: BigInt::from(0),
+                y: BigInt::from(0),
+                height: BigInt::from(100_000),
+            },
+            terminals,
+            remote_packets: None,
+            bandwidth: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_anomalies() {
+        let mut cfg = PoxConfig::default();
+        cfg.anomaly.min_cluster_size = 2;
+        cfg.anomaly.min_dropped_packets = 2;
+        let dropped = [1, 5, 7];
+        let remote = remote(vec![
+            // 0x3 is just out of the radius, 0x4 reports another rspr
+            terminal("0x1", 10, 10, -80, &[]),
+            terminal("0x2", 10, 10, -80, &[]),
+            terminal("0x3", 10, 11, -80, &[]),
+            terminal("0x4", 10, 10, -60, &[]),
+            // the bound is below 60 - 20 * log10(100) = 20 dBm
+            terminal("0x5", 50, 50, 30, &[]),
+            terminal("0x6", 80, 80, -70, &dropped),
+            terminal("0x7", -80, -80, -75, &dropped),
+            terminal("0x8", -40, 80, -75, &[2, 3]),
+        ]);
+        let anomalies = detect_anomalies(&remote, &cfg).unwrap();
+        let flagged: Vec<(&str, Vec<AnomalyKind>)> = anomalies
+            .iter()
+            .map(|a| (a.terminal_address.as_str(), a.kinds.clone()))
+            .collect();
+        assert_eq!(
+            flagged,
+            vec![
+                ("0x1", vec![AnomalyKind::Colocated]),
+                ("0x2", vec![AnomalyKind::Colocated]),
+                ("0x5", vec![AnomalyKind::ImplausibleRspr]),
+                ("0x6", vec![AnomalyKind::ReusedDropPattern]),
+                ("0x7", vec![AnomalyKind::ReusedDropPattern]),
+            ]
+        );
+        assert!(anomalies
+            .iter()
+            .all(|a| !a.excluded && a.weight_percent == 100));
+
+        // within the radius, 0x3 joins the cluster
+        cfg.anomaly.colocation_radius = dec!(1.5);
+        cfg.anomaly.policy = AnomalyPolicyConfig::Exclude;
+        let anomalies = detect_anomalies(&remote, &cfg).unwrap();
+        assert_eq!(anomalies[2].terminal_address, "0x3");
+        assert!(anomalies
+            .iter()
+            .all(|a| a.excluded && a.weight_percent == 0));
+
+        cfg.anomaly.policy = AnomalyPolicyConfig::DownWeight;
+        cfg.anomaly.down_weight_percent = 101;
+        assert!(matches!(
+            detect_anomalies(&remote, &cfg),
+            Err(Error::InvalidAnomalyErr(_))
+        ));
+    }
+}
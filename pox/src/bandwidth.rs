@@ -0,0 +1,203 @@
+use config::PoxConfig;
+use halo2_proofs::pasta::Fp;
+use num_bigint::BigInt;
+use rs_merkle::{algorithms::Sha256, Hasher, MerkleProof, MerkleTree};
+use rust_decimal::prelude::{Signed, Zero};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use types::FixedPoint;
+use util::serde_bin::SerdeBinTrait;
+
+use crate::{Penalty, PoX};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoBTerminalResult<T: FixedPoint> {
+    pub terminal_address: String,
+    // observed by the terminal in its session with the remote
+    pub traffic: T,
+    // leaf index and inclusion proof of the traffic in the tree of the remote
+    index: usize,
+    proof: Vec<u8>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoBRemoteResult<T: FixedPoint> {
+    pub value: T,
+    // ground bandwidth claimed by the remote
+    pub claimed: T,
+    // total traffic of the terminals
+    pub observed: T,
+    // root of the traffic of every terminal, None without terminals; it comes with the
+    // result, nothing ties it to the snapshot of the remote
+    traffic_root: Option<[u8; 32]>,
+    pub terminal_results: Vec<PoBTerminalResult<T>>,
+}
+impl SerdeBinTrait for PoBRemoteResult<BigInt> {}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoBVerify {
+    Success,
+    Fail(String),
+}
+
+/// Commits to the address and the traffic of a terminal.
+fn traffic_leaf(address: &str, traffic: &BigInt) -> [u8; 32] {
+    let mut bytes = (address.len() as u64).to_le_bytes().to_vec();
+    bytes.extend_from_slice(address.as_bytes());
+    bytes.extend_from_slice(&traffic.to_signed_bytes_le());
+    Sha256::hash(&bytes)
+}
+/// The claimed bandwidth backed by the observed traffic, nothing if it is claimed beyond the
+/// tolerance.
+fn credited_bandwidth(claimed: &BigInt, observed: &BigInt, cfg: &PoxConfig) -> BigInt {
+    let tolerance = 100 + cfg.pob.overclaim_tolerance_percent;
+    if claimed * 100 > observed * tolerance {
+        return BigInt::zero();
+    }
+    claimed.min(observed).clone()
+}
+
+impl PoBRemoteResult<BigInt> {
+    /// Checks the inclusion proof of every terminal, and that the observed traffic and the
+    /// value are the ones of the terminals. A wrong total adds a failure at the end.
+    /// The traffic root is the one of the result itself, so this only checks that the result
+    /// is consistent: a prover that builds the tree from other traffic passes it.
+    pub fn verify(&self, cfg: &PoxConfig) -> Vec<PoBVerify> {
+        let total = self.terminal_results.len();
+        let mut results: Vec<PoBVerify> = self
+            .terminal_results
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let Some(root) = self.traffic_root else {
+                    return PoBVerify::Fail("PoB: no traffic root".to_owned());
+                };
+                // in leaf order, so that a terminal can not be counted twice
+                if r.index != i {
+                    return PoBVerify::Fail(format!(
+                        "PoB: Terminal {} is at index {} instead of {}",
+                        r.terminal_address, r.index, i
+                    ));
+                }
+                match MerkleProof::<Sha256>::try_from(r.proof.as_slice()) {
+                    Ok(proof)
+                        if proof.verify(
+                            root,
+                            &[r.index],
+                            &[traffic_leaf(&r.terminal_address, &r.traffic)],
+                            total,
+                        ) =>
+                    {
+                        PoBVerify::Success
+                    }
+                    Ok(_) => PoBVerify::Fail(format!(
+                        "PoB: Terminal {} traffic is not in the tree",
+                        r.terminal_address
+                    )),
+                    Err(e) => PoBVerify::Fail(format!(
+                        "PoB: Terminal {} proof verify failed: {}",
+                        r.terminal_address, e
+                    )),
+                }
+            })
+            .collect();
+        let observed: BigInt = self
+            .terminal_results
+            .iter()
+            .map(|r| r.traffic.clone())
+            .sum();
+        if observed != self.observed
+            || self.value != credited_bandwidth(&self.claimed, &observed, cfg)
+        {
+            results.push(PoBVerify::Fail(format!(
+                "PoB: claimed value {} and observed traffic {} do not match the terminals",
+                self.value, self.observed
+            )));
+        }
+        results
+    }
+}
+
+impl<P, ZK> PoX<P, ZK>
+where
+    P: Penalty<BaseType = BigInt>,
+    ZK: zkt::ZkTraitHalo2<F = Fp>,
+{
+    /// Checks the ground bandwidth claimed by the remote against the traffic observed by its
+    /// terminals, with the traffic of every terminal committed in a merkle tree.
+    /// PoB is evaluated per snapshot only: eval_range and the RPC serve PoD and PoF, and
+    /// nothing verifies a PoB result against the snapshot yet.
+    pub fn eval_pob(&self) -> PoBRemoteResult<BigInt> {
+        let _span = tracing::debug_span!("eval_pob").entered();
+        let claimed = self
+            .remote
+            .bandwidth
+            .as_ref()
+            .map_or(BigInt::zero(), |b| BigInt::from(b.ground.max(0)));
+        // the terminals are sorted by address
+        let traffic: Vec<(String, BigInt)> = self
+            .remote
+            .terminals
+            .iter()
+            .map(|t| {
+                let traffic = t
+                    .service
+                    .as_ref()
+                    .map_or(BigInt::zero(), |s| BigInt::from(s.net_traffic));
+                if traffic.is_negative() {
+                    warn!("PoB: Terminal {} negative traffic {}", t.address, traffic);
+                    return (t.address.clone(), BigInt::zero());
+                }
+                (t.address.clone(), traffic)
+            })
+            .collect();
+        let observed: BigInt = traffic.iter().map(|(_, t)| t.clone()).sum();
+        let leaves: Vec<[u8; 32]> = traffic
+            .iter()
+            .map(|(address, traffic)| traffic_leaf(address, traffic))
+            .collect();
+        let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+        let terminal_results = traffic
+            .into_iter()
+            .enumerate()
+            .map(|(index, (terminal_address, traffic))| PoBTerminalResult {
+                terminal_address,
+                traffic,
+                index,
+                proof: tree.proof(&[index]).to_bytes(),
+            })
+            .collect();
+        let value = credited_bandwidth(&claimed, &observed, &self.cfg);
+        if value.is_zero() && !claimed.is_zero() {
+            warn!(
+                "PoB: claimed bandwidth {} is not backed by the observed traffic {}",
+                claimed, observed
+            );
+        }
+        debug!(message = "PoB Result", ?claimed, ?observed, ?value);
+        PoBRemoteResult {
+            value,
+            claimed,
+            observed,
+            traffic_root: tree.root(),
+            terminal_results,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credited_bandwidth() {
+        let cfg = PoxConfig::default();
+        let credited = |claimed: i64, observed: i64| {
+            credited_bandwidth(&BigInt::from(claimed), &BigInt::from(observed), &cfg)
+        };
+        assert_eq!(credited(100, 150), BigInt::from(100));
+        assert_eq!(credited(100, 100), BigInt::from(100));
+        // within the 20% tolerance only the observed traffic counts
+        assert_eq!(credited(120, 100), BigInt::from(100));
+        assert_eq!(credited(121, 100), BigInt::zero());
+        assert_eq!(credited(0, 100), BigInt::zero());
+    }
+}
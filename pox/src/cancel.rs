@@ -0,0 +1,59 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use types::Error;
+
+/// Shared flag to stop an evaluation, checked between the terminals and the blocks.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::CancelledErr);
+        }
+        Ok(())
+    }
+    /// Cancels the token when the guard is dropped, with the future waiting for the
+    /// evaluation.
+    pub fn drop_guard(&self) -> CancelOnDrop {
+        CancelOnDrop(self.clone())
+    }
+}
+
+pub struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let shared = token.clone();
+        assert!(token.check().is_ok());
+        {
+            let _guard = token.drop_guard();
+            assert!(!shared.is_cancelled());
+        }
+        assert!(shared.is_cancelled());
+        assert!(matches!(shared.check(), Err(Error::CancelledErr)));
+    }
+}
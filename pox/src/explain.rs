@@ -0,0 +1,153 @@
+use config::{PenaltyTypeConfig, PoxConfig};
+use halo2_proofs::pasta::Fp;
+use num_bigint::BigInt;
+use num_rational::Ratio;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use types::{Error, FixedPointDecimal, FixedPointInteger, GetPos2D, Remote};
+
+use crate::{
+    block_statement, pod_estimate, range_snapshots, AnomalyKind, BlockResult, CancellationToken,
+    Kernel, Penalty, PenaltyKind, PoX,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoDNeighbourExplanation {
+    pub terminal_address: String,
+    // value of the kernel, numerator over denominator
+    pub coef: f64,
+}
+/// Why a terminal got its PoD weight, for the operators.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoDExplanation {
+    pub terminal_address: String,
+    // reported by more than one terminal, dropped before the evaluation
+    pub duplicate: bool,
+    // excluded before the evaluation by the anomaly policy if any
+    pub anomalies: Vec<AnomalyKind>,
+    pub rspr: Option<Decimal>,
+    // the terminal itself is one of them
+    pub neighbours: Vec<PoDNeighbourExplanation>,
+    // kernel weighted rspr of the neighbours
+    pub rspr_estimate: Option<Decimal>,
+    pub diff: Option<Decimal>,
+    pub penalty: PenaltyTypeConfig,
+    // weight of a terminal that agrees with its neighbours
+    pub max_weight: Option<Decimal>,
+    pub weight: Option<Decimal>,
+    // rspr predicted by the path loss model if enabled
+    pub expected_rspr: Option<Decimal>,
+    pub plausibility_percent: u32,
+    pub error: Option<String>,
+}
+
+impl<ZK> PoX<PenaltyKind<BigInt>, ZK>
+where
+    ZK: zkt::ZkTraitHalo2<F = Fp>,
+{
+    /// Explains the weight of every terminal, the same as eval_pod without the proofs.
+    /// The terminals excluded as anomalies and the dropped duplicates come last.
+    pub fn explain_pod(&self) -> Vec<PoDExplanation> {
+        let _span = tracing::debug_span!("explain_pod").entered();
+        let precision = self.cfg.rspr_precision_bigint;
+        let empty = |address: &String, duplicate: bool| PoDExplanation {
+            terminal_address: address.clone(),
+            duplicate,
+            anomalies: self
+                .anomalies
+                .iter()
+                .find(|a| &a.terminal_address == address)
+                .map(|a| a.kinds.clone())
+                .unwrap_or_default(),
+            rspr: None,
+            neighbours: Vec::new(),
+            rspr_estimate: None,
+            diff: None,
+            penalty: self.cfg.penalty.penalty_type.clone(),
+            max_weight: self.penalty.max_diff().fixed_to_decimal(precision).ok(),
+            weight: None,
+            expected_rspr: None,
+            plausibility_percent: 100,
+            error: None,
+        };
+        let denom = self.kernel.denom();
+        let explain = |i: usize| -> Result<PoDExplanation, Error> {
+            let t = &self.remote.terminals[i];
+            let coefs = self.neighbour_coefs(i);
+            let neighbours = coefs
+                .iter()
+                .map(|cx| {
+                    Ok(PoDNeighbourExplanation {
+                        terminal_address: self.remote.terminals[cx.neighbour].address.clone(),
+                        coef: Ratio::new(cx.coef.clone(), denom.clone()).fixed_to_f64()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let (diff, value) = pod_estimate(&coefs, &t.alpha.rspr)?;
+            let weight = self.penalty.eval(diff.to_integer());
+            let pos = t.get_pos_2d();
+            let expected_rspr = self
+                .path_loss
+                .as_ref()
+                .map(|m| m.expected_rspr_at(&self.remote.position, &pos))
+                .transpose()?;
+            Ok(PoDExplanation {
+                rspr: Some(t.alpha.rspr.fixed_to_decimal(precision)?),
+                neighbours,
+                rspr_estimate: Some(value.to_integer().fixed_to_decimal(precision)?),
+                diff: Some(diff.to_integer().fixed_to_decimal(precision)?),
+                weight: Some(weight.fixed_to_decimal(precision)?),
+                expected_rspr,
+                plausibility_percent: self.path_loss.as_ref().map_or(Ok(100), |m| {
+                    m.plausibility_percent(&self.remote.position, &pos, &t.alpha.rspr)
+                })?,
+                ..empty(&t.address, false)
+            })
+        };
+        (0..self.remote.terminals.len())
+            .map(|i| {
+                explain(i).unwrap_or_else(|e| PoDExplanation {
+                    error: Some(e.to_string()),
+                    ..empty(&self.remote.terminals[i].address, false)
+                })
+            })
+            .chain(
+                self.anomalies
+                    .iter()
+                    .filter(|a| a.excluded)
+                    .map(|a| empty(&a.terminal_address, false)),
+            )
+            .chain(self.duplicates.iter().map(|address| empty(address, true)))
+            .collect()
+    }
+}
+
+/// Explanations of every block snapshot in the range of the statement, it fails with
+/// CancelledErr once cancel is cancelled.
+pub fn explain_range<ZK>(
+    snapshots: Vec<(u64, Remote<BigInt>)>,
+    zkp: ZK,
+    cfg: &PoxConfig,
+    statement: &zkt::Statement,
+    cancel: &CancellationToken,
+) -> Result<Vec<BlockResult<Vec<PoDExplanation>>>, Error>
+where
+    ZK: zkt::ZkTraitHalo2<F = Fp> + Clone,
+{
+    range_snapshots(snapshots, statement)?
+        .into_iter()
+        .map(|(block_height, remote)| {
+            cancel.check()?;
+            let pox = PoX::new(
+                remote,
+                zkp.clone(),
+                cfg,
+                block_statement(statement, block_height),
+            )?;
+            Ok(BlockResult {
+                block_height,
+                result: pox.explain_pod(),
+            })
+        })
+        .collect()
+}
@@ -12,11 +12,31 @@ use rust_decimal::prelude::{FromPrimitive, Zero};
 use rust_decimal::Decimal;
 use tracing::{debug, warn};
 use types::{
-    Alpha, Error, FixedPoint, FixedPointInteger, GetPos2D, MerkleAble, MerkleComparison,
-    MerkleProofStruct, Pos3D, Remote,
+    Alpha, CompletePackets, Error, FixedPoint, FixedPointInteger, GetPos2D, MerkleAble,
+    MerkleComparison, MerkleProofStruct, Pos2D, Pos3D, Remote,
 };
 mod math;
 use math::*;
+mod aggregate;
+pub use aggregate::*;
+mod explain;
+pub use explain::*;
+mod anomaly;
+pub use anomaly::*;
+mod propagation;
+pub use propagation::*;
+mod orbit;
+pub use orbit::*;
+mod service;
+pub use service::*;
+mod bandwidth;
+pub use bandwidth::*;
+mod sampling;
+pub use sampling::*;
+mod cancel;
+pub use cancel::*;
+mod status;
+pub use status::*;
 mod tests;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -27,20 +47,32 @@ use zkt::ZKT;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct PoDCoef<T: FixedPoint> {
     index: usize,
+    // index of the nearby terminal
+    neighbour: usize,
     coef: T,
     x: T,
 }
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PoDTerminalResult<T: FixedPoint> {
     pub terminal_address: String,
+    // the output of the PoD proof, nothing else is proved
     pub weight: T,
     value_for_remote: T,
     proof: (Vec<u8>, Vec<u8>),
+    // share of the weight that counts in the score, 100 without a path loss model; it is
+    // not part of the proven weight, the verifier recomputes it from the snapshot
+    pub plausibility_percent: u32,
+    // anything but Ok has an empty result
+    pub status: TerminalStatus,
 }
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PoDRemoteResult<T: FixedPoint> {
     pub score: T,
     pub terminal_results: Vec<PoDTerminalResult<T>>,
+    // aggregated proof of all the terminal results, their own proofs are empty then
+    proof: Option<(Vec<u8>, Vec<u8>)>,
+    // flagged terminals, the excluded ones have no result
+    pub anomalies: Vec<TerminalAnomaly>,
 }
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PoFTerminalResult<T: FixedPoint> {
@@ -48,11 +80,40 @@ pub struct PoFTerminalResult<T: FixedPoint> {
     pub valid_packets_num: T,
     proof: MerkleProofStruct,
     pub invalid_packets_num: T,
+    // share of the packets that count, 100 without a service quality score
+    pub service_percent: u32,
+    // openings of the challenged packets, None in the full mode
+    pub sample: Option<PoFSample>,
+    // anything but Ok has an empty result
+    pub status: TerminalStatus,
+}
+/// How many of the sequence numbers up to its last packet the remote attests to, the
+/// terminals are scored over the attested ones only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketCoverage {
+    pub attested: u64,
+    // sequence numbers from 0 to the last attested one
+    pub span: u64,
+}
+impl PacketCoverage {
+    pub fn from_packets(packets: &CompletePackets) -> Self {
+        Self {
+            attested: packets.data.len() as u64,
+            span: packets.span(),
+        }
+    }
+    pub fn percent(&self) -> u32 {
+        if self.span == 0 {
+            return 0;
+        }
+        (self.attested.min(self.span) * 100 / self.span) as u32
+    }
 }
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PoFRemoteResult<T: FixedPoint> {
     pub value: T,
     pub terminal_results: Vec<PoFTerminalResult<T>>,
+    pub coverage: PacketCoverage,
 }
 impl SerdeBinTrait for PoDRemoteResult<BigInt> {}
 impl SerdeBinTrait for PoFRemoteResult<BigInt> {}
@@ -61,8 +122,26 @@ pub struct PoX<P: Penalty<BaseType = BigInt>, ZK: zkt::ZkTraitHalo2<F = Fp>> {
     zk_prover: ZK,
     pub(crate) kernel: KernelKind<BigInt>,
     pub(crate) penalty: P,
+    // params of the PoD circuit, derived from the kernel and the penalty, None in the
+    // unproven mode
+    zk_params: Option<zkt::PoDParams>,
     remote: Remote<BigInt>,
+    // neighbour search over the terminals, None if the kernel has no bounded support
+    index: Option<GridIndex>,
+    // addresses reported by more than one terminal, they are dropped
+    duplicates: Vec<String>,
+    anomalies: Vec<TerminalAnomaly>,
+    // expected rspr of the terminals, None if disabled
+    path_loss: Option<PathLoss>,
+    // quality of the sessions of the terminals, None if disabled
+    service: Option<ServiceScore>,
+    // challenged packets of the terminals, None in the full mode
+    sampling: Option<Sampling>,
     pod_max_value: BigInt,
+    // what the PoD proofs are bound to, without the terminals
+    statement: zkt::Statement,
+    // the terminals left are skipped once cancelled
+    cancel: CancellationToken,
     cfg: PoxConfig,
 }
 use zkt::ZkTraitHalo2;
@@ -74,7 +153,7 @@ pub enum PoFVerify {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PoDVerify {
     Success,
-    Fail,
+    Fail(String),
 }
 impl PoDTerminalResult<BigInt> {
     pub fn new_empty_for_err(address: String, err: Error) -> PoDTerminalResult<BigInt> {
@@ -88,46 +167,546 @@ impl PoDTerminalResult<BigInt> {
             weight: BigInt::zero(),
             value_for_remote: BigInt::zero(),
             proof: (vec![], vec![]),
+            plausibility_percent: 100,
+            status: TerminalStatus::from_err(&err),
         }
     }
 }
+/// What the verifier recomputes of a terminal from the snapshot of the remote.
+struct SnapshotTerminal {
+    plausibility: Result<u32, String>,
+    // the proof of the terminal is verified against it
+    commitment: Result<Fp, String>,
+    // weight and value of the terminal, what it is checked against in the unproven mode
+    estimate: Result<(BigInt, BigInt), String>,
+}
 impl PoDRemoteResult<BigInt> {
     pub fn new_from_results(
         results: Vec<PoDTerminalResult<BigInt>>,
+        anomalies: Vec<TerminalAnomaly>,
         pod_max_value: BigInt,
     ) -> Self {
-        let total_value = results
+        let mut result = PoDRemoteResult {
+            score: BigInt::zero(),
+            terminal_results: results,
+            proof: None,
+            anomalies,
+        };
+        let weights: Vec<BigInt> = result
+            .terminal_results
             .iter()
-            .map(|r| r.value_for_remote.clone() * r.weight.clone())
+            .map(|r| result.effective_weight(r))
+            .collect();
+        let total_value = result
+            .terminal_results
+            .iter()
+            .zip(&weights)
+            .map(|(r, w)| r.value_for_remote.clone() * w.clone())
             .sum::<BigInt>();
-        let weight: BigInt = results.iter().map(|r| r.weight.clone()).sum();
+        let weight: BigInt = weights.iter().sum();
         if weight.is_zero() {
             warn!("PoD: Total weight is zero, set value to zero");
-            return PoDRemoteResult {
-                score: BigInt::zero(),
-                terminal_results: results,
-            };
+            return result;
         }
         let value = Ratio::new(total_value.clone(), weight.clone()).to_integer();
-        let score = (value.clone() - pod_max_value.clone()).max(BigInt::zero());
-        debug!(message = "PoD Result", ?total_value, ?weight,remote_value=?value.clone(),remote_score=?score.clone());
-        PoDRemoteResult {
-            score,
-            terminal_results: results,
+        result.score = (value.clone() - pod_max_value.clone()).max(BigInt::zero());
+        debug!(message = "PoD Result", ?total_value, ?weight,remote_value=?value.clone(),remote_score=?result.score.clone());
+        result
+    }
+    /// Weight of a terminal in the score, after the anomaly policy and the physical
+    /// plausibility of its rspr. Only the weight it starts from is proved, the anomalies and
+    /// the plausibility are checked against the snapshot by verify.
+    pub fn effective_weight(&self, r: &PoDTerminalResult<BigInt>) -> BigInt {
+        let anomaly_percent = self
+            .anomalies
+            .iter()
+            .find(|a| a.terminal_address == r.terminal_address)
+            .map_or(100, |a| a.weight_percent);
+        Ratio::new(
+            r.weight.clone() * anomaly_percent * r.plausibility_percent,
+            BigInt::from(10000),
+        )
+        .to_integer()
+    }
+    /// Checks the proofs against the params of cfg, the statement of the request and the input
+    /// commitments recomputed from the snapshot of the remote, so that the proofs are made
+    /// from its positions and rspr, and the claimed weight and value of every Ok terminal
+    /// against the public output of its proof, or of the aggregated proof of the Ok
+    /// terminals, the others have no proof and no weight. In the unproven mode of cfg there is
+    /// no proof, the claimed weight and value are the ones recomputed from the snapshot. The
+    /// terminals have to be unique, sorted, not excluded and within the weight bounds of cfg,
+    /// and their plausibility the one recomputed from the snapshot. A score that is not the
+    /// one recomputed with the pod_max_value of cfg, or anomalies and terminals that are not
+    /// the ones of the snapshot, add a failure at the end.
+    pub fn verify(
+        &self,
+        cfg: &PoxConfig,
+        statement: &zkt::Statement,
+        remote: &Remote<BigInt>,
+    ) -> Vec<PoDVerify> {
+        let expected = KernelKind::<BigInt>::from_pox_cfg(cfg)
+            .and_then(|kernel| pod_zk_params(&kernel, &PenaltyKind::<BigInt>::from_pox_cfg(cfg)?))
+            .map_err(|e| e.to_string());
+        let snapshot = self.recompute_snapshot(cfg, remote);
+        let in_snapshot = |i: usize| {
+            snapshot
+                .as_ref()
+                .map_err(Clone::clone)
+                .and_then(|s| s.get(i).ok_or_else(|| "not in the snapshot".to_owned()))
+        };
+        // what the proof of the i-th terminal has to be made from
+        let commitment = |i: usize| in_snapshot(i).and_then(|t| t.commitment.clone());
+        // the terminals that are not Ok have no proof, nothing but a zero weight and value
+        let unproven = || Ok((BigInt::zero(), BigInt::zero()));
+        // weight and value proved for each terminal
+        let proved: Vec<Result<(BigInt, BigInt), String>> = match &self.proof {
+            // nothing is proved in the unproven mode, the weights are recomputed instead
+            _ if cfg.penalty.unproven => self
+                .terminal_results
+                .iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    if !r.status.is_ok() {
+                        return unproven();
+                    }
+                    if self.proof.is_some() || !r.proof.0.is_empty() || !r.proof.1.is_empty() {
+                        return Err("proof in the unproven mode".to_owned());
+                    }
+                    in_snapshot(i).and_then(|t| t.estimate.clone())
+                })
+                .collect(),
+            Some(proof) => {
+                let statement = statement.for_terminals(
+                    self.terminal_results
+                        .iter()
+                        .filter(|r| r.status.is_ok())
+                        .map(|r| r.terminal_address.clone())
+                        .collect(),
+                );
+                let commitments = self
+                    .terminal_results
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, r)| r.status.is_ok())
+                    .map(|(i, _)| commitment(i))
+                    .collect::<Result<Vec<_>, _>>();
+                let outputs = expected
+                    .clone()
+                    .and_then(|expected| {
+                        ZKT::verify_batch_pod_proof(
+                            &expected,
+                            cfg.zk.max_k,
+                            &statement,
+                            &commitments?,
+                            proof.0.clone(),
+                            proof.1.clone(),
+                        )
+                        .map_err(|e| e.to_string())
+                    })
+                    .and_then(|out| {
+                        if out.terminals.len() != statement.terminals.len() {
+                            return Err(format!(
+                                "aggregated proof has {} terminals, {} Ok results",
+                                out.terminals.len(),
+                                statement.terminals.len()
+                            ));
+                        }
+                        Ok(out.terminals)
+                    });
+                let mut outputs = outputs.map(|o| o.into_iter().map(|o| (o.weight, o.value)));
+                self.terminal_results
+                    .iter()
+                    .map(|r| match outputs.as_mut() {
+                        _ if !r.status.is_ok() => unproven(),
+                        // as many outputs as Ok results
+                        Ok(outputs) => outputs.next().ok_or_else(|| "no output".to_owned()),
+                        Err(e) => Err(e.clone()),
+                    })
+                    .collect()
+            }
+            None => self
+                .terminal_results
+                .iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    if !r.status.is_ok() {
+                        return unproven();
+                    }
+                    let statement = statement.for_terminals(vec![r.terminal_address.clone()]);
+                    let out = ZKT::verify_pod_proof(
+                        expected.as_ref()?,
+                        cfg.zk.max_k,
+                        &statement,
+                        &commitment(i)?,
+                        r.proof.0.clone(),
+                        r.proof.1.clone(),
+                    )
+                    .map_err(|e| e.to_string())?;
+                    Ok((out.weight, out.value))
+                })
+                .collect(),
+        };
+        let max_weight = PenaltyKind::<BigInt>::from_pox_cfg(cfg)
+            .map(|penalty| penalty.max_diff().clone())
+            .map_err(|e| e.to_string());
+        let mut results: Vec<PoDVerify> = self
+            .terminal_results
+            .iter()
+            .zip(proved)
+            .enumerate()
+            .map(|(i, (r, proved))| {
+                let checked = proved
+                    .and_then(|(weight, value)| {
+                        if weight != r.weight || value != r.value_for_remote {
+                            return Err(format!(
+                                "claimed weight {} and value {} do not match the proof ({}, {})",
+                                r.weight, r.value_for_remote, weight, value
+                            ));
+                        }
+                        Ok(())
+                    })
+                    .and_then(|_| self.check_terminal(i, r, max_weight.as_ref()?))
+                    .and_then(|_| match snapshot.as_ref().ok().and_then(|s| s.get(i)) {
+                        // the results of the other statuses have no weight
+                        Some(t) if r.status.is_ok() => {
+                            let expected = t.plausibility.clone()?;
+                            if expected != r.plausibility_percent {
+                                return Err(format!(
+                                    "plausibility {}% is not the recomputed {}%",
+                                    r.plausibility_percent, expected
+                                ));
+                            }
+                            Ok(())
+                        }
+                        _ => Ok(()),
+                    });
+                match checked {
+                    Ok(()) => PoDVerify::Success,
+                    Err(e) => PoDVerify::Fail(format!(
+                        "PoD: Terminal {} verify failed: {}",
+                        r.terminal_address, e
+                    )),
+                }
+            })
+            .collect();
+        if let Err(e) = snapshot {
+            results.push(PoDVerify::Fail(format!(
+                "PoD: snapshot verify failed: {}",
+                e
+            )));
+        }
+        if let Err(e) = self.check_score(cfg) {
+            results.push(PoDVerify::Fail(format!("PoD: score verify failed: {}", e)));
         }
+        results
     }
-    pub fn verify(&self) -> Vec<PoDVerify> {
-        self.terminal_results
+    /// Plausibility, input commitment and estimate of every scored terminal of the snapshot, after
+    /// checking that they are the terminals of the results and that the anomalies are the
+    /// ones detected in it.
+    fn recompute_snapshot(
+        &self,
+        cfg: &PoxConfig,
+        remote: &Remote<BigInt>,
+    ) -> Result<Vec<SnapshotTerminal>, String> {
+        let (remote, _, anomalies) = scored_terminals(remote, cfg).map_err(|e| e.to_string())?;
+        if anomalies != self.anomalies {
+            return Err(format!(
+                "claimed {} anomalies are not the {} detected in the snapshot",
+                self.anomalies.len(),
+                anomalies.len()
+            ));
+        }
+        if !remote
+            .terminals
             .iter()
-            .map(|r| {
-                if ZKT::verify_proof(r.proof.0.clone(), r.proof.1.clone()) {
-                    PoDVerify::Success
-                } else {
-                    PoDVerify::Fail
+            .map(|t| &t.address)
+            .eq(self.terminal_results.iter().map(|r| &r.terminal_address))
+        {
+            return Err(format!(
+                "{} terminal results are not the {} scored terminals of the snapshot",
+                self.terminal_results.len(),
+                remote.terminals.len()
+            ));
+        }
+        let path_loss = PathLoss::from_pox_cfg(cfg).map_err(|e| e.to_string())?;
+        let kernel = KernelKind::<BigInt>::from_pox_cfg(cfg).map_err(|e| e.to_string())?;
+        let penalty = PenaltyKind::<BigInt>::from_pox_cfg(cfg).map_err(|e| e.to_string())?;
+        let index = kernel.support_radius_sqr().map(|radius_sqr| {
+            GridIndex::new(remote.terminals.iter().map(|t| &t.position), &radius_sqr)
+        });
+        Ok(remote
+            .terminals
+            .par_iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let pos = t.get_pos_2d();
+                let coefs_x = neighbour_coefs(&remote, &kernel, index.as_ref(), i);
+                SnapshotTerminal {
+                    plausibility: path_loss
+                        .as_ref()
+                        .map_or(Ok(100), |m| {
+                            m.plausibility_percent(&remote.position, &pos, &t.alpha.rspr)
+                        })
+                        .map_err(|e| e.to_string()),
+                    commitment: zkt::pod_input_commitment(
+                        &zk_point(&pos),
+                        &t.alpha.rspr,
+                        &zk_neighbours(&remote, &coefs_x),
+                    )
+                    .map_err(|e| e.to_string()),
+                    estimate: pod_estimate(&coefs_x, &t.alpha.rspr)
+                        .map(|(diff, value)| (penalty.eval(diff.to_integer()), value.to_integer()))
+                        .map_err(|e| e.to_string()),
                 }
             })
-            .collect()
+            .collect())
+    }
+    /// The weight bounds of the i-th terminal, and that it comes after the terminal before
+    /// it, so that no terminal counts twice.
+    fn check_terminal(
+        &self,
+        i: usize,
+        r: &PoDTerminalResult<BigInt>,
+        max_weight: &BigInt,
+    ) -> Result<(), String> {
+        if r.weight.fixed_is_negative() || &r.weight > max_weight {
+            return Err(format!("weight {} is out of [0, {}]", r.weight, max_weight));
+        }
+        if r.plausibility_percent > 100 {
+            return Err(format!(
+                "plausibility {}% is over 100%",
+                r.plausibility_percent
+            ));
+        }
+        if !r.status.is_ok() && !(r.weight.is_zero() && r.value_for_remote.is_zero()) {
+            return Err(format!("status {} with a weight {}", r.status, r.weight));
+        }
+        if let Some(previous) = i.checked_sub(1).map(|j| &self.terminal_results[j]) {
+            if previous.terminal_address == r.terminal_address {
+                return Err("duplicate terminal".to_owned());
+            }
+            if previous.terminal_address > r.terminal_address {
+                return Err(format!(
+                    "not sorted, after terminal {}",
+                    previous.terminal_address
+                ));
+            }
+        }
+        if self
+            .anomalies
+            .iter()
+            .any(|a| a.excluded && a.terminal_address == r.terminal_address)
+        {
+            return Err("excluded as an anomaly".to_owned());
+        }
+        Ok(())
+    }
+    /// Recomputes the score from the terminal results and the anomalies with the
+    /// pod_max_value of cfg, not with the one of the prover.
+    fn check_score(&self, cfg: &PoxConfig) -> Result<(), String> {
+        let pod_max_value =
+            BigInt::fixed_from_decimal(cfg.pod_max_value, cfg.rspr_precision_bigint)
+                .map_err(|e| e.to_string())?;
+        for (i, a) in self.anomalies.iter().enumerate() {
+            if a.weight_percent > 100 {
+                return Err(format!(
+                    "anomaly of terminal {} keeps {}% of its weight",
+                    a.terminal_address, a.weight_percent
+                ));
+            }
+            if i > 0 && self.anomalies[i - 1].terminal_address >= a.terminal_address {
+                return Err(format!(
+                    "anomaly of terminal {} is duplicated or not sorted",
+                    a.terminal_address
+                ));
+            }
+        }
+        let recomputed = Self::new_from_results(
+            self.terminal_results.clone(),
+            self.anomalies.clone(),
+            pod_max_value.clone(),
+        );
+        if recomputed.score != self.score {
+            return Err(format!(
+                "claimed score {} is not the recomputed {} with pod_max_value {}",
+                self.score, recomputed.score, pod_max_value
+            ));
+        }
+        Ok(())
+    }
+}
+/// Terminals of the remote that are scored, unique and sorted by address, without the ones
+/// excluded as anomalies. Also the duplicated addresses and the flagged terminals, the
+/// verifier recomputes them from the same snapshot.
+pub(crate) fn scored_terminals(
+    remote: &Remote<BigInt>,
+    cfg: &PoxConfig,
+) -> Result<(Remote<BigInt>, Vec<String>, Vec<TerminalAnomaly>), Error> {
+    let mut terminals = remote.terminals.clone();
+
+    let mut counts = HashMap::new();
+    for t in &terminals {
+        let count = counts.entry(t.address.clone()).or_insert(0);
+        *count += 1;
+    }
+    let mut duplicates: Vec<String> = counts
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|(address, _)| address.clone())
+        .collect();
+    duplicates.sort();
+    terminals.retain(|t| counts[&t.address] == 1);
+    debug!(
+        message = format!(
+            "remove duplicate terminals for Remote {}",
+            address_brief(&remote.address)
+        ),
+        before = counts.len(),
+        after = terminals.len()
+    );
+    // TODO: address may have lower case or upper case problem
+    terminals.sort_by(|a, b| a.address.cmp(&b.address));
+    let mut remote = Remote {
+        address: remote.address.clone(),
+        terminals,
+        position: remote.position.clone(),
+        remote_packets: remote.remote_packets.clone(),
+        bandwidth: remote.bandwidth.clone(),
+        epoch: remote.epoch.clone(),
+    };
+    let anomalies = detect_anomalies(&remote, cfg)?;
+    remote.terminals.retain(|t| {
+        !anomalies
+            .iter()
+            .any(|a| a.excluded && a.terminal_address == t.address)
+    });
+    debug!(
+        message = "anomaly detection",
+        flagged = anomalies.len(),
+        after = remote.terminals.len()
+    );
+    Ok((remote, duplicates, anomalies))
+}
+/// Statement the PoD proofs of a remote are bound to, the verifier rebuilds it from the
+/// request it verifies. Only the parts of cfg the results depend on are fingerprinted.
+pub fn pod_statement(
+    cfg: &PoxConfig,
+    remote: &str,
+    epoch: u64,
+    block_from: u64,
+    block_to: u64,
+) -> Result<zkt::Statement, Error> {
+    let config = bincode::serialize(&(
+        cfg.coordinate_precision_bigint,
+        cfg.rspr_precision_bigint,
+        &cfg.penalty,
+        &cfg.kernel,
+        &cfg.block_aggregation,
+        &cfg.anomaly,
+        &cfg.path_loss,
+        &cfg.orbit,
+        &cfg.pod_max_value,
+    ))
+    .map_err(|e| Error::ZeroKnownledgeProofErr(e.to_string()))?;
+    Ok(zkt::Statement {
+        remote: remote.to_owned(),
+        terminals: Vec::new(),
+        epoch,
+        block_from,
+        block_to,
+        config,
+    })
+}
+/// Parameters of the PoD circuit, the same for the prover and the verifier.
+/// The circuit proves the linear penalty only, the others are scored in the unproven mode.
+fn pod_zk_params(
+    kernel: &KernelKind<BigInt>,
+    penalty: &PenaltyKind<BigInt>,
+) -> Result<zkt::PoDParams, Error> {
+    let max_diff = match penalty {
+        PenaltyKind::Linear(linear) => linear.max_diff.clone(),
+        _ => {
+            return Err(Error::ZeroKnownledgeProofErr(format!(
+                "no circuit for the penalty {:?}, it is only scored with penalty.unproven",
+                penalty
+            )))
+        }
+    };
+    let params = zkt::PoDParams {
+        kernel: kernel.zk_params()?,
+        max_diff,
+    };
+    params
+        .numer_limbs()
+        .map_err(|e| Error::ZeroKnownledgeProofErr(e.to_string()))?;
+    Ok(params)
+}
+/// Non-zero kernel coefficients of the i-th terminal with its neighbours, by neighbour index.
+fn neighbour_coefs(
+    remote: &Remote<BigInt>,
+    kernel: &KernelKind<BigInt>,
+    index: Option<&GridIndex>,
+    i: usize,
+) -> Vec<PoDCoef<BigInt>> {
+    let pos = remote.terminals[i].get_pos_2d();
+    let neighbours = match index {
+        Some(index) => index.candidates(&pos),
+        None => (0..remote.terminals.len()).collect(),
+    };
+    neighbours
+        .into_iter()
+        .filter_map(|j| {
+            let t2 = &remote.terminals[j];
+            let coef = kernel.eval_numer(&pos, &t2.get_pos_2d());
+            if coef.fixed_is_zero() {
+                None
+            } else {
+                Some(PoDCoef {
+                    index: i,
+                    neighbour: j,
+                    coef,
+                    x: t2.alpha.rspr.clone(),
+                })
+            }
+        })
+        .collect()
+}
+/// Neighbours of the PoD proof of a terminal, in the order of its coefficients.
+fn zk_neighbours(remote: &Remote<BigInt>, coefs_x: &[PoDCoef<BigInt>]) -> Vec<zkt::Neighbour> {
+    coefs_x
+        .iter()
+        .map(|cx| zkt::Neighbour {
+            pos: zk_point(&remote.terminals[cx.neighbour].get_pos_2d()),
+            rspr: cx.x.clone(),
+        })
+        .collect()
+}
+/// The distance of rspr to the kernel weighted rspr of the neighbours, and that estimate.
+fn pod_estimate(
+    coefs_x: &[PoDCoef<BigInt>],
+    rspr: &BigInt,
+) -> Result<(Ratio<BigInt>, Ratio<BigInt>), Error> {
+    let total_value: BigInt = coefs_x
+        .iter()
+        .map(|cx| cx.coef.clone() * cx.x.clone())
+        .sum();
+    let total_weight: BigInt = coefs_x.iter().map(|cx| cx.coef.clone()).sum();
+    if total_weight.is_zero() {
+        return Err(Error::BigRationalConversionErr(
+            total_value.to_string(),
+            "zero kernel weight".to_owned(),
+        ));
     }
+    let binding = total_weight.clone() * rspr.clone() - total_value.clone();
+    let diff = binding.magnitude();
+    let diff = diff.to_bigint().ok_or(Error::BigIntConversionErr(
+        diff.to_string(),
+        "to_bigint".to_owned(),
+    ))?;
+    Ok((
+        Ratio::new(diff, total_weight.clone()),
+        Ratio::new(total_value, total_weight),
+    ))
 }
 impl PoFTerminalResult<BigInt> {
     pub fn new_empty_for_err(address: String, err: Error) -> PoFTerminalResult<BigInt> {
@@ -140,14 +719,84 @@ impl PoFTerminalResult<BigInt> {
             terminal_address: address,
             valid_packets_num: BigInt::zero(),
             invalid_packets_num: BigInt::zero(),
-
             proof: MerkleProofStruct::empty(),
+            service_percent: 100,
+            sample: None,
+            status: TerminalStatus::from_err(&err),
         }
     }
+    /// Packets of the terminal scaled by its service quality.
+    pub fn service_scaled(&self, packets: &BigInt) -> BigInt {
+        Ratio::new(packets * self.service_percent, BigInt::from(100)).to_integer()
+    }
+    /// The packet counts against the size of the reference tree, and the reference root
+    /// against the one the other terminals are compared with. A terminal without packets
+    /// has nothing to check.
+    fn check_counts(&self, reference_root: Option<[u8; 32]>) -> Result<(), String> {
+        let proof = &self.proof;
+        if self.valid_packets_num.fixed_is_negative()
+            || self.invalid_packets_num.fixed_is_negative()
+        {
+            return Err(format!(
+                "negative packet counts, {} valid and {} invalid",
+                self.valid_packets_num, self.invalid_packets_num
+            ));
+        }
+        if self.service_percent > 100 {
+            return Err(format!(
+                "service quality {}% is over 100%",
+                self.service_percent
+            ));
+        }
+        if self.valid_packets_num.clone() + self.invalid_packets_num.clone()
+            != BigInt::from(proof.total_leaves_count)
+        {
+            return Err(format!(
+                "{} valid and {} invalid packets, {} in the reference tree",
+                self.valid_packets_num, self.invalid_packets_num, proof.total_leaves_count
+            ));
+        }
+        if !self.status.is_ok() && proof.total_leaves_count != 0 {
+            return Err(format!(
+                "status {} with {} packets",
+                self.status, proof.total_leaves_count
+            ));
+        }
+        if proof.total_leaves_count == 0 {
+            return Ok(());
+        }
+        if reference_root != Some(proof.reference_merkle_tree_root) {
+            return Err(format!(
+                "reference root {} is not the one of the other terminals",
+                hex::encode(proof.reference_merkle_tree_root)
+            ));
+        }
+        if proof.indices_to_prove.windows(2).any(|w| w[0] >= w[1])
+            || proof
+                .indices_to_prove
+                .last()
+                .is_some_and(|&i| i >= proof.total_leaves_count)
+        {
+            return Err("indices to prove are not sorted within the reference tree".to_owned());
+        }
+        // nothing dropped, the terminal has the packets of the reference
+        if proof.indices_to_prove.is_empty()
+            && proof.dropped_merkle_tree_root != proof.reference_merkle_tree_root
+        {
+            return Err("no dropped packets proved, but the roots differ".to_owned());
+        }
+        Ok(())
+    }
 }
 impl PoFRemoteResult<BigInt> {
-    pub fn new_from_results(results: Vec<PoFTerminalResult<BigInt>>) -> Self {
-        let total_value: BigInt = results.iter().map(|r| r.valid_packets_num.clone()).sum();
+    pub fn new_from_results(
+        results: Vec<PoFTerminalResult<BigInt>>,
+        coverage: PacketCoverage,
+    ) -> Self {
+        let total_value: BigInt = results
+            .iter()
+            .map(|r| r.service_scaled(&r.valid_packets_num))
+            .sum();
         debug!(message = "PoF Result", ?total_value);
         // if results.len() == 0 {
         //     warn!("PoF: No terminal results, set value to zero");
@@ -160,12 +809,50 @@ impl PoFRemoteResult<BigInt> {
         PoFRemoteResult {
             value: total_value,
             terminal_results: results,
+            coverage,
         }
     }
-    pub fn verify(&self) -> Vec<PoFVerify> {
-        self.terminal_results
+    /// Checks the packet counts and the merkle proofs of every terminal, all against the same
+    /// reference tree, or the challenged packets in the sampled mode of cfg. A value that is
+    /// not the sum of the terminals, or a tree that is not the size of the attested packets,
+    /// adds a failure at the end.
+    pub fn verify(&self, cfg: &PoxConfig, statement: &zkt::Statement) -> Vec<PoFVerify> {
+        let sampling = Sampling::from_pox_cfg(cfg);
+        // the first terminal with packets, every terminal is compared with the same packets
+        let reference_root = self
+            .terminal_results
+            .iter()
+            .find(|r| r.proof.total_leaves_count > 0)
+            .map(|r| r.proof.reference_merkle_tree_root);
+        let mut results: Vec<PoFVerify> = self
+            .terminal_results
             .iter()
             .map(|r| {
+                if let Err(e) = r.check_counts(reference_root) {
+                    return PoFVerify::Fail(format!(
+                        "PoF: Terminal {} inconsistent: {}",
+                        r.terminal_address, e
+                    ));
+                }
+                match &sampling {
+                    Err(e) => return PoFVerify::Fail(format!("PoF: {}", e)),
+                    Ok(Some(sampling)) if r.proof.total_leaves_count > 0 => {
+                        return match sampling.verify(r, statement) {
+                            Ok(()) => PoFVerify::Success,
+                            Err(e) => PoFVerify::Fail(format!(
+                                "PoF: Terminal {} sample verify failed: {}",
+                                r.terminal_address, e
+                            )),
+                        };
+                    }
+                    Ok(None) if r.sample.is_some() => {
+                        return PoFVerify::Fail(format!(
+                            "PoF: Terminal {} has a sample, every dropped packet is expected",
+                            r.terminal_address
+                        ));
+                    }
+                    _ => {}
+                }
                 if r.proof.indices_to_prove.len()!=r.proof.leaves_to_prove.len(){
                     return PoFVerify::Fail(format!(
                         "PoF: Terminal {} proof verify failed: indices_to_prove.len()!=leaves_to_prove.len()",
@@ -173,6 +860,10 @@ impl PoFRemoteResult<BigInt> {
                     ));
                 }
                 if r.proof.indices_to_prove.len() == 0 {
+                    // check_counts leaves a terminal that is not Ok without any packet
+                    if !r.status.is_ok() {
+                        return PoFVerify::Success;
+                    }
                     if r.valid_packets_num.is_zero() && r.invalid_packets_num.is_zero() {
                         return PoFVerify::Fail(format!(
                             "PoF: Terminal {} Empty proof",
@@ -228,66 +919,94 @@ impl PoFRemoteResult<BigInt> {
                     )),
                 }
             })
-            .collect()
+            .collect();
+        let total: BigInt = self
+            .terminal_results
+            .iter()
+            .map(|r| r.service_scaled(&r.valid_packets_num))
+            .sum();
+        if total != self.value {
+            results.push(PoFVerify::Fail(format!(
+                "PoF: value {} is not the sum {} of the terminals",
+                self.value, total
+            )));
+        }
+        let attested = self.coverage.attested;
+        if attested > self.coverage.span
+            || self.terminal_results.iter().any(|r| {
+                r.proof.total_leaves_count > 0 && r.proof.total_leaves_count as u64 != attested
+            })
+        {
+            results.push(PoFVerify::Fail(format!(
+                "PoF: trees of the terminals are not over the {} attested packets of {}",
+                attested, self.coverage.span
+            )));
+        }
+        results
     }
 }
-impl<ZK> PoX<LinearPenalty<BigInt>, ZK>
+impl<ZK> PoX<PenaltyKind<BigInt>, ZK>
 where
     ZK: zkt::ZkTraitHalo2<F = Fp>,
 {
-    pub fn new(remote: Remote<BigInt>, zkp: ZK, cfg: &PoxConfig) -> Result<Self, Error> {
+    pub fn new(
+        remote: Remote<BigInt>,
+        zkp: ZK,
+        cfg: &PoxConfig,
+        statement: zkt::Statement,
+    ) -> Result<Self, Error> {
         let _span = tracing::debug_span!("PoX::new").entered();
-        let mut terminals = remote.terminals.clone();
-
-        let mut counts = HashMap::new();
-        for t in &terminals {
-            let count = counts.entry(t.address.clone()).or_insert(0);
-            *count += 1;
-        }
-        terminals.retain(|t| counts[&t.address] == 1);
-        debug!(
-            message = format!(
-                "remove duplicate terminals for Remote {}",
-                address_brief(&remote.address)
-            ),
-            before = counts.len(),
-            after = terminals.len()
-        );
-        // TODO: address may have lower case or upper case problem
-        terminals.sort_by(|a, b| a.address.cmp(&b.address));
-        let remote = Remote {
-            address: remote.address.clone(),
-            terminals,
-            position: remote.position.clone(),
-            remote_packets: remote.remote_packets.clone(),
-            epoch: remote.epoch.clone(),
+        let (remote, duplicates, anomalies) = scored_terminals(&remote, cfg)?;
+        let kernel = match cfg.kernel.kernel_type {
+            config::KernelTypeConfig::GaussianTaylor => {
+                KernelKind::GaussianTaylor(Gaussian::<BigInt, GaussianTaylor>::from_pox_cfg(cfg)?)
+            }
+            config::KernelTypeConfig::Quadratic => {
+                KernelKind::Quadratic(Quadratic::<BigInt>::from_pox_cfg(cfg)?)
+            }
         };
+        // the weights are only worth something once proved, refuse what has no circuit
+        // unless the penalty is explicitly unproven
+        let penalty = PenaltyKind::from_pox_cfg(cfg)?;
+        let zk_params = if cfg.penalty.unproven {
+            None
+        } else {
+            Some(pod_zk_params(&kernel, &penalty)?)
+        };
+        let positions = remote.terminals.iter().map(|t| &t.position);
+        let index = kernel
+            .support_radius_sqr()
+            .map(|radius_sqr| GridIndex::new(positions, &radius_sqr));
+        let service = ServiceScore::from_pox_cfg(cfg, &remote.position.height)?;
         let pox = Self {
-            kernel: match cfg.kernel.kernel_type {
-                config::KernelTypeConfig::GaussianTaylor => KernelKind::GaussianTaylor(
-                    Gaussian::<BigInt, GaussianTaylor>::from_pox_cfg(&cfg)?,
-                ),
-                config::KernelTypeConfig::Quadratic => {
-                    KernelKind::Quadratic(Quadratic::<BigInt>::from_pox_cfg(&cfg)?)
-                }
-            },
+            kernel,
             remote,
+            index,
+            duplicates,
+            anomalies,
+            path_loss: PathLoss::from_pox_cfg(cfg)?,
+            service,
+            sampling: Sampling::from_pox_cfg(cfg)?,
             zk_prover: zkp,
-            penalty: LinearPenalty {
-                max_diff: BigInt::fixed_from_decimal(
-                    cfg.penalty.max_diff,
-                    cfg.rspr_precision_bigint,
-                )?,
-            },
+            penalty,
+            zk_params,
             pod_max_value: BigInt::fixed_from_decimal(
                 cfg.pod_max_value,
                 cfg.rspr_precision_bigint,
             )?,
+            statement,
+            cancel: CancellationToken::new(),
             cfg: cfg.clone(),
         };
         debug!(meesage="PoX",kernel=?pox.kernel,penalty=?pox.penalty,pod_max_value=?pox.pod_max_value);
         Ok(pox)
     }
+    /// Stops the evaluation between the terminals once cancel is cancelled, the results are
+    /// incomplete then.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
     pub fn eval_pod(&self) -> PoDRemoteResult<BigInt> {
         let _span = tracing::debug_span!("eval_pod").entered();
         let mut coef_hist = SyncHistogram::<u64>::from(Histogram::new(3).unwrap());
@@ -344,55 +1063,44 @@ where
                 let mut coef_hist = coef_hist.recorder();
                 let mut x_hist = x_hist.recorder();
                 let mut y_hist = y_hist.recorder();
-                (
-                    self.remote
-                        .terminals
-                        .iter()
-                        .filter_map(|t2| {
-                            let coef = self.kernel.eval_numer(&t1.get_pos_2d(), &t2.get_pos_2d());
-                            if coef.fixed_is_zero() {
-                                None
-                            } else {
-                                let _ = coef_hist
-                                    .record(coef.fixed_log_magnitude_to_u64().map_or_else(
-                                        |e| {
-                                            warn!("coef to_u64 error: {}", e);
-                                            u64::max_value() / 4
-                                        },
-                                        |f| f,
-                                    ))
-                                    .map_err(|e| {
-                                        warn!("record coef_hist error: {}", e);
-                                        e
-                                    });
-                                let _ = x_hist.record(coor_to_u64(&t2.position.x)).map_err(|e| {
-                                    warn!("record coef_hist error: {}", e);
-                                    e
-                                });
-                                let _ = y_hist.record(coor_to_u64(&t2.position.y)).map_err(|e| {
-                                    warn!("record coef_hist error: {}", e);
-                                    e
-                                });
-                                Some(PoDCoef {
-                                    index: i,
-                                    coef,
-                                    x: t2.alpha.rspr.clone(),
-                                })
-                            }
-                        })
-                        .collect(),
-                    t1.alpha.clone(),
-                    t1.address.clone(),
-                )
+                let coefs = if self.cancel.is_cancelled() {
+                    Vec::new()
+                } else {
+                    self.neighbour_coefs(i)
+                };
+                coefs.iter().for_each(|cx| {
+                    let t2 = &self.remote.terminals[cx.neighbour];
+                    let _ = coef_hist
+                        .record(cx.coef.fixed_log_magnitude_to_u64().map_or_else(
+                            |e| {
+                                warn!("coef to_u64 error: {}", e);
+                                u64::max_value() / 4
+                            },
+                            |f| f,
+                        ))
+                        .map_err(|e| {
+                            warn!("record coef_hist error: {}", e);
+                            e
+                        });
+                    let _ = x_hist.record(coor_to_u64(&t2.position.x)).map_err(|e| {
+                        warn!("record coef_hist error: {}", e);
+                        e
+                    });
+                    let _ = y_hist.record(coor_to_u64(&t2.position.y)).map_err(|e| {
+                        warn!("record coef_hist error: {}", e);
+                        e
+                    });
+                });
+                (coefs, t1.alpha.clone(), t1.address.clone(), t1.get_pos_2d())
             })
-            .collect::<Vec<(Vec<_>, Alpha<BigInt>, String)>>();
+            .collect::<Vec<(Vec<_>, Alpha<BigInt>, String, Pos2D<BigInt>)>>();
         debug!(calc_coefx_time = ?calc_coefx_start.elapsed());
         coef_hist.refresh();
         rspr_hist.refresh();
         x_hist.refresh();
         y_hist.refresh();
         // info! average & min & max & percentile
-        coefx.iter().for_each(|(coefs, _, _)| {
+        coefx.iter().for_each(|(coefs, _, _, _)| {
             let _ = nearby_len_hist.record(coefs.len() as u64).map_err(|e| {
                 warn!("record nearby_len_hist error: {}", e);
                 e
@@ -476,31 +1184,16 @@ where
         let mut diff_mag_hist = SyncHistogram::<u64>::from(Histogram::new(3).unwrap());
         let mut rspr_eval_hist = SyncHistogram::<u64>::from(Histogram::new(3).unwrap());
         let mut weight_mag_hist = SyncHistogram::<u64>::from(Histogram::new(3).unwrap());
-        let pod_result = coefx
+        let (mut pod_result, zk_terminals): (Vec<_>, Vec<_>) = coefx
             .par_iter()
             .map(
-                |(coefs_x, alpha, address)| -> Result<PoDTerminalResult<BigInt>, Error> {
-                    let coefs: Result<Vec<_>, Error> =
-                        coefs_x.iter().map(|e| e.coef.to_fp()).collect();
-                    let xs: Result<Vec<_>, Error> = coefs_x.iter().map(|e| e.x.to_fp()).collect();
-                    let coefs = coefs?;
-                    let xs = xs?;
-                    let total_value: BigInt = coefs_x
-                        .iter()
-                        .map(|cx| cx.coef.clone() * cx.x.clone())
-                        .sum();
-                    let total_weight: BigInt = coefs_x.iter().map(|cx| cx.coef.clone()).sum();
-                    // debug!(
-                    //     "address: {}, total_value: {}, total_weight: {}",
-                    //     address, total_value, total_weight
-                    // );
-                    let binding = total_weight.clone() * alpha.rspr.clone() - total_value.clone();
-                    let diff = binding.magnitude();
-                    let diff = diff.to_bigint().ok_or(Error::BigIntConversionErr(
-                        diff.to_string(),
-                        "to_bigint".to_owned(),
-                    ))?;
-                    let diff = Ratio::new(diff, total_weight.clone());
+                |(coefs_x, alpha, address, pos)| -> Result<
+                    (PoDTerminalResult<BigInt>, zkt::PoDTerminal),
+                    Error,
+                > {
+                    self.cancel.check()?;
+                    let neighbours = zk_neighbours(&self.remote, coefs_x);
+                    let (diff, value) = pod_estimate(coefs_x, &alpha.rspr)?;
                     let _ = diff_mag_hist
                         .recorder()
                         .record(diff.to_integer().fixed_magnitude_to_u64().map_or_else(
@@ -514,11 +1207,11 @@ where
                             warn!("record diff_mag_hist error: {}", e);
                             e
                         });
-                    let value = Ratio::new(total_value.clone(), total_weight.clone());
                     let _ = rspr_eval_hist
                         .recorder()
                         .record(
-                            (total_value / total_weight)
+                            value
+                                .to_integer()
                                 .fixed_magnitude_to_u64()
                                 .map_or_else(
                                     |e| {
@@ -551,25 +1244,36 @@ where
                     //     "PoD: address: {}, weight: {}, value: {}, binding: {}, diff: {}",
                     //     address, weight, value, binding, diff
                     // );
-                    // info!(coefs_len = coefs.len(), xs_len = xs.len(), "zk input len");
-                    let zkr = self
-                        .zk_prover
-                        .gen_proof(coefs, xs)
-                        .map_err(|e| Error::ZeroKnownledgeProofErr(e.to_string()))?;
-                    Ok(PoDTerminalResult {
-                        weight,
-                        value_for_remote: value.to_integer(),
-                        proof: zkr,
-                        // proof: vec![],
-                        terminal_address: address.clone(),
-                    })
+                    // info!(neighbours_len = neighbours.len(), "zk input len");
+                    Ok((
+                        PoDTerminalResult {
+                            weight,
+                            value_for_remote: value.to_integer(),
+                            // filled by prove_pod
+                            proof: (vec![], vec![]),
+                            terminal_address: address.clone(),
+                            plausibility_percent: self.path_loss.as_ref().map_or(Ok(100), |m| {
+                                m.plausibility_percent(&self.remote.position, pos, &alpha.rspr)
+                            })?,
+                            status: TerminalStatus::Ok,
+                        },
+                        zkt::PoDTerminal {
+                            pos: zk_point(pos),
+                            rspr: alpha.rspr.clone(),
+                            neighbours,
+                        },
+                    ))
                 },
             )
-            .map(|r| match r {
-                Ok(r) => r,
-                Err(e) => PoDTerminalResult::new_empty_for_err("".to_string(), e),
+            .zip(coefx.par_iter())
+            .map(|(r, (_, _, address, _))| match r {
+                Ok((r, t)) => (r, Some(t)),
+                Err(e) => (PoDTerminalResult::new_empty_for_err(address.clone(), e), None),
             })
-            .collect::<Vec<_>>();
+            .unzip();
+        let prove_start = Instant::now();
+        let proof = self.prove_pod(&mut pod_result, &zk_terminals);
+        debug!(prove_time = ?prove_start.elapsed(), aggregated = proof.is_some());
 
         assert!(pod_result.len() == self.remote.terminals.len());
         rspr_eval_hist.refresh();
@@ -605,20 +1309,106 @@ where
             p50 = weight_mag_hist.value_at_quantile(0.5) as f64,
             p75 = weight_mag_hist.value_at_quantile(0.75) as f64,
         );
-        PoDRemoteResult::new_from_results(pod_result, self.pod_max_value.clone())
+        let mut result = PoDRemoteResult::new_from_results(
+            pod_result,
+            self.anomalies.clone(),
+            self.pod_max_value.clone(),
+        );
+        result.proof = proof;
+        result
+    }
+    fn neighbour_coefs(&self, i: usize) -> Vec<PoDCoef<BigInt>> {
+        neighbour_coefs(&self.remote, &self.kernel, self.index.as_ref(), i)
+    }
+    /// Fills the proofs of the terminal results, or returns a single proof for all of them
+    /// in aggregate mode. It falls back to one proof per terminal when they do not fit.
+    /// The terminals left without a proof by a cancellation are Cancelled.
+    fn prove_pod(
+        &self,
+        results: &mut [PoDTerminalResult<BigInt>],
+        terminals: &[Option<zkt::PoDTerminal>],
+    ) -> Option<(Vec<u8>, Vec<u8>)> {
+        // the unproven mode leaves the proofs empty
+        let zk_params = self.zk_params.as_ref()?;
+        if self.cfg.zk.aggregate && !self.cancel.is_cancelled() {
+            // the terminals without a result have no weight, the proof covers the others
+            let (addresses, terminals): (Vec<_>, Vec<_>) = results
+                .iter()
+                .zip(terminals)
+                .filter_map(|(r, t)| Some((r.terminal_address.clone(), t.clone()?)))
+                .unzip();
+            if terminals.is_empty() {
+                return None;
+            }
+            let statement = self.statement.for_terminals(addresses);
+            match self
+                .zk_prover
+                .gen_batch_pod_proof(zk_params, &statement, &terminals)
+            {
+                Ok(proof) => return Some(proof),
+                Err(e) => warn!("PoD: aggregated proof failed, prove each terminal: {}", e),
+            }
+        }
+        results
+            .par_iter_mut()
+            .zip(terminals.par_iter())
+            .for_each(|(r, t)| {
+                if let Some(t) = t {
+                    if let Err(e) = self.cancel.check() {
+                        *r = PoDTerminalResult::new_empty_for_err(r.terminal_address.clone(), e);
+                        return;
+                    }
+                    let statement = self
+                        .statement
+                        .for_terminals(vec![r.terminal_address.clone()]);
+                    match self.zk_prover.gen_pod_proof(
+                        zk_params,
+                        &statement,
+                        &t.pos,
+                        &t.rspr,
+                        &t.neighbours,
+                    ) {
+                        Ok(proof) => r.proof = proof,
+                        Err(e) => {
+                            *r = PoDTerminalResult::new_empty_for_err(
+                                r.terminal_address.clone(),
+                                Error::ZeroKnownledgeProofErr(e.to_string()),
+                            )
+                        }
+                    }
+                }
+            });
+        None
     }
     pub fn eval_pof(&self) -> PoFRemoteResult<BigInt> {
         let _span = tracing::debug_span!("eval_pof").entered();
+        let coverage = self
+            .remote
+            .remote_packets
+            .as_ref()
+            .map_or(PacketCoverage::default(), PacketCoverage::from_packets);
         let result = if let Some(remote_packets) = self.remote.remote_packets.as_ref() {
+            if !remote_packets.is_consistent() {
+                warn!(
+                    "PoF: sequence ranges do not match the {} packets of the remote",
+                    remote_packets.data.len()
+                );
+                return PoFRemoteResult::new_from_results(Vec::new(), coverage);
+            }
+            if coverage.attested < coverage.span {
+                debug!(
+                    message = "PoF: remote packets with gaps",
+                    attested = coverage.attested,
+                    span = coverage.span,
+                    ranges = remote_packets.ranges.len()
+                );
+            }
             let ref_merkle = remote_packets.merkle_tree();
             let ref_merkle = match ref_merkle {
                 Ok(m) => m,
                 Err(e) => {
                     warn!("PoF: Reference Merkle tree error: {}", e.to_string());
-                    return PoFRemoteResult {
-                        value: BigInt::zero(),
-                        terminal_results: Vec::new(),
-                    };
+                    return PoFRemoteResult::new_from_results(Vec::new(), coverage);
                 }
             };
             debug!(
@@ -636,8 +1426,28 @@ where
                 .terminals
                 .par_iter()
                 .map(|t| {
+                    self.cancel.check()?;
+                    let service_percent = self
+                        .service
+                        .as_ref()
+                        .map_or(100, |s| s.percent(t.service.as_ref()));
                     if let Some(terminal_packets) = t.terminal_packets.as_ref() {
-                        let dropped_merkle = terminal_packets.merkle_tree()?;
+                        // over the sequence numbers the remote attests to only
+                        let dropped_merkle = terminal_packets.merkle_tree_over(remote_packets)?;
+                        if let Some(sampling) = &self.sampling {
+                            let (proof, sample) =
+                                sampling.prove(&ref_merkle, &dropped_merkle, &self.statement)?;
+                            let valid = sampling.credited(proof.total_leaves_count, &sample);
+                            return Ok(PoFTerminalResult {
+                                valid_packets_num: BigInt::from(valid),
+                                invalid_packets_num: BigInt::from(proof.total_leaves_count - valid),
+                                proof,
+                                terminal_address: t.address.clone(),
+                                service_percent,
+                                sample: Some(sample),
+                                status: TerminalStatus::Ok,
+                            });
+                        }
                         let proof = ref_merkle
                             .comparison_proof_with_dropping_difference(&dropped_merkle)?;
                         let _ = packet_len_hist
@@ -665,14 +1475,16 @@ where
                                 e
                             });
 
-                        assert!(terminal_packets.data.len() >= proof.indices_to_prove.len());
                         Ok(PoFTerminalResult {
                             valid_packets_num: BigInt::from(
-                                terminal_packets.data.len() - proof.indices_to_prove.len(),
+                                proof.total_leaves_count - proof.indices_to_prove.len(),
                             ),
                             invalid_packets_num: BigInt::from(proof.indices_to_prove.len()),
                             proof: proof,
                             terminal_address: t.address.clone(),
+                            service_percent,
+                            sample: None,
+                            status: TerminalStatus::Ok,
                         })
                     } else {
                         Ok(PoFTerminalResult {
@@ -680,12 +1492,16 @@ where
                             invalid_packets_num: BigInt::zero(),
                             proof: MerkleProofStruct::empty(),
                             terminal_address: t.address.clone(),
+                            service_percent,
+                            sample: None,
+                            status: TerminalStatus::NoPackets,
                         })
                     }
                 })
-                .map(|r| match r {
+                .zip(self.remote.terminals.par_iter())
+                .map(|(r, t)| match r {
                     Ok(r) => r,
-                    Err(e) => PoFTerminalResult::new_empty_for_err("".to_string(), e),
+                    Err(e) => PoFTerminalResult::new_empty_for_err(t.address.clone(), e),
                 })
                 .collect::<Vec<_>>();
             debug!(
@@ -723,6 +1539,6 @@ where
         } else {
             Vec::new()
         };
-        PoFRemoteResult::new_from_results(result)
+        PoFRemoteResult::new_from_results(result, coverage)
     }
 }
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+use rust_decimal::prelude::{One, Signed, Zero};
+use types::Pos2D;
+
+/// Uniform grid over the terminal positions. A cell is wider than the support radius of the
+/// kernel, so the neighbours of a point are all in the 3x3 cells around its own.
+#[derive(Clone, Debug)]
+pub(crate) struct GridIndex {
+    side: BigInt,
+    cells: HashMap<(BigInt, BigInt), Vec<usize>>,
+}
+
+impl GridIndex {
+    pub(crate) fn new<'a>(
+        positions: impl Iterator<Item = &'a Pos2D<BigInt>>,
+        radius_sqr: &BigInt,
+    ) -> Self {
+        // floor(sqrt(r^2)) + 1 > r
+        let side = radius_sqr.max(&BigInt::zero()).sqrt() + BigInt::one();
+        let mut index = Self {
+            side,
+            cells: HashMap::new(),
+        };
+        for (i, pos) in positions.enumerate() {
+            let cell = index.cell(pos);
+            index.cells.entry(cell).or_default().push(i);
+        }
+        index
+    }
+
+    fn cell(&self, pos: &Pos2D<BigInt>) -> (BigInt, BigInt) {
+        let floor_div = |c: &BigInt| {
+            let q = c / &self.side;
+            if (c % &self.side).is_negative() {
+                q - 1
+            } else {
+                q
+            }
+        };
+        (floor_div(&pos.x), floor_div(&pos.y))
+    }
+
+    /// Indices of the points that may be within the radius of `pos`, in ascending order so
+    /// that the result is the same as scanning all the points.
+    pub(crate) fn candidates(&self, pos: &Pos2D<BigInt>) -> Vec<usize> {
+        let (cx, cy) = self.cell(pos);
+        let mut candidates = Vec::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(indices) = self.cells.get(&(&cx + dx, &cy + dy)) {
+                    candidates.extend_from_slice(indices);
+                }
+            }
+        }
+        candidates.sort_unstable();
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PosTrait;
+
+    #[test]
+    fn test_grid_candidates() {
+        // a deterministic spread of points, with negative coordinates and duplicates
+        let positions: Vec<Pos2D<BigInt>> = (0..400i64)
+            .map(|i| Pos2D {
+                x: BigInt::from((i * 7919) % 1000 - 500),
+                y: BigInt::from((i * 104729) % 1000 - 500),
+            })
+            .chain(std::iter::once(Pos2D {
+                x: BigInt::from(-500),
+                y: BigInt::from(-500),
+            }))
+            .collect();
+        for radius_sqr in [0i64, 1, 99, 100, 2500, 40000] {
+            let radius_sqr = BigInt::from(radius_sqr);
+            let index = GridIndex::new(positions.iter(), &radius_sqr);
+            for pos in &positions {
+                let expected: Vec<usize> = positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| pos.dist_sqr(p) <= radius_sqr)
+                    .map(|(j, _)| j)
+                    .collect();
+                let found: Vec<usize> = index
+                    .candidates(pos)
+                    .into_iter()
+                    .filter(|&j| pos.dist_sqr(&positions[j]) <= radius_sqr)
+                    .collect();
+                assert_eq!(found, expected, "radius_sqr {}", radius_sqr);
+            }
+        }
+    }
+}
@@ -51,6 +51,10 @@ impl Kernel for Quadratic<Decimal> {
         Decimal::fixed_one()
     }
 
+    fn support_radius_sqr(&self) -> Option<Self::BaseType> {
+        Some(self.max_dis_sqr.clone())
+    }
+
     fn from_pox_cfg(config: &config::PoxConfig) -> Result<Self, Error> {
         Ok(Self {
             max_dis_sqr: config.kernel.quadratic.max_dis_sqr.clone(),
@@ -83,6 +87,10 @@ impl Kernel for Quadratic<BigInt> {
             self.max_dis_sqr.clone() - dis
         }
     }
+
+    fn support_radius_sqr(&self) -> Option<Self::BaseType> {
+        Some(self.max_dis_sqr.clone())
+    }
 }
 impl Kernel for KernelKind<BigInt> {
     type BaseType = BigInt;
@@ -113,6 +121,13 @@ impl Kernel for KernelKind<BigInt> {
             Self::Quadratic(kernel) => kernel.eval_numer(x1, x2),
         }
     }
+
+    fn support_radius_sqr(&self) -> Option<Self::BaseType> {
+        match self {
+            Self::GaussianTaylor(kernel) => kernel.support_radius_sqr(),
+            Self::Quadratic(kernel) => kernel.support_radius_sqr(),
+        }
+    }
 }
 
 impl Kernel for Gaussian<Decimal, GaussianVanilla> {
@@ -128,6 +143,10 @@ impl Kernel for Gaussian<Decimal, GaussianVanilla> {
         Decimal::PI * self.sigma_sqr
     }
 
+    fn support_radius_sqr(&self) -> Option<Self::BaseType> {
+        None
+    }
+
     fn from_pox_cfg(config: &config::PoxConfig) -> Result<Self, Error> {
         Ok(Self {
             sigma_sqr: config.kernel.gaussian.sigma.clone() * config.kernel.gaussian.sigma.clone(),
@@ -173,12 +192,20 @@ fn taylor_exp_numer(x_sqr: BigInt, sigma_sqr: BigInt, max_order: usize) -> BigIn
     for i in 1..=max_order {
         numer[i] = -numer[i - 1].clone() * x_sqr.clone();
     }
+    for (n, b) in numer.iter_mut().zip(taylor_exp_coefs(sigma_sqr, max_order)) {
+        *n *= b;
+    }
+    numer.iter().sum()
+}
+// coefs[i] = b of x^(2i), numer = \Sum{coefs[i] * (-x^2)^i}
+fn taylor_exp_coefs(sigma_sqr: BigInt, max_order: usize) -> Vec<BigInt> {
+    let mut coefs: Vec<BigInt> = vec![BigInt::one(); max_order + 1];
     let mut b = BigInt::one();
     for i in (0..max_order).rev() {
         b *= (i + 1) * 2 * sigma_sqr.clone();
-        numer[i] *= b.clone();
+        coefs[i] = b.clone();
     }
-    numer.iter().sum()
+    coefs
 }
 fn taylor_exp_denom(sigma_sqr: BigInt, max_order: usize) -> BigInt {
     factorial(max_order) * BigInt::from(2).pow(max_order as u32) * sigma_sqr.pow(max_order as u32)
@@ -207,6 +234,10 @@ impl Kernel for Gaussian<BigInt, GaussianTaylor> {
         taylor_exp_denom(self.sigma_sqr.clone(), self.implement_params.max_order)
     }
 
+    fn support_radius_sqr(&self) -> Option<Self::BaseType> {
+        Some(self.max_dis_sqr())
+    }
+
     fn from_pox_cfg(config: &config::PoxConfig) -> Result<Self, Error> {
         let sigma = BigInt::fixed_from_decimal(
             config.kernel.gaussian.sigma.clone(),
@@ -224,6 +255,44 @@ impl Kernel for Gaussian<BigInt, GaussianTaylor> {
     }
 }
 
+impl Quadratic<BigInt> {
+    // max_dis_sqr - x^2
+    pub fn zk_params(&self) -> zkt::KernelParams {
+        zkt::KernelParams {
+            coefs: vec![self.max_dis_sqr.clone(), BigInt::one()],
+            max_dis_sqr: self.max_dis_sqr.clone(),
+        }
+    }
+}
+impl Gaussian<BigInt, GaussianTaylor> {
+    // x^2 / sigma^2 > sigma_range^2 <=> x^2 > floor(sigma_range^2 * sigma^2)
+    fn max_dis_sqr(&self) -> BigInt {
+        let sigma_range = self.implement_params.sigma_range.clone();
+        (sigma_range.clone() * sigma_range * Ratio::<BigInt>::from_integer(self.sigma_sqr.clone()))
+            .floor()
+            .to_integer()
+    }
+    pub fn zk_params(&self) -> zkt::KernelParams {
+        zkt::KernelParams {
+            coefs: taylor_exp_coefs(self.sigma_sqr.clone(), self.implement_params.max_order),
+            max_dis_sqr: self.max_dis_sqr(),
+        }
+    }
+}
+impl KernelKind<BigInt> {
+    /// Parameters of the kernel circuit, the numerator has to fit in the field.
+    pub fn zk_params(&self) -> Result<zkt::KernelParams, Error> {
+        let params = match self {
+            Self::GaussianTaylor(kernel) => kernel.zk_params(),
+            Self::Quadratic(kernel) => kernel.zk_params(),
+        };
+        params
+            .numer_limbs()
+            .map_err(|e| Error::ZeroKnownledgeProofErr(e.to_string()))?;
+        Ok(params)
+    }
+}
+
 fn factorial(i: usize) -> BigInt {
     (1..=i)
         .map(|x| BigInt::from(x))
@@ -367,4 +436,123 @@ mod tests {
         );
         assert_eq!(kernel.denom(), BigInt::from_str("4026531840").unwrap());
     }
+    #[test]
+    fn test_support_radius_sqr() {
+        let kernel = Gaussian {
+            sigma_sqr: BigInt::from(16),
+            implement_params: GaussianTaylor {
+                max_order: 5,
+                sigma_range: Ratio::<BigInt>::from_str("3").unwrap(),
+            },
+        };
+        assert_eq!(kernel.support_radius_sqr(), Some(BigInt::from(144)));
+        let pos1 = Pos2D {
+            x: BigInt::from(0),
+            y: BigInt::from(0),
+        };
+        let pos2 = Pos2D {
+            x: BigInt::from(12),
+            y: BigInt::from(1),
+        };
+        assert!(kernel.eval_numer(&pos1, &pos2).is_zero());
+        let kernel = KernelKind::Quadratic(Quadratic {
+            max_dis_sqr: BigInt::from(100),
+        });
+        assert_eq!(kernel.support_radius_sqr(), Some(BigInt::from(100)));
+        let kernel = Gaussian {
+            sigma_sqr: Decimal::from_str("0.01").unwrap(),
+            implement_params: GaussianVanilla {},
+        };
+        assert_eq!(kernel.support_radius_sqr(), None);
+    }
+    #[test]
+    fn test_zk_params() {
+        let kernel = Gaussian {
+            sigma_sqr: BigInt::from(16),
+            implement_params: GaussianTaylor {
+                max_order: 5,
+                sigma_range: Ratio::<BigInt>::from_str("3").unwrap(),
+            },
+        };
+        let params = kernel.zk_params();
+        assert_eq!(params.max_dis_sqr, BigInt::from(144));
+        let pos1 = Pos2D {
+            x: BigInt::from(1),
+            y: BigInt::from(2),
+        };
+        for (x, y) in [(2, 2), (5, 9), (10, 10), (13, 2), (13, 3)] {
+            let pos2 = Pos2D {
+                x: BigInt::from(x),
+                y: BigInt::from(y),
+            };
+            assert_eq!(
+                params.eval_numer(&pos1.dist_sqr(&pos2)),
+                kernel.eval_numer(&pos1, &pos2)
+            );
+        }
+        let kernel = Quadratic {
+            max_dis_sqr: BigInt::from(100000),
+        };
+        let pos2 = Pos2D {
+            x: BigInt::from(300),
+            y: BigInt::from(400),
+        };
+        let params = kernel.zk_params();
+        assert_eq!(
+            params.eval_numer(&pos1.dist_sqr(&pos2)),
+            kernel.eval_numer(&pos1, &pos2)
+        );
+    }
+
+    #[test]
+    fn test_default_zk_params_prove() {
+        use zkt::ZkTraitHalo2;
+        // the default kernel fits in the field, its proofs verify
+        let cfg = config::PoxConfig::default();
+        let kernel = KernelKind::<BigInt>::from_pox_cfg(&cfg).unwrap();
+        let penalty = crate::PenaltyKind::<BigInt>::from_pox_cfg(&cfg).unwrap();
+        let params = crate::pod_zk_params(&kernel, &penalty).unwrap();
+        let statement = zkt::Statement {
+            remote: "remote".to_owned(),
+            terminals: vec!["terminal".to_owned()],
+            epoch: 1,
+            block_from: 10,
+            block_to: 20,
+            config: vec![0],
+        };
+        let point = |x: i64, y: i64| zkt::Point {
+            x: BigInt::from(x),
+            y: BigInt::from(y),
+        };
+        // 1 m is 1000 with coordinate_precision_bigint 3
+        let neighbours: Vec<zkt::Neighbour> = [
+            ((0, 0), -700000),
+            ((300000, -400000), -800000),
+            ((-600000, 0), -400000),
+            ((900000, 0), -750000),
+        ]
+        .iter()
+        .map(|((x, y), rspr)| zkt::Neighbour {
+            pos: point(*x, *y),
+            rspr: BigInt::from(*rspr),
+        })
+        .collect();
+        let commitment =
+            zkt::pod_input_commitment(&point(0, 0), &BigInt::from(-650000), &neighbours).unwrap();
+        let zkt = zkt::ZKT::new(&cfg).unwrap();
+        let (out, proof) = zkt
+            .gen_pod_proof(
+                &params,
+                &statement,
+                &point(0, 0),
+                &BigInt::from(-650000),
+                &neighbours,
+            )
+            .unwrap();
+        let verified =
+            zkt::ZKT::verify_pod_proof(&params, cfg.zk.max_k, &statement, &commitment, out, proof)
+                .unwrap();
+        assert_eq!(verified.params, params);
+        assert!(verified.weight > BigInt::zero());
+    }
 }
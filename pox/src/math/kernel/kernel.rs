@@ -1,4 +1,5 @@
 use config::PoxConfig;
+use num_bigint::BigInt;
 use types::{Error, FixedPoint, Pos2D};
 
 pub trait PosTrait {
@@ -18,6 +19,12 @@ impl<T: FixedPoint> PosTrait for Pos2D<T> {
             + (self.y.clone() - target.y.clone()).fixed_sqr()
     }
 }
+pub fn zk_point(pos: &Pos2D<BigInt>) -> zkt::Point {
+    zkt::Point {
+        x: pos.x.clone(),
+        y: pos.y.clone(),
+    }
+}
 use std::fmt::Debug;
 pub trait Kernel: Sized + Debug + std::marker::Sync {
     type BaseType: FixedPoint;
@@ -25,6 +32,8 @@ pub trait Kernel: Sized + Debug + std::marker::Sync {
     fn from_pox_cfg(config: &PoxConfig) -> Result<Self, Error>;
     fn denom(&self) -> Self::BaseType;
     fn eval_numer(&self, x1: &Self::PosType, x2: &Self::PosType) -> Self::BaseType;
+    // the numerator is zero beyond it, None if the kernel has no bounded support
+    fn support_radius_sqr(&self) -> Option<Self::BaseType>;
 }
 #[cfg(test)]
 mod tests {
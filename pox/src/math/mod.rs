@@ -4,3 +4,5 @@ mod kernel;
 pub(crate) use kernel::*;
 mod penalty;
 pub(crate) use penalty::*;
+mod grid;
+pub(crate) use grid::*;
@@ -0,0 +1,77 @@
+use num_bigint::BigInt;
+use num_rational::Ratio;
+use rust_decimal::prelude::{One, Zero};
+use tracing::warn;
+use types::FixedPoint;
+
+use super::penalty;
+
+// y = max * exp(-x / decay), by its taylor series up to max_order as the gaussian kernel:
+// exp(-x / l) ~= \Sum{(-x)^i * n! / i! * l^(n - i)} / (n! * l^n)
+// the series diverges for large x, the weight is zero beyond range * decay
+#[derive(Clone, Debug)]
+pub struct ExponentialPenalty<T: FixedPoint> {
+    pub max_diff: T,
+    pub decay: T,
+    pub max_order: usize,
+    pub range: Ratio<BigInt>,
+}
+// coefs[i] = n! / i! * l^(n - i), coefs[0] is the denominator
+fn taylor_exp_coefs(decay: &BigInt, max_order: usize) -> Vec<BigInt> {
+    let mut coefs = vec![BigInt::one(); max_order + 1];
+    for i in (0..max_order).rev() {
+        coefs[i] = coefs[i + 1].clone() * (i + 1) * decay;
+    }
+    coefs
+}
+impl penalty::Penalty for ExponentialPenalty<BigInt> {
+    type BaseType = BigInt;
+    fn eval(&self, diff: BigInt) -> BigInt {
+        if diff < BigInt::zero() {
+            warn!(message = "invalid diff:", ?diff, ?self.max_diff);
+            return BigInt::zero();
+        }
+        if Ratio::new(diff.clone(), self.decay.clone()) > self.range {
+            return BigInt::zero();
+        }
+        let coefs = taylor_exp_coefs(&self.decay, self.max_order);
+        let mut power = BigInt::one();
+        let mut numer = BigInt::zero();
+        for coef in &coefs {
+            numer += coef * &power;
+            power *= -&diff;
+        }
+        let weight = self.max_diff.clone() * numer / &coefs[0];
+        weight.max(BigInt::zero()).min(self.max_diff.clone())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::Penalty;
+
+    use super::*;
+
+    #[test]
+    fn test_exponential_penalty_eval() {
+        let penalty = ExponentialPenalty {
+            max_diff: BigInt::from(1_000_000),
+            decay: BigInt::from(10),
+            max_order: 20,
+            range: Ratio::from_integer(BigInt::from(3)),
+        };
+        assert_eq!(penalty.eval(BigInt::from(0)), BigInt::from(1_000_000));
+        // exp(-1), exp(-3)
+        assert_eq!(penalty.eval(BigInt::from(10)), BigInt::from(367879));
+        assert_eq!(penalty.eval(BigInt::from(30)), BigInt::from(49787));
+        assert_eq!(penalty.eval(BigInt::from(31)), BigInt::from(0));
+        assert_eq!(penalty.eval(BigInt::from(-1)), BigInt::from(0));
+
+        // 1 - x / l, clamped to zero
+        let penalty = ExponentialPenalty {
+            max_order: 1,
+            ..penalty
+        };
+        assert_eq!(penalty.eval(BigInt::from(5)), BigInt::from(500_000));
+        assert_eq!(penalty.eval(BigInt::from(15)), BigInt::from(0));
+    }
+}
@@ -0,0 +1,75 @@
+use tracing::warn;
+use types::FixedPoint;
+
+use super::penalty;
+
+// y = max - L(x), L(x) = x^2 / (2 * delta) for x <= delta, x - delta / 2 above,
+// quadratic for small diffs and linear for the large ones
+#[derive(Clone, Debug)]
+pub struct HuberPenalty<T: FixedPoint> {
+    pub max_diff: T,
+    pub delta: T,
+}
+impl<T: FixedPoint> penalty::Penalty for HuberPenalty<T> {
+    type BaseType = T;
+    fn eval(&self, diff: T) -> T {
+        if diff < T::fixed_zero() {
+            warn!(message = "invalid diff:", ?diff, ?self.max_diff);
+            return T::fixed_zero();
+        }
+        let two = T::fixed_one() + T::fixed_one();
+        let loss = if diff <= self.delta {
+            diff.fixed_sqr() / (two * self.delta.clone())
+        } else {
+            diff - self.delta.clone() / two
+        };
+        if loss > self.max_diff {
+            T::fixed_zero()
+        } else {
+            self.max_diff.clone() - loss
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::Penalty;
+
+    use super::*;
+    use num_bigint::BigInt;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_huber_penalty_eval() {
+        let penalty = HuberPenalty {
+            max_diff: Decimal::from_str("10").unwrap(),
+            delta: Decimal::from_str("4").unwrap(),
+        };
+        // 2^2 / 8
+        assert_eq!(
+            penalty.eval(Decimal::from_str("2").unwrap()),
+            Decimal::from_str("9.5").unwrap()
+        );
+        // continuous at delta: 16 / 8 = 4 - 2
+        assert_eq!(
+            penalty.eval(Decimal::from_str("4").unwrap()),
+            Decimal::from_str("8").unwrap()
+        );
+        assert_eq!(
+            penalty.eval(Decimal::from_str("7").unwrap()),
+            Decimal::from_str("5").unwrap()
+        );
+        assert_eq!(
+            penalty.eval(Decimal::from_str("13").unwrap()),
+            Decimal::from_str("0").unwrap()
+        );
+        let penalty = HuberPenalty {
+            max_diff: BigInt::from(100000),
+            delta: BigInt::from(40000),
+        };
+        assert_eq!(penalty.eval(BigInt::from(20000)), BigInt::from(95000));
+        assert_eq!(penalty.eval(BigInt::from(70000)), BigInt::from(50000));
+        assert_eq!(penalty.eval(BigInt::from(130000)), BigInt::from(0));
+        assert_eq!(penalty.eval(BigInt::from(-1)), BigInt::from(0));
+    }
+}
@@ -2,3 +2,11 @@ mod penalty;
 pub use penalty::*;
 mod linear;
 pub use linear::*;
+mod quadratic;
+pub use quadratic::*;
+mod exponential;
+pub use exponential::*;
+mod huber;
+pub use huber::*;
+mod step;
+pub use step::*;
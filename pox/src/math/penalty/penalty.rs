@@ -1,6 +1,135 @@
-use types::FixedPoint;
+use config::{PenaltyTypeConfig, PoxConfig};
+use num_bigint::BigInt;
+use num_rational::Ratio;
+use types::{Error, FixedPoint, FixedPointDecimal, FixedPointInteger};
+
+use super::{ExponentialPenalty, HuberPenalty, LinearPenalty, QuadraticPenalty, StepPenalty};
 
 pub trait Penalty {
     type BaseType: FixedPoint;
     fn eval(&self, dist: Self::BaseType) -> Self::BaseType;
 }
+
+#[derive(Clone, Debug)]
+pub enum PenaltyKind<T: FixedPoint> {
+    Linear(LinearPenalty<T>),
+    Quadratic(QuadraticPenalty<T>),
+    ExponentialDecay(ExponentialPenalty<T>),
+    Huber(HuberPenalty<T>),
+    Step(StepPenalty<T>),
+}
+
+impl PenaltyKind<BigInt> {
+    pub fn from_pox_cfg(config: &PoxConfig) -> Result<Self, Error> {
+        let cfg = &config.penalty;
+        let fixed = |v| BigInt::fixed_from_decimal(v, config.rspr_precision_bigint);
+        let positive = |name: &str, v: BigInt| {
+            if v.fixed_is_zero() || v.fixed_is_negative() {
+                Err(Error::InvalidPenaltyErr(format!(
+                    "{} should be positive: {}",
+                    name, v
+                )))
+            } else {
+                Ok(v)
+            }
+        };
+        let max_diff = fixed(cfg.max_diff)?;
+        if max_diff.fixed_is_negative() {
+            return Err(Error::InvalidPenaltyErr(format!(
+                "max_diff should not be negative: {}",
+                max_diff
+            )));
+        }
+        Ok(match cfg.penalty_type {
+            PenaltyTypeConfig::Linear => Self::Linear(LinearPenalty { max_diff }),
+            PenaltyTypeConfig::Quadratic => Self::Quadratic(QuadraticPenalty { max_diff }),
+            PenaltyTypeConfig::ExponentialDecay => Self::ExponentialDecay(ExponentialPenalty {
+                max_diff,
+                decay: positive("decay", fixed(cfg.exponential.decay)?)?,
+                max_order: cfg.exponential.max_order,
+                range: Ratio::<BigInt>::fixed_from_decimal(&cfg.exponential.range)?,
+            }),
+            PenaltyTypeConfig::Huber => Self::Huber(HuberPenalty {
+                max_diff,
+                delta: positive("delta", fixed(cfg.huber.delta)?)?,
+            }),
+            PenaltyTypeConfig::Step => Self::Step(StepPenalty {
+                max_diff,
+                threshold: fixed(cfg.step.threshold)?,
+            }),
+        })
+    }
+
+    /// The weight of a terminal without any diff.
+    pub fn max_diff(&self) -> &BigInt {
+        match self {
+            Self::Linear(p) => &p.max_diff,
+            Self::Quadratic(p) => &p.max_diff,
+            Self::ExponentialDecay(p) => &p.max_diff,
+            Self::Huber(p) => &p.max_diff,
+            Self::Step(p) => &p.max_diff,
+        }
+    }
+}
+
+impl Penalty for PenaltyKind<BigInt> {
+    type BaseType = BigInt;
+
+    fn eval(&self, dist: BigInt) -> BigInt {
+        match self {
+            Self::Linear(p) => p.eval(dist),
+            Self::Quadratic(p) => p.eval(dist),
+            Self::ExponentialDecay(p) => p.eval(dist),
+            Self::Huber(p) => p.eval(dist),
+            Self::Step(p) => p.eval(dist),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_penalty_kind_from_cfg() {
+        let mut cfg = PoxConfig::default();
+        cfg.penalty.max_diff = dec!(10);
+        cfg.rspr_precision_bigint = 4;
+        let diff = BigInt::from(40000);
+        let mut weights = Vec::new();
+        for penalty_type in [
+            PenaltyTypeConfig::Linear,
+            PenaltyTypeConfig::Quadratic,
+            PenaltyTypeConfig::ExponentialDecay,
+            PenaltyTypeConfig::Huber,
+            PenaltyTypeConfig::Step,
+        ] {
+            cfg.penalty.penalty_type = penalty_type;
+            let penalty = PenaltyKind::from_pox_cfg(&cfg).unwrap();
+            assert_eq!(penalty.max_diff(), &BigInt::from(100000));
+            assert_eq!(penalty.eval(BigInt::from(0)), BigInt::from(100000));
+            weights.push(penalty.eval(diff.clone()));
+        }
+        // a diff of 4 with the default parameters
+        assert_eq!(
+            weights,
+            [60000, 84000, 44932, 84000, 100000]
+                .map(BigInt::from)
+                .to_vec()
+        );
+
+        cfg.penalty.penalty_type = PenaltyTypeConfig::Huber;
+        cfg.penalty.huber.delta = dec!(0);
+        assert!(matches!(
+            PenaltyKind::from_pox_cfg(&cfg),
+            Err(Error::InvalidPenaltyErr(_))
+        ));
+        cfg.penalty.penalty_type = PenaltyTypeConfig::Linear;
+        cfg.penalty.max_diff = dec!(-1);
+        assert!(matches!(
+            PenaltyKind::from_pox_cfg(&cfg),
+            Err(Error::InvalidPenaltyErr(_))
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use tracing::warn;
+use types::FixedPoint;
+
+use super::penalty;
+
+// y = max - x^2 / max, the same end points as the linear penalty but milder for small diffs
+#[derive(Clone, Debug)]
+pub struct QuadraticPenalty<T: FixedPoint> {
+    pub max_diff: T,
+}
+impl<T: FixedPoint> penalty::Penalty for QuadraticPenalty<T> {
+    type BaseType = T;
+    fn eval(&self, diff: T) -> T {
+        if diff > self.max_diff || diff < T::fixed_zero() || self.max_diff.fixed_is_zero() {
+            warn!(message = "invalid diff:", ?diff, ?self.max_diff);
+            T::fixed_zero()
+        } else {
+            self.max_diff.clone() - diff.fixed_sqr() / self.max_diff.clone()
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::Penalty;
+
+    use super::*;
+    use num_bigint::BigInt;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_quadratic_penalty_eval() {
+        let penalty = QuadraticPenalty {
+            max_diff: Decimal::from_str("0.1").unwrap(),
+        };
+        assert_eq!(
+            penalty.eval(Decimal::from_str("0.05").unwrap()),
+            Decimal::from_str("0.075").unwrap()
+        );
+        assert_eq!(
+            penalty.eval(Decimal::from_str("0.1").unwrap()),
+            Decimal::from_str("0.0").unwrap()
+        );
+        assert_eq!(
+            penalty.eval(Decimal::from_str("0.15").unwrap()),
+            Decimal::from_str("0.0").unwrap()
+        );
+        let penalty = QuadraticPenalty {
+            max_diff: BigInt::from(1000),
+        };
+        assert_eq!(penalty.eval(BigInt::from(0)), BigInt::from(1000));
+        // 1000 - 333^2 / 1000 = 1000 - 110
+        assert_eq!(penalty.eval(BigInt::from(333)), BigInt::from(890));
+        assert_eq!(penalty.eval(BigInt::from(-1)), BigInt::from(0));
+        let penalty = QuadraticPenalty {
+            max_diff: BigInt::from(0),
+        };
+        assert_eq!(penalty.eval(BigInt::from(0)), BigInt::from(0));
+    }
+}
@@ -0,0 +1,43 @@
+use tracing::warn;
+use types::FixedPoint;
+
+use super::penalty;
+
+// y = max for x <= threshold, 0 above
+#[derive(Clone, Debug)]
+pub struct StepPenalty<T: FixedPoint> {
+    pub max_diff: T,
+    pub threshold: T,
+}
+impl<T: FixedPoint> penalty::Penalty for StepPenalty<T> {
+    type BaseType = T;
+    fn eval(&self, diff: T) -> T {
+        if diff < T::fixed_zero() {
+            warn!(message = "invalid diff:", ?diff, ?self.max_diff);
+            T::fixed_zero()
+        } else if diff > self.threshold {
+            T::fixed_zero()
+        } else {
+            self.max_diff.clone()
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::Penalty;
+
+    use super::*;
+    use num_bigint::BigInt;
+
+    #[test]
+    fn test_step_penalty_eval() {
+        let penalty = StepPenalty {
+            max_diff: BigInt::from(100),
+            threshold: BigInt::from(50),
+        };
+        assert_eq!(penalty.eval(BigInt::from(0)), BigInt::from(100));
+        assert_eq!(penalty.eval(BigInt::from(50)), BigInt::from(100));
+        assert_eq!(penalty.eval(BigInt::from(51)), BigInt::from(0));
+        assert_eq!(penalty.eval(BigInt::from(-1)), BigInt::from(0));
+    }
+}
@@ -0,0 +1,295 @@
+use config::PoxConfig;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use types::{Error, FixedPointDecimal, RemoteOrbit, RemoteTrack};
+
+use crate::{falloff_percent, BlockResult};
+
+// WGS84, in km
+const EARTH_EQUATORIAL_RADIUS: f64 = 6378.137;
+const EARTH_FLATTENING: f64 = 1.0 / 298.257223563;
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2440587.5;
+const J2000_JULIAN_DATE: f64 = 2451545.0;
+const DAYS_PER_JULIAN_YEAR: f64 = 365.25;
+
+/// How far the reported track of the remote at a block is from its TLE.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrbitCheck {
+    // in km
+    pub position_deviation: Option<Decimal>,
+    // of the orbital speed, in km/s
+    pub speed_deviation: Option<Decimal>,
+    // share of the PoD score of the block that counts
+    pub weight_percent: u32,
+    pub error: Option<String>,
+}
+impl OrbitCheck {
+    fn failed(error: String) -> Self {
+        Self {
+            position_deviation: None,
+            speed_deviation: None,
+            weight_percent: 0,
+            error: Some(error),
+        }
+    }
+}
+
+struct Tolerances {
+    position: Decimal,
+    max_position: Decimal,
+    speed: Decimal,
+    max_speed: Decimal,
+}
+impl Tolerances {
+    fn from_pox_cfg(cfg: &PoxConfig) -> Result<Self, Error> {
+        let c = &cfg.orbit;
+        let tolerances = Self {
+            position: c.position_tolerance,
+            max_position: c.max_position_deviation,
+            speed: c.speed_tolerance,
+            max_speed: c.max_speed_deviation,
+        };
+        if tolerances.position < Decimal::ZERO
+            || tolerances.max_position <= tolerances.position
+            || tolerances.speed < Decimal::ZERO
+            || tolerances.max_speed <= tolerances.speed
+        {
+            return Err(Error::InvalidOrbitErr(format!(
+                "tolerances have to be in [0, max deviation): position {} of {} km, speed {} of {} km/s",
+                tolerances.position,
+                tolerances.max_position,
+                tolerances.speed,
+                tolerances.max_speed
+            )));
+        }
+        Ok(tolerances)
+    }
+}
+
+/// Julian years since J2000 of a unix time, the time scale of the TLE epochs.
+fn unix_to_j2000_years(unix_time: f64) -> f64 {
+    (unix_time / 86400.0 + UNIX_EPOCH_JULIAN_DATE - J2000_JULIAN_DATE) / DAYS_PER_JULIAN_YEAR
+}
+fn block_unix_time(cfg: &PoxConfig, block_height: u64) -> Result<f64, Error> {
+    Ok(cfg.orbit.genesis_time as f64
+        + block_height as f64 * cfg.orbit.block_interval.fixed_to_f64()?)
+}
+fn geodetic_to_ecef(longitude: f64, latitude: f64, height: f64) -> [f64; 3] {
+    let (lon, lat) = (longitude.to_radians(), latitude.to_radians());
+    let e2 = EARTH_FLATTENING * (2.0 - EARTH_FLATTENING);
+    let n = EARTH_EQUATORIAL_RADIUS / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    [
+        (n + height) * lat.cos() * lon.cos(),
+        (n + height) * lat.cos() * lon.sin(),
+        (n * (1.0 - e2) + height) * lat.sin(),
+    ]
+}
+
+struct Propagator {
+    epoch: f64,
+    constants: sgp4::Constants,
+}
+impl Propagator {
+    fn from_tle(orbit: &RemoteOrbit) -> Result<Self, String> {
+        let name = orbit.tle.line0.trim();
+        let elements = sgp4::Elements::from_tle(
+            (!name.is_empty()).then(|| name.to_owned()),
+            orbit.tle.line1.trim().as_bytes(),
+            orbit.tle.line2.trim().as_bytes(),
+        )
+        .map_err(|e| format!("invalid TLE: {}", e))?;
+        let constants = sgp4::Constants::from_elements(&elements)
+            .map_err(|e| format!("invalid TLE elements: {}", e))?;
+        Ok(Self {
+            epoch: elements.epoch(),
+            constants,
+        })
+    }
+    /// Earth fixed position in km and orbital speed in km/s at a unix time.
+    fn predict(&self, unix_time: f64) -> Result<([f64; 3], f64), String> {
+        let years = unix_to_j2000_years(unix_time);
+        let minutes = (years - self.epoch) * DAYS_PER_JULIAN_YEAR * 1440.0;
+        let prediction = self
+            .constants
+            .propagate(sgp4::MinutesSinceEpoch(minutes))
+            .map_err(|e| format!("propagation failed: {}", e))?;
+        // TEME to earth fixed, polar motion is far below the tolerances
+        let gmst = sgp4::iau_epoch_to_sidereal_time(years);
+        let [x, y, z] = prediction.position;
+        let position = [
+            gmst.cos() * x + gmst.sin() * y,
+            -gmst.sin() * x + gmst.cos() * y,
+            z,
+        ];
+        let speed = prediction
+            .velocity
+            .iter()
+            .map(|v| v * v)
+            .sum::<f64>()
+            .sqrt();
+        Ok((position, speed))
+    }
+    fn check(&self, track: &RemoteTrack, unix_time: f64, tolerances: &Tolerances) -> OrbitCheck {
+        let (expected, expected_speed) = match self.predict(unix_time) {
+            Ok(prediction) => prediction,
+            Err(e) => return OrbitCheck::failed(e),
+        };
+        let reported = geodetic_to_ecef(track.longitude, track.latitude, track.height);
+        let position_deviation = expected
+            .iter()
+            .zip(reported)
+            .map(|(e, r)| (e - r).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let speed_deviation = (track.speed - expected_speed).abs();
+        let (position_deviation, speed_deviation) = (
+            Decimal::from_f64(position_deviation),
+            Decimal::from_f64(speed_deviation),
+        );
+        OrbitCheck {
+            position_deviation: position_deviation.map(|d| d.round_dp(3)),
+            speed_deviation: speed_deviation.map(|d| d.round_dp(3)),
+            // a deviation that is not a number earns nothing
+            weight_percent: position_deviation.zip(speed_deviation).map_or(
+                0,
+                |(position, speed)| {
+                    falloff_percent(position, tolerances.position, tolerances.max_position).min(
+                        falloff_percent(speed, tolerances.speed, tolerances.max_speed),
+                    )
+                },
+            ),
+            error: None,
+        }
+    }
+}
+
+/// Checks the reported track of the remote at every block against its TLE, empty if the
+/// check is disabled. A block without a TLE or a track earns nothing.
+pub fn check_orbit(
+    orbit: Option<&RemoteOrbit>,
+    block_heights: &[u64],
+    cfg: &PoxConfig,
+) -> Result<Vec<BlockResult<OrbitCheck>>, Error> {
+    if !cfg.orbit.enabled {
+        return Ok(Vec::new());
+    }
+    let _span = tracing::debug_span!("check_orbit").entered();
+    let tolerances = Tolerances::from_pox_cfg(cfg)?;
+    let propagator = orbit
+        .ok_or_else(|| "remote has no TLE".to_owned())
+        .and_then(Propagator::from_tle);
+    block_heights
+        .iter()
+        .map(|&block_height| {
+            let track =
+                orbit.and_then(|o| o.tracks.iter().find(|t| t.block_height == block_height));
+            let result = match (&propagator, track) {
+                (Err(e), _) => OrbitCheck::failed(e.clone()),
+                (Ok(_), None) => OrbitCheck::failed("no reported track at the block".to_owned()),
+                (Ok(propagator), Some(track)) => {
+                    propagator.check(track, block_unix_time(cfg, block_height)?, &tolerances)
+                }
+            };
+            if result.weight_percent < 100 {
+                warn!(
+                    message = "remote track deviates from its TLE",
+                    block_height,
+                    position_deviation = ?result.position_deviation,
+                    speed_deviation = ?result.speed_deviation,
+                    error = ?result.error
+                );
+            }
+            Ok(BlockResult {
+                block_height,
+                result,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()
+        .map(|checks| {
+            debug!(message = "orbit checked", blocks = checks.len());
+            checks
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use types::Tle;
+
+    fn ecef_to_geodetic([x, y, z]: [f64; 3]) -> (f64, f64, f64) {
+        let e2 = EARTH_FLATTENING * (2.0 - EARTH_FLATTENING);
+        let p = (x * x + y * y).sqrt();
+        let mut lat = z.atan2(p * (1.0 - e2));
+        let mut height = 0.0;
+        for _ in 0..10 {
+            let n = EARTH_EQUATORIAL_RADIUS / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+            height = p / lat.cos() - n;
+            lat = z.atan2(p * (1.0 - e2 * n / (n + height)));
+        }
+        (y.atan2(x).to_degrees(), lat.to_degrees(), height)
+    }
+
+    #[test]
+    fn test_check_orbit() {
+        let mut cfg = PoxConfig::default();
+        // the epoch of the TLE, 2020-07-12 21:16:01 UTC
+        cfg.orbit.genesis_time = 1594588561;
+        let mut orbit = RemoteOrbit {
+            address: "0xr".to_owned(),
+            tle: Tle {
+                line0: "ISS (ZARYA)".to_owned(),
+                line1: "1 25544U 98067A   20194.88612269 -.00002218  00000-0 -31515-4 0  9992"
+                    .to_owned(),
+                line2: "2 25544  51.6461 221.2784 0001413  89.1723 280.4612 15.49507896236008"
+                    .to_owned(),
+            },
+            tracks: Vec::new(),
+        };
+        assert!(check_orbit(Some(&orbit), &[0], &cfg).unwrap().is_empty());
+        cfg.orbit.enabled = true;
+
+        let propagator = Propagator::from_tle(&orbit).unwrap();
+        for block_height in [0, 100, 1000] {
+            let (position, speed) = propagator
+                .predict(block_unix_time(&cfg, block_height).unwrap())
+                .unwrap();
+            let (longitude, latitude, height) = ecef_to_geodetic(position);
+            assert!(height > 400.0 && height < 440.0, "height {}", height);
+            assert!(speed > 7.6 && speed < 7.7, "speed {}", speed);
+            orbit.tracks.push(RemoteTrack {
+                block_height,
+                longitude,
+                latitude,
+                height,
+                speed,
+            });
+        }
+        // the remote claims to be 2 degrees further east, above 200 km off
+        orbit.tracks[1].longitude += 2.0;
+        // half way between the speed tolerance and the max deviation
+        orbit.tracks[2].speed += 0.55;
+        let checks = check_orbit(Some(&orbit), &[0, 100, 1000, 2000], &cfg).unwrap();
+        let percents: Vec<u32> = checks.iter().map(|c| c.result.weight_percent).collect();
+        assert_eq!(percents[..2], [100, 0]);
+        assert!((49..=50).contains(&percents[2]), "percent {}", percents[2]);
+        assert_eq!(checks[0].result.position_deviation, Some(dec!(0)));
+        // no track at block 2000
+        assert_eq!(percents[3], 0);
+        assert!(checks[3].result.error.is_some());
+
+        let checks = check_orbit(None, &[0], &cfg).unwrap();
+        assert_eq!(checks[0].result.weight_percent, 0);
+
+        orbit.tle.line2 = "2 25544".to_owned();
+        let checks = check_orbit(Some(&orbit), &[0], &cfg).unwrap();
+        assert_eq!(checks[0].result.weight_percent, 0);
+
+        cfg.orbit.max_speed_deviation = dec!(0.1);
+        assert!(matches!(
+            check_orbit(Some(&orbit), &[0], &cfg),
+            Err(Error::InvalidOrbitErr(_))
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use config::PoxConfig;
+use num_bigint::BigInt;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, MathematicalOps};
+use rust_decimal_macros::dec;
+use types::{Error, FixedPointInteger, Pos2D, Pos3D};
+
+// 20 * log10(1000 / c) for d in km and f in MHz
+const FSPL_CONSTANT_DB: Decimal = dec!(32.44);
+// below it the log-distance model does not hold
+const MIN_DISTANCE_M: Decimal = dec!(1);
+
+/// Log-distance path loss from the remote to the terminals, free space with exponent 2.
+/// The model is computed in Decimal, so that the prover and the verifier agree on every
+/// plausibility_percent.
+#[derive(Clone, Debug)]
+pub struct PathLoss {
+    // expected rspr in dBm at the reference distance
+    reference_rspr: Decimal,
+    // in meters, the expected rspr is flat below it
+    reference_distance: Decimal,
+    exponent: Decimal,
+    tolerance_db: Decimal,
+    max_deviation_db: Decimal,
+    coordinate_precision: u32,
+    rspr_precision: u32,
+}
+
+fn invalid(e: String) -> Error {
+    Error::InvalidPathLossErr(e)
+}
+
+impl PathLoss {
+    /// None if the model is disabled.
+    pub fn from_pox_cfg(cfg: &PoxConfig) -> Result<Option<Self>, Error> {
+        let c = &cfg.path_loss;
+        if !c.enabled {
+            return Ok(None);
+        }
+        if c.frequency_mhz <= Decimal::ZERO || c.exponent <= Decimal::ZERO {
+            return Err(invalid(format!(
+                "frequency {} MHz and exponent {} have to be positive",
+                c.frequency_mhz, c.exponent
+            )));
+        }
+        if c.tolerance_db < Decimal::ZERO || c.max_deviation_db <= c.tolerance_db {
+            return Err(invalid(format!(
+                "tolerance {} dB has to be in [0, max deviation {} dB)",
+                c.tolerance_db, c.max_deviation_db
+            )));
+        }
+        // at 1 m, log10(d_km) = -3
+        let reference_rspr = c.tx_power_dbm - c.extra_loss_db + dec!(30) * c.exponent
+            - dec!(20) * c.frequency_mhz.log10()
+            - FSPL_CONSTANT_DB;
+        Ok(Some(Self {
+            reference_rspr,
+            reference_distance: MIN_DISTANCE_M,
+            exponent: c.exponent,
+            tolerance_db: c.tolerance_db,
+            max_deviation_db: c.max_deviation_db,
+            coordinate_precision: cfg.coordinate_precision_bigint,
+            rspr_precision: cfg.rspr_precision_bigint,
+        }))
+    }
+
+    /// Free space from reference_rspr dBm at reference_distance meters, for the bounds of the
+    /// anomaly detection.
+    pub(crate) fn free_space(
+        reference_rspr: Decimal,
+        reference_distance: Decimal,
+        cfg: &PoxConfig,
+    ) -> Result<Self, Error> {
+        if reference_distance <= Decimal::ZERO {
+            return Err(invalid(format!(
+                "reference distance {} m is not positive",
+                reference_distance
+            )));
+        }
+        Ok(Self {
+            reference_rspr,
+            reference_distance,
+            exponent: dec!(2),
+            tolerance_db: Decimal::ZERO,
+            max_deviation_db: Decimal::ZERO,
+            coordinate_precision: cfg.coordinate_precision_bigint,
+            rspr_precision: cfg.rspr_precision_bigint,
+        })
+    }
+
+    /// Distance in meters from the remote to the terminal on the ground.
+    pub fn distance(&self, remote: &Pos3D<BigInt>, pos: &Pos2D<BigInt>) -> Result<Decimal, Error> {
+        let dx = (&pos.x - &remote.x).fixed_to_decimal(self.coordinate_precision)?;
+        let dy = (&pos.y - &remote.y).fixed_to_decimal(self.coordinate_precision)?;
+        let height = remote.height.fixed_to_decimal(self.coordinate_precision)?;
+        dx.checked_mul(dx)
+            .zip(dy.checked_mul(dy))
+            .zip(height.checked_mul(height))
+            .and_then(|((x, y), h)| x.checked_add(y)?.checked_add(h))
+            .and_then(|d| d.sqrt())
+            .ok_or_else(|| invalid(format!("distance of ({}, {}, {}) m", dx, dy, height)))
+    }
+
+    /// Expected rspr in dBm at distance_m meters.
+    pub fn expected_rspr(&self, distance_m: Decimal) -> Result<Decimal, Error> {
+        let ratio = distance_m.max(self.reference_distance) / self.reference_distance;
+        let log = ratio
+            .checked_log10()
+            .ok_or_else(|| invalid(format!("log10 of {}", ratio)))?;
+        Ok(self.reference_rspr - dec!(10) * self.exponent * log)
+    }
+
+    /// Expected rspr in dBm of a terminal at pos.
+    pub fn expected_rspr_at(
+        &self,
+        remote: &Pos3D<BigInt>,
+        pos: &Pos2D<BigInt>,
+    ) -> Result<Decimal, Error> {
+        self.expected_rspr(self.distance(remote, pos)?)
+    }
+
+    /// rspr in dBm of its fixed point value.
+    pub(crate) fn rspr(&self, rspr: &BigInt) -> Result<Decimal, Error> {
+        rspr.fixed_to_decimal(self.rspr_precision)
+    }
+
+    /// Share of the weight of the terminal in percent, full within the tolerance of the
+    /// expected rspr, decreasing linearly to zero at the max deviation.
+    pub fn plausibility_percent(
+        &self,
+        remote: &Pos3D<BigInt>,
+        pos: &Pos2D<BigInt>,
+        rspr: &BigInt,
+    ) -> Result<u32, Error> {
+        let expected = self.expected_rspr_at(remote, pos)?;
+        Ok(falloff_percent(
+            (self.rspr(rspr)? - expected).abs(),
+            self.tolerance_db,
+            self.max_deviation_db,
+        ))
+    }
+}
+
+/// 100 up to the tolerance, decreasing linearly to 0 at the max deviation.
+pub(crate) fn falloff_percent(
+    deviation: Decimal,
+    tolerance: Decimal,
+    max_deviation: Decimal,
+) -> u32 {
+    if deviation >= max_deviation {
+        return 0;
+    }
+    if deviation <= tolerance {
+        return 100;
+    }
+    let share = (max_deviation - deviation) * dec!(100) / (max_deviation - tolerance);
+    share.floor().to_u32().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_loss() {
+        let mut cfg = PoxConfig::default();
+        assert!(PathLoss::from_pox_cfg(&cfg).unwrap().is_none());
+        cfg.path_loss.enabled = true;
+        let model = PathLoss::from_pox_cfg(&cfg).unwrap().unwrap();
+        // 1 km at 2400 MHz: 20 * log10(2400) + 32.44 = 100.04 dB
+        let at = |d: Decimal| model.expected_rspr(d).unwrap();
+        assert!((at(dec!(1000)) - dec!(-80.0442)).abs() < dec!(0.001));
+        // 6 dB less every time the distance doubles in free space
+        assert!((at(dec!(1000)) - at(dec!(2000)) - dec!(6.0206)).abs() < dec!(0.001));
+        assert_eq!(at(dec!(0)), at(MIN_DISTANCE_M));
+
+        // the remote is 1 km above the terminal, coordinates with 3 decimals
+        let remote = Pos3D {
+            x: BigInt::from(0),
+            y: BigInt::from(0),
+            height: BigInt::from(1_000_000),
+        };
+        let pos = Pos2D {
+            x: BigInt::from(0),
+            y: BigInt::from(0),
+        };
+        assert_eq!(
+            model.distance(&remote, &pos).unwrap().round_dp(9),
+            dec!(1000)
+        );
+        // rspr with 4 decimals, the model expects -80.04 dBm
+        let percent = |rspr: i64| {
+            model
+                .plausibility_percent(&remote, &pos, &BigInt::from(rspr))
+                .unwrap()
+        };
+        assert_eq!(percent(-800_000), 100);
+        assert_eq!(percent(-710_000), 100);
+        assert_eq!(percent(-500_000), 0);
+        // 20.04 dB off, half way between the tolerance and the max deviation
+        assert_eq!(percent(-1_000_800), 49);
+        assert_eq!(percent(-1_100_442), 0);
+
+        // 20 * log10(100) below the reference at 100 times the distance
+        let bound = PathLoss::free_space(dec!(60), dec!(1), &cfg).unwrap();
+        assert_eq!(
+            bound.expected_rspr(dec!(100)).unwrap().round_dp(9),
+            dec!(20)
+        );
+        assert!(PathLoss::free_space(dec!(60), dec!(0), &cfg).is_err());
+
+        cfg.path_loss.max_deviation_db = dec!(10);
+        assert!(matches!(
+            PathLoss::from_pox_cfg(&cfg),
+            Err(Error::InvalidPathLossErr(_))
+        ));
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::BTreeSet;
+
+use config::{PoFModeConfig, PoxConfig};
+use num_bigint::BigInt;
+use rs_merkle::{algorithms::Sha256, Hasher, MerkleProof, MerkleTree};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use types::{Error, FixedPointDecimal, MerkleProofStruct};
+
+use crate::PoFTerminalResult;
+
+/// Openings of the dropped tree of a terminal at the challenged leaves, the ones of the
+/// reference tree are in the proof of the terminal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoFSample {
+    dropped_proof: Vec<u8>,
+    dropped_leaves: Vec<[u8; 32]>,
+    // challenged leaves the terminal dropped
+    pub dropped: usize,
+    // bounds of the drop rate at the confidence of cfg
+    pub drop_rate_lower: Decimal,
+    pub drop_rate_upper: Decimal,
+}
+
+/// Challenges a sample of the packets of every terminal instead of opening every drop.
+#[derive(Clone, Debug)]
+pub struct Sampling {
+    sample_count: usize,
+    confidence_z: f64,
+}
+
+impl Sampling {
+    /// None in the full mode.
+    pub fn from_pox_cfg(cfg: &PoxConfig) -> Result<Option<Self>, Error> {
+        let c = &cfg.pof;
+        if c.mode == PoFModeConfig::Full {
+            return Ok(None);
+        }
+        let confidence_z = c.confidence_z.fixed_to_f64()?;
+        if c.sample_count == 0 || confidence_z < 0.0 {
+            return Err(Error::InvalidPoFErr(format!(
+                "sample_count {} has to be positive and confidence_z {} not negative",
+                c.sample_count, confidence_z
+            )));
+        }
+        Ok(Some(Self {
+            sample_count: c.sample_count,
+            confidence_z,
+        }))
+    }
+
+    /// Leaves to open, sorted, derived from both roots, the size of the trees and the
+    /// statement so that the prover can not choose them, nor rebuild the dropped tree once
+    /// it knows them. Every leaf of a small tree is opened.
+    pub fn indices(
+        &self,
+        reference_root: &[u8; 32],
+        dropped_root: &[u8; 32],
+        statement: &zkt::Statement,
+        total: usize,
+    ) -> Vec<usize> {
+        if self.sample_count >= total {
+            return (0..total).collect();
+        }
+        let mut seed = reference_root.to_vec();
+        seed.extend_from_slice(dropped_root);
+        seed.extend_from_slice(&(total as u64).to_le_bytes());
+        seed.extend_from_slice(&statement.epoch.to_le_bytes());
+        seed.extend_from_slice(&statement.block_from.to_le_bytes());
+        seed.extend_from_slice(&statement.block_to.to_le_bytes());
+        seed.extend_from_slice(statement.remote.as_bytes());
+        let mut indices = BTreeSet::new();
+        let mut counter = 0_u64;
+        while indices.len() < self.sample_count {
+            let mut bytes = seed.clone();
+            bytes.extend_from_slice(&counter.to_le_bytes());
+            let hash = Sha256::hash(&bytes);
+            let draw = u64::from_le_bytes(hash[..8].try_into().unwrap_or_default());
+            indices.insert((draw % total as u64) as usize);
+            counter += 1;
+        }
+        indices.into_iter().collect()
+    }
+
+    /// Wilson score bounds of the drop rate, exact if every leaf is sampled.
+    pub fn drop_rate_bounds(
+        &self,
+        total: usize,
+        sampled: usize,
+        dropped: usize,
+    ) -> (Decimal, Decimal) {
+        let rate = |r: f64, strategy| {
+            Decimal::from_f64(r.clamp(0.0, 1.0))
+                .unwrap_or(Decimal::ONE)
+                .round_dp_with_strategy(4, strategy)
+        };
+        if sampled == 0 {
+            return (Decimal::ZERO, Decimal::ONE);
+        }
+        if sampled >= total {
+            let p = Decimal::from(dropped) / Decimal::from(sampled);
+            return (
+                p.round_dp_with_strategy(4, RoundingStrategy::ToZero),
+                p.round_dp_with_strategy(4, RoundingStrategy::AwayFromZero),
+            );
+        }
+        let n = sampled as f64;
+        let p = dropped as f64 / n;
+        let z2 = self.confidence_z * self.confidence_z;
+        let denom = 1.0 + z2 / n;
+        let center = (p + z2 / (2.0 * n)) / denom;
+        let half = self.confidence_z / denom * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        (
+            rate(center - half, RoundingStrategy::ToZero),
+            rate(center + half, RoundingStrategy::AwayFromZero),
+        )
+    }
+
+    /// Valid packets credited to a terminal, the ones left at the upper bound of its drop
+    /// rate.
+    pub fn credited(&self, total: usize, sample: &PoFSample) -> usize {
+        (Decimal::from(total) * (Decimal::ONE - sample.drop_rate_upper))
+            .floor()
+            .to_usize()
+            .unwrap_or_default()
+    }
+
+    /// Opens the reference tree and the dropped tree of a terminal at the challenged
+    /// leaves, the dropped tree has the empty hash at the leaves the terminal missed.
+    pub(crate) fn prove(
+        &self,
+        reference: &MerkleTree<Sha256>,
+        terminal: &MerkleTree<Sha256>,
+        statement: &zkt::Statement,
+    ) -> Result<(MerkleProofStruct, PoFSample), Error> {
+        let leaves_err =
+            || Error::MerkleTreeErr("Couldn't get the leaves of the merkle tree".to_owned());
+        let root_err =
+            || Error::MerkleTreeErr("Couldn't get the root of the merkle tree".to_owned());
+        let reference_leaves = reference.leaves().ok_or_else(leaves_err)?;
+        let terminal_leaves = terminal.leaves().ok_or_else(leaves_err)?;
+        let empty = Sha256::hash(&[]);
+        let dropped_leaves: Vec<[u8; 32]> = reference_leaves
+            .iter()
+            .enumerate()
+            .map(|(i, leaf)| {
+                if terminal_leaves.get(i) == Some(leaf) {
+                    *leaf
+                } else {
+                    empty
+                }
+            })
+            .collect();
+        let dropped_tree = MerkleTree::<Sha256>::from_leaves(&dropped_leaves);
+        let root = reference.root().ok_or_else(root_err)?;
+        let dropped_root = dropped_tree.root().ok_or_else(root_err)?;
+        let total = reference_leaves.len();
+        let indices = self.indices(&root, &dropped_root, statement, total);
+        let leaves_to_prove: Vec<[u8; 32]> = indices.iter().map(|&i| reference_leaves[i]).collect();
+        let sampled_dropped: Vec<[u8; 32]> = indices.iter().map(|&i| dropped_leaves[i]).collect();
+        let dropped = leaves_to_prove
+            .iter()
+            .zip(&sampled_dropped)
+            .filter(|(r, d)| r != d)
+            .count();
+        let (drop_rate_lower, drop_rate_upper) =
+            self.drop_rate_bounds(total, indices.len(), dropped);
+        let proof = MerkleProofStruct {
+            reference_merkle_tree_root: root,
+            dropped_merkle_tree_root: dropped_root,
+            proof: reference.proof(&indices).to_bytes(),
+            indices_to_prove: indices.clone(),
+            leaves_to_prove,
+            total_leaves_count: total,
+        };
+        let sample = PoFSample {
+            dropped_proof: dropped_tree.proof(&indices).to_bytes(),
+            dropped_leaves: sampled_dropped,
+            dropped,
+            drop_rate_lower,
+            drop_rate_upper,
+        };
+        Ok((proof, sample))
+    }
+
+    /// Checks that the challenged leaves are opened in both trees, and the drops, bounds
+    /// and valid packets the terminal claims from them.
+    pub(crate) fn verify(
+        &self,
+        r: &PoFTerminalResult<BigInt>,
+        statement: &zkt::Statement,
+    ) -> Result<(), String> {
+        let sample = r.sample.as_ref().ok_or("no sample of the packets")?;
+        let proof = &r.proof;
+        let total = proof.total_leaves_count;
+        if proof.indices_to_prove
+            != self.indices(
+                &proof.reference_merkle_tree_root,
+                &proof.dropped_merkle_tree_root,
+                statement,
+                total,
+            )
+        {
+            return Err("opened leaves are not the challenged ones".to_owned());
+        }
+        let opened = |bytes: &[u8], root: [u8; 32], leaves: &[[u8; 32]]| {
+            leaves.len() == proof.indices_to_prove.len()
+                && MerkleProof::<Sha256>::try_from(bytes)
+                    .is_ok_and(|p| p.verify(root, &proof.indices_to_prove, leaves, total))
+        };
+        if !opened(
+            &proof.proof,
+            proof.reference_merkle_tree_root,
+            &proof.leaves_to_prove,
+        ) {
+            return Err("reference tree opening failed".to_owned());
+        }
+        if !opened(
+            &sample.dropped_proof,
+            proof.dropped_merkle_tree_root,
+            &sample.dropped_leaves,
+        ) {
+            return Err("dropped tree opening failed".to_owned());
+        }
+        let empty = Sha256::hash(&[]);
+        let mut dropped = 0;
+        for (reference, leaf) in proof.leaves_to_prove.iter().zip(&sample.dropped_leaves) {
+            if leaf != reference {
+                if *leaf != empty {
+                    return Err("a dropped leaf is neither the reference nor empty".to_owned());
+                }
+                dropped += 1;
+            }
+        }
+        let bounds = self.drop_rate_bounds(total, proof.indices_to_prove.len(), dropped);
+        if sample.dropped != dropped || (sample.drop_rate_lower, sample.drop_rate_upper) != bounds {
+            return Err(format!(
+                "claimed {} dropped in [{}, {}], the sample has {} in [{}, {}]",
+                sample.dropped,
+                sample.drop_rate_lower,
+                sample.drop_rate_upper,
+                dropped,
+                bounds.0,
+                bounds.1
+            ));
+        }
+        let credited = self.credited(total, sample);
+        if r.valid_packets_num != BigInt::from(credited) {
+            return Err(format!(
+                "claimed {} valid packets, {} credited",
+                r.valid_packets_num, credited
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_sampling() {
+        let mut cfg = PoxConfig::default();
+        assert!(Sampling::from_pox_cfg(&cfg).unwrap().is_none());
+        cfg.pof.mode = PoFModeConfig::Sampled;
+        cfg.pof.sample_count = 100;
+        let sampling = Sampling::from_pox_cfg(&cfg).unwrap().unwrap();
+        let statement = crate::pod_statement(&cfg, "0x0", 1, 10, 10).unwrap();
+
+        let indices = sampling.indices(&[1; 32], &[3; 32], &statement, 10_000);
+        assert_eq!(indices.len(), 100);
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            indices,
+            sampling.indices(&[1; 32], &[3; 32], &statement, 10_000)
+        );
+        assert_ne!(
+            indices,
+            sampling.indices(&[2; 32], &[3; 32], &statement, 10_000)
+        );
+        assert_ne!(
+            indices,
+            sampling.indices(&[1; 32], &[4; 32], &statement, 10_000)
+        );
+        assert_ne!(
+            indices,
+            sampling.indices(&[1; 32], &[3; 32], &statement, 10_001)
+        );
+        assert_eq!(
+            sampling.indices(&[1; 32], &[3; 32], &statement, 50),
+            (0..50).collect::<Vec<_>>()
+        );
+
+        // 10 of 100 dropped, 95% Wilson interval
+        let (lower, upper) = sampling.drop_rate_bounds(10_000, 100, 10);
+        assert_eq!((lower, upper), (dec!(0.0552), dec!(0.1744)));
+        // the whole tree sampled, the rate is exact
+        assert_eq!(
+            sampling.drop_rate_bounds(50, 50, 10),
+            (dec!(0.2), dec!(0.2))
+        );
+        let sample = PoFSample {
+            dropped_proof: Vec::new(),
+            dropped_leaves: Vec::new(),
+            dropped: 10,
+            drop_rate_lower: lower,
+            drop_rate_upper: upper,
+        };
+        assert_eq!(sampling.credited(10_000, &sample), 8256);
+
+        cfg.pof.sample_count = 0;
+        assert!(matches!(
+            Sampling::from_pox_cfg(&cfg),
+            Err(Error::InvalidPoFErr(_))
+        ));
+    }
+
+    #[test]
+    fn test_sampling_rebuilt_dropped_tree() {
+        let mut cfg = PoxConfig::default();
+        cfg.pof.mode = PoFModeConfig::Sampled;
+        cfg.pof.sample_count = 20;
+        let sampling = Sampling::from_pox_cfg(&cfg).unwrap().unwrap();
+        let statement = crate::pod_statement(&cfg, "0x0", 1, 10, 10).unwrap();
+        let total = 1000_usize;
+        let leaves: Vec<[u8; 32]> = (0..total)
+            .map(|i| Sha256::hash(&(i as u64).to_le_bytes()))
+            .collect();
+        let reference = MerkleTree::<Sha256>::from_leaves(&leaves);
+        // the terminal dropped every other packet
+        let empty = Sha256::hash(&[]);
+        let received: Vec<[u8; 32]> = leaves
+            .iter()
+            .enumerate()
+            .map(|(i, l)| if i % 2 == 0 { *l } else { empty })
+            .collect();
+        let terminal = MerkleTree::<Sha256>::from_leaves(&received);
+        let (proof, sample) = sampling.prove(&reference, &terminal, &statement).unwrap();
+        let result = |proof: MerkleProofStruct, sample: PoFSample| {
+            let valid = sampling.credited(total, &sample);
+            PoFTerminalResult {
+                terminal_address: "0x1".to_owned(),
+                valid_packets_num: BigInt::from(valid),
+                proof,
+                invalid_packets_num: BigInt::from(total - valid),
+                service_percent: 100,
+                sample: Some(sample),
+                status: crate::TerminalStatus::Ok,
+            }
+        };
+        assert!(sample.dropped > 0);
+        let honest = result(proof.clone(), sample);
+        assert_eq!(sampling.verify(&honest, &statement), Ok(()));
+
+        // the challenged packets filled in once they are known, as if none was dropped
+        let indices = proof.indices_to_prove.clone();
+        let mut rebuilt_leaves = received;
+        for &i in &indices {
+            rebuilt_leaves[i] = leaves[i];
+        }
+        let rebuilt = MerkleTree::<Sha256>::from_leaves(&rebuilt_leaves);
+        let mut forged_proof = proof;
+        forged_proof.dropped_merkle_tree_root = rebuilt.root().unwrap();
+        let (lower, upper) = sampling.drop_rate_bounds(total, indices.len(), 0);
+        let forged_sample = PoFSample {
+            dropped_proof: rebuilt.proof(&indices).to_bytes(),
+            dropped_leaves: indices.iter().map(|&i| leaves[i]).collect(),
+            dropped: 0,
+            drop_rate_lower: lower,
+            drop_rate_upper: upper,
+        };
+        let forged = result(forged_proof, forged_sample);
+        assert_eq!(
+            sampling.verify(&forged, &statement),
+            Err("opened leaves are not the challenged ones".to_owned())
+        );
+    }
+}
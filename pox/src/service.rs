@@ -0,0 +1,95 @@
+use config::PoxConfig;
+use num_bigint::BigInt;
+use rust_decimal::prelude::ToPrimitive;
+use types::{Error, FixedPointDecimal, ServiceQuality};
+
+// in km/ms
+const SPEED_OF_LIGHT: f64 = 299.792458;
+
+/// Scores the sessions of the terminals of a remote.
+#[derive(Clone, Debug)]
+pub struct ServiceScore {
+    full_session_duration: u64,
+    // shortest plausible round trip to the remote in ms, after the slack
+    min_latency: f64,
+}
+
+impl ServiceScore {
+    /// None if the score is disabled, remote_height is in fixed point.
+    pub fn from_pox_cfg(cfg: &PoxConfig, remote_height: &BigInt) -> Result<Option<Self>, Error> {
+        let c = &cfg.service_quality;
+        if !c.enabled {
+            return Ok(None);
+        }
+        let slack = c.latency_slack.fixed_to_f64()?;
+        if c.full_session_duration == 0 || slack < 0.0 {
+            return Err(Error::InvalidServiceQualityErr(format!(
+                "full_session_duration {} has to be positive and latency_slack {} not negative",
+                c.full_session_duration, slack
+            )));
+        }
+        // the remote is at least its height away
+        let height =
+            remote_height.to_f64().unwrap_or_default() / cfg.coordinate_precision_pow10() as f64;
+        Ok(Some(Self {
+            full_session_duration: c.full_session_duration,
+            min_latency: 2.0 * height.max(0.0) / SPEED_OF_LIGHT - slack,
+        }))
+    }
+
+    /// Share of the packets of the terminal that count in percent, a terminal without a
+    /// session counts fully.
+    pub fn percent(&self, service: Option<&ServiceQuality>) -> u32 {
+        let Some(service) = service else {
+            return 100;
+        };
+        let session = match service.disconnect_time {
+            None => 100,
+            Some(end) if end < service.connect_time => 0,
+            Some(end) => {
+                let duration = (end - service.connect_time) as u64;
+                (duration.saturating_mul(100) / self.full_session_duration).min(100) as u32
+            }
+        };
+        if (service.net_latency as f64) < self.min_latency {
+            return 0;
+        }
+        session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_score() {
+        let mut cfg = PoxConfig::default();
+        // 550 km, coordinates with 3 decimals
+        let height = BigInt::from(550_000);
+        assert!(ServiceScore::from_pox_cfg(&cfg, &height).unwrap().is_none());
+        cfg.service_quality.enabled = true;
+        let score = ServiceScore::from_pox_cfg(&cfg, &height).unwrap().unwrap();
+        let service = |connect_time, disconnect_time, net_latency| ServiceQuality {
+            connect_time,
+            disconnect_time,
+            net_latency,
+            net_bandwidth: 100,
+            net_traffic: 1000,
+        };
+        assert_eq!(score.percent(None), 100);
+        assert_eq!(score.percent(Some(&service(0, None, 30))), 100);
+        assert_eq!(score.percent(Some(&service(100, Some(1900), 30))), 50);
+        assert_eq!(score.percent(Some(&service(0, Some(7200), 30))), 100);
+        assert_eq!(score.percent(Some(&service(100, Some(0), 30))), 0);
+        // the round trip to 550 km takes at least 3.67 ms
+        assert_eq!(score.percent(Some(&service(0, None, 3))), 100);
+        assert_eq!(score.percent(Some(&service(0, None, 2))), 0);
+
+        cfg.service_quality.full_session_duration = 0;
+        assert!(matches!(
+            ServiceScore::from_pox_cfg(&cfg, &height),
+            Err(Error::InvalidServiceQualityErr(_))
+        ));
+    }
+}
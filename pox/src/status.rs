@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use types::Error;
+
+/// Why the result of a terminal is what it is, anything but Ok comes with an empty result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TerminalStatus {
+    Ok,
+    // the zk proof of the terminal could not be generated
+    ZkError,
+    // a negative rspr or a fixed point out of range
+    NegativeInput,
+    // the packets of the terminal could not be compared with the reference
+    MerkleError,
+    // the terminal has no packets
+    NoPackets,
+    // the evaluation was cancelled before the terminal
+    Cancelled,
+    // any other error
+    Other,
+}
+impl TerminalStatus {
+    pub fn from_err(err: &Error) -> Self {
+        match err {
+            Error::ZeroKnownledgeProofErr(_) => Self::ZkError,
+            Error::NegativeSqrtErr(_) | Error::NegativeFpErr(_) | Error::FpOutOfRangeErr(..) => {
+                Self::NegativeInput
+            }
+            Error::MerkleTreeErr(_) | Error::EmptyMerkleTreeErr => Self::MerkleError,
+            Error::CancelledErr => Self::Cancelled,
+            _ => Self::Other,
+        }
+    }
+    pub fn is_ok(&self) -> bool {
+        *self == Self::Ok
+    }
+}
+impl fmt::Display for TerminalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Number of terminal results of every status.
+pub fn count_status(
+    statuses: impl Iterator<Item = TerminalStatus>,
+) -> BTreeMap<TerminalStatus, usize> {
+    let mut counts = BTreeMap::new();
+    statuses.for_each(|s| *counts.entry(s).or_insert(0) += 1);
+    counts
+}
+/// Status of every terminal over several blocks, the first one that is not Ok.
+pub(crate) fn merge_status<'a>(
+    statuses: impl Iterator<Item = (&'a String, TerminalStatus)>,
+) -> BTreeMap<String, TerminalStatus> {
+    let mut merged: BTreeMap<String, TerminalStatus> = BTreeMap::new();
+    for (address, status) in statuses {
+        let s = merged.entry(address.clone()).or_insert(status);
+        if s.is_ok() {
+            *s = status;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        assert_eq!(
+            TerminalStatus::from_err(&Error::ZeroKnownledgeProofErr("".to_owned())),
+            TerminalStatus::ZkError
+        );
+        assert_eq!(
+            TerminalStatus::from_err(&Error::NegativeFpErr("-1".to_owned())),
+            TerminalStatus::NegativeInput
+        );
+        assert_eq!(
+            TerminalStatus::from_err(&Error::EmptyMerkleTreeErr),
+            TerminalStatus::MerkleError
+        );
+        assert_eq!(
+            TerminalStatus::from_err(&Error::CancelledErr),
+            TerminalStatus::Cancelled
+        );
+        assert_eq!(TerminalStatus::MerkleError.to_string(), "MerkleError");
+
+        let (a, b) = ("0x1".to_owned(), "0x2".to_owned());
+        let statuses = vec![
+            (&a, TerminalStatus::Ok),
+            (&b, TerminalStatus::NoPackets),
+            (&a, TerminalStatus::ZkError),
+            (&b, TerminalStatus::Ok),
+            (&a, TerminalStatus::Cancelled),
+        ];
+        let merged = merge_status(statuses.iter().cloned());
+        assert_eq!(merged[&a], TerminalStatus::ZkError);
+        assert_eq!(merged[&b], TerminalStatus::NoPackets);
+        let counts = count_status(statuses.iter().map(|(_, s)| *s));
+        assert_eq!(counts[&TerminalStatus::Ok], 2);
+        assert_eq!(counts[&TerminalStatus::ZkError], 1);
+        assert_eq!(counts.get(&TerminalStatus::MerkleError), None);
+    }
+}
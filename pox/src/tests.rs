@@ -13,15 +13,20 @@ mod tests {
     use rust_decimal::{prelude::Zero, Decimal};
     use rust_decimal_macros::dec;
     use tracing::{debug, info};
-    use types::{Alpha, CompletePackets, EndPointFrom, Packet, Pos2D, Remote};
+    use types::{
+        Alpha, Bandwidth, CompletePackets, EndPointFrom, Packet, Pos2D, Remote, ServiceQuality,
+    };
     use util::{compressor::BrotliCompressor, serde_bin::SerdeBinTrait};
     use zkt::ZkTraitHalo2;
 
     use crate::{
-        Gaussian, GaussianTaylor, Kernel, KernelKind, PoDRemoteResult, PoDTerminalResult,
-        PoFRemoteResult, PoFVerify, PoX, PosTrait,
+        eval_range, pod_statement, AnomalyKind, CancellationToken, Gaussian, GaussianTaylor,
+        Kernel, KernelKind, PoBRemoteResult, PoBVerify, PoDRangeResult, PoDRemoteResult,
+        PoDTerminalResult, PoDVerify, PoFRangeResult, PoFRemoteResult, PoFVerify, PoX, PosTrait,
+        TerminalAnomaly, TerminalStatus,
     };
 
+    #[derive(Clone)]
     struct TestZK {}
     impl ZkTraitHalo2 for TestZK {
         type F = Fp;
@@ -33,11 +38,130 @@ mod tests {
             Ok((Vec::new(), Vec::new()))
         }
 
-        fn verify_proof(_out: Vec<u8>, _proof: Vec<u8>) -> bool {
-            true
+        fn verify_proof(_out: Vec<u8>, _proof: Vec<u8>) -> Result<(), zkt::traits::Error> {
+            Ok(())
+        }
+
+        fn gen_pod_proof(
+            &self,
+            _params: &zkt::PoDParams,
+            _statement: &zkt::Statement,
+            _pos: &zkt::Point,
+            _rspr: &BigInt,
+            _neighbours: &[zkt::Neighbour],
+        ) -> Result<(Vec<u8>, Vec<u8>), zkt::traits::Error> {
+            Ok((Vec::new(), Vec::new()))
+        }
+
+        fn verify_pod_proof(
+            _params: &zkt::PoDParams,
+            _max_k: u32,
+            _statement: &zkt::Statement,
+            _commitment: &Fp,
+            _out: Vec<u8>,
+            _proof: Vec<u8>,
+        ) -> Result<zkt::PoDPublicOutput, zkt::traits::Error> {
+            Err(zkt::traits::Error::Verification("no proof".to_owned()))
+        }
+
+        fn gen_batch_pod_proof(
+            &self,
+            _params: &zkt::PoDParams,
+            _statement: &zkt::Statement,
+            _terminals: &[zkt::PoDTerminal],
+        ) -> Result<(Vec<u8>, Vec<u8>), zkt::traits::Error> {
+            Ok((Vec::new(), Vec::new()))
+        }
+
+        fn verify_batch_pod_proof(
+            _params: &zkt::PoDParams,
+            _max_k: u32,
+            _statement: &zkt::Statement,
+            _commitments: &[Fp],
+            _out: Vec<u8>,
+            _proof: Vec<u8>,
+        ) -> Result<zkt::PoDBatchPublicOutput, zkt::traits::Error> {
+            Err(zkt::traits::Error::Verification("no proof".to_owned()))
+        }
+
+        fn setup(&self) -> Result<(), zkt::traits::Error> {
+            Ok(())
+        }
+    }
+    // cancels its token once the aggregated proof is asked for, and fails it
+    #[derive(Clone)]
+    struct CancellingZK(CancellationToken);
+    impl ZkTraitHalo2 for CancellingZK {
+        type F = Fp;
+        fn gen_proof(
+            &self,
+            coefs: Vec<Fp>,
+            x: Vec<Fp>,
+        ) -> Result<(Vec<u8>, Vec<u8>), zkt::traits::Error> {
+            TestZK {}.gen_proof(coefs, x)
+        }
+
+        fn verify_proof(out: Vec<u8>, proof: Vec<u8>) -> Result<(), zkt::traits::Error> {
+            TestZK::verify_proof(out, proof)
+        }
+
+        fn gen_pod_proof(
+            &self,
+            params: &zkt::PoDParams,
+            statement: &zkt::Statement,
+            pos: &zkt::Point,
+            rspr: &BigInt,
+            neighbours: &[zkt::Neighbour],
+        ) -> Result<(Vec<u8>, Vec<u8>), zkt::traits::Error> {
+            TestZK {}.gen_pod_proof(params, statement, pos, rspr, neighbours)
         }
 
-        fn setup() {}
+        fn verify_pod_proof(
+            params: &zkt::PoDParams,
+            max_k: u32,
+            statement: &zkt::Statement,
+            commitment: &Fp,
+            out: Vec<u8>,
+            proof: Vec<u8>,
+        ) -> Result<zkt::PoDPublicOutput, zkt::traits::Error> {
+            TestZK::verify_pod_proof(params, max_k, statement, commitment, out, proof)
+        }
+
+        fn gen_batch_pod_proof(
+            &self,
+            _params: &zkt::PoDParams,
+            _statement: &zkt::Statement,
+            _terminals: &[zkt::PoDTerminal],
+        ) -> Result<(Vec<u8>, Vec<u8>), zkt::traits::Error> {
+            self.0.cancel();
+            Err(zkt::traits::Error::Proving("cancelled".to_owned()))
+        }
+
+        fn verify_batch_pod_proof(
+            params: &zkt::PoDParams,
+            max_k: u32,
+            statement: &zkt::Statement,
+            commitments: &[Fp],
+            out: Vec<u8>,
+            proof: Vec<u8>,
+        ) -> Result<zkt::PoDBatchPublicOutput, zkt::traits::Error> {
+            TestZK::verify_batch_pod_proof(params, max_k, statement, commitments, out, proof)
+        }
+    }
+    fn statement(cfg: &PoxConfig) -> zkt::Statement {
+        pod_statement(cfg, "0x0", 1, 10, 20).unwrap()
+    }
+    #[test]
+    fn test_pod_statement() {
+        let cfg = PoxConfig::default();
+        let expected = statement(&cfg);
+        // settings of the node do not change the statement, the ones of the results do
+        let mut other = cfg.clone();
+        other.rayon_num_threads = 4;
+        other.zk.aggregate = false;
+        assert_eq!(expected, statement(&other));
+        other.penalty.max_diff = dec!(11);
+        assert_ne!(expected.config, statement(&other).config);
     }
     /*
            Y
@@ -91,14 +215,11 @@ mod tests {
        1: (7*(-70)+8*(-80)+3*(-40))/(7+8+3)= -490-640-120/18 = -1250/18 = 69.4444
        2: (4*(-70)+3*(-80)+8*(-40))/(4+3+8)= -280-240-320/15 = -840/15 = 56
     */
-    #[test]
-    fn test_pod() {
-        let _guard = init_logger_for_test!();
-        use crate::PoX;
-        use config::PoxConfig;
-        use types::{Alpha, FixedPoint, Remote};
-        let mut cfg = PoxConfig {
+    // the quadratic kernel of the figure above, with max_dis_sqr 25 and max_diff 20
+    fn pod_cfg() -> PoxConfig {
+        PoxConfig {
             rayon_num_threads: 0,
+            request_num_threads: 0,
             kernel: KernelConfig {
                 quadratic: QuadraticConfig {
                     max_dis_sqr: dec!(25),
@@ -113,14 +234,32 @@ mod tests {
                 },
                 kernel_type: KernelTypeConfig::Quadratic,
             },
-            penalty: PenaltyConfig { max_diff: dec!(20) },
+            penalty: PenaltyConfig {
+                max_diff: dec!(20),
+                ..Default::default()
+            },
             rspr_precision_bigint: 4,
             coordinate_precision_bigint: 3,
+            zk: ZkConfig {
+                max_k: 14,
+                aggregate: true,
+                debug: false,
+            },
+            block_aggregation: BlockAggregationConfig::TimeWeighted,
+            anomaly: AnomalyConfig::default(),
+            path_loss: PathLossConfig::default(),
+            orbit: OrbitConfig::default(),
+            service_quality: ServiceQualityConfig::default(),
+            pof: PoFConfig::default(),
+            pob: PoBConfig::default(),
             pod_max_value: dec!(-100),
-        };
-        let remote = Remote::<Decimal> {
+        }
+    }
+    fn pod_remote() -> Remote<Decimal> {
+        Remote::<Decimal> {
             terminals: vec![
                 types::Terminal {
+                    service: None,
                     address: "0x1".to_string(),
                     alpha: Alpha { rspr: dec!(-70) },
                     terminal_packets: None,
@@ -130,6 +269,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x2".to_string(),
                     alpha: Alpha { rspr: dec!(-80) },
                     terminal_packets: None,
@@ -139,6 +279,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x3".to_string(),
                     alpha: Alpha { rspr: dec!(-40) },
                     terminal_packets: None,
@@ -148,6 +289,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x4".to_string(),
                     alpha: Alpha { rspr: dec!(-60) },
                     terminal_packets: None,
@@ -157,6 +299,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x5".to_string(),
                     alpha: Alpha { rspr: dec!(-50) },
                     terminal_packets: None,
@@ -167,6 +310,7 @@ mod tests {
                 },
             ],
             remote_packets: None,
+            bandwidth: None,
             epoch: 1,
             address: "0x123456".to_string(),
             position: types::Pos3D {
@@ -174,7 +318,15 @@ mod tests {
                 y: dec!(0),
                 height: dec!(10000),
             },
-        };
+        }
+    }
+    #[test]
+    fn test_pod() {
+        let _guard = init_logger_for_test!();
+        use crate::PoX;
+        use types::{FixedPoint, FixedPointInteger, Remote};
+        let mut cfg = pod_cfg();
+        let remote = pod_remote();
         let remote_decimal = remote.clone();
         let remote = Remote::from_with_config(remote, &cfg).unwrap();
         let ss = remote_decimal
@@ -232,39 +384,112 @@ mod tests {
                     weight: BigInt::from(123158),
                     value_for_remote: BigInt::from(-623157),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x2".to_string(),
                     weight: BigInt::from(30233),
                     value_for_remote: BigInt::from(-630232),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x3".to_string(),
                     weight: BigInt::from(0),
                     value_for_remote: BigInt::from(-614102),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x4".to_string(),
                     weight: BigInt::from(183871),
                     value_for_remote: BigInt::from(-616129),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x5".to_string(),
                     weight: BigInt::from(100000),
                     value_for_remote: BigInt::from(-600000),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
             ],
+            proof: Some((Vec::new(), Vec::new())),
+            anomalies: Vec::new(),
         };
-        let pox = PoX::new(remote.clone(), TestZK {}, &cfg).unwrap();
+        let pox = PoX::new(remote.clone(), TestZK {}, &cfg, statement(&cfg)).unwrap();
         if let KernelKind::Quadratic(kernel) = &pox.kernel {
             assert_eq!(kernel.max_dis_sqr, BigInt::from(25_000_000));
-            assert_eq!(pox.penalty.max_diff, BigInt::from(200_000));
+            assert_eq!(pox.penalty.max_diff(), &BigInt::from(200_000));
             let pod_result = pox.eval_pod();
             assert_eq!(required_result, pod_result);
+            assert!(pod_result.check_score(&cfg).is_ok());
+            let max_weight = pox.penalty.max_diff();
+            for (i, r) in pod_result.terminal_results.iter().enumerate() {
+                assert!(pod_result.check_terminal(i, r, max_weight).is_ok());
+            }
+            // the verifier recomputes the score with its own pod_max_value
+            let mut verifier_cfg = cfg.clone();
+            verifier_cfg.pod_max_value = dec!(-90);
+            assert!(pod_result.check_score(&verifier_cfg).is_err());
+            let mut forged = pod_result.clone();
+            forged.score += 1;
+            assert!(forged.check_score(&cfg).is_err());
+            let mut forged = pod_result.clone();
+            forged.terminal_results[4].weight = max_weight + 1;
+            assert!(forged
+                .check_terminal(4, &forged.terminal_results[4], max_weight)
+                .is_err());
+            forged.terminal_results[4] = forged.terminal_results[3].clone();
+            assert_eq!(
+                forged.check_terminal(4, &forged.terminal_results[4], max_weight),
+                Err("duplicate terminal".to_owned())
+            );
+            forged.terminal_results.swap(0, 1);
+            assert!(forged
+                .check_terminal(1, &forged.terminal_results[1], max_weight)
+                .is_err());
+            // a failed terminal keeps its address and reason, but no weight
+            let mut forged = pod_result.clone();
+            forged.terminal_results[0].status = TerminalStatus::ZkError;
+            assert!(forged
+                .check_terminal(0, &forged.terminal_results[0], max_weight)
+                .is_err());
+            forged.terminal_results[0] = PoDTerminalResult::new_empty_for_err(
+                "0x1".to_owned(),
+                types::Error::ZeroKnownledgeProofErr("".to_owned()),
+            );
+            assert_eq!(forged.terminal_results[0].status, TerminalStatus::ZkError);
+            assert!(forged
+                .check_terminal(0, &forged.terminal_results[0], max_weight)
+                .is_ok());
+            let explanations = pox.explain_pod();
+            assert_eq!(explanations.len(), required_result.terminal_results.len());
+            for (e, r) in explanations.iter().zip(&required_result.terminal_results) {
+                assert_eq!(e.terminal_address, r.terminal_address);
+                assert!(!e.duplicate);
+                assert_eq!(e.weight, Some(r.weight.fixed_to_decimal(4).unwrap()));
+                assert_eq!(
+                    e.rspr_estimate,
+                    Some(r.value_for_remote.fixed_to_decimal(4).unwrap())
+                );
+            }
+            // every terminal is in the kernel support of 0x1
+            assert_eq!(explanations[0].neighbours.len(), 5);
+            let mut duplicated = remote.clone();
+            duplicated.terminals.push(remote.terminals[0].clone());
+            let pox_duplicated = PoX::new(duplicated, TestZK {}, &cfg, statement(&cfg)).unwrap();
+            let explanations = pox_duplicated.explain_pod();
+            assert_eq!(explanations.len(), 5);
+            assert_eq!(explanations[4].terminal_address, "0x1");
+            assert!(explanations[4].duplicate);
+            assert_eq!(explanations[4].weight, None);
             assert_eq!(
                 PoDRemoteResult::decompress_deserialize(
                     &pod_result
@@ -285,7 +510,7 @@ mod tests {
             panic!("KernelKind is not Quadratic")
         }
         cfg.kernel.kernel_type = KernelTypeConfig::GaussianTaylor;
-        let pox = PoX::new(remote.clone(), TestZK {}, &cfg).unwrap();
+        let pox = PoX::new(remote.clone(), TestZK {}, &cfg, statement(&cfg)).unwrap();
         if let KernelKind::GaussianTaylor(kernel) = &pox.kernel {
             assert_eq!(kernel.sigma_sqr, BigInt::from(4000000));
             assert_eq!(kernel.implement_params.max_order, 1);
@@ -342,36 +567,130 @@ mod tests {
                     weight: BigInt::from(173685),
                     value_for_remote: BigInt::from(-673684),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x2".to_string(),
                     weight: BigInt::from(94445),
                     value_for_remote: BigInt::from(-694444),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x3".to_string(),
                     weight: BigInt::from(40000),
                     value_for_remote: BigInt::from(-560000),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x4".to_string(),
                     weight: BigInt::from(200000),
                     value_for_remote: BigInt::from(-600000),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
                 PoDTerminalResult {
                     terminal_address: "0x5".to_string(),
                     weight: BigInt::from(200000),
                     value_for_remote: BigInt::from(-500000),
                     proof: (Vec::new(), Vec::new()),
+                    plausibility_percent: 100,
+                    status: TerminalStatus::Ok,
                 },
             ],
+            proof: Some((Vec::new(), Vec::new())),
+            anomalies: Vec::new(),
         };
         assert_eq!(pod_result, required_result);
     }
     #[test]
+    fn test_pod_zkt() {
+        let _guard = init_logger_for_test!();
+        // the quadratic kernel of test_pod fits in small circuits, the proofs are real ones
+        let mut cfg = pod_cfg();
+        let remote = Remote::from_with_config(pod_remote(), &cfg).unwrap();
+        for aggregate in [true, false] {
+            cfg.zk.aggregate = aggregate;
+            let zk = zkt::ZKT::new(&cfg).unwrap();
+            let pox = PoX::new(remote.clone(), zk, &cfg, statement(&cfg)).unwrap();
+            let pod_result = pox.eval_pod();
+            assert_eq!(pod_result.proof.is_some(), aggregate);
+            assert!(pod_result
+                .terminal_results
+                .iter()
+                .all(|r| r.status == TerminalStatus::Ok));
+            let verified = pod_result.verify(&cfg, &statement(&cfg), &remote);
+            assert_eq!(verified.len(), pod_result.terminal_results.len());
+            assert!(verified.iter().all(|v| *v == PoDVerify::Success));
+
+            // a weight that is not the proved one
+            let mut forged = pod_result.clone();
+            forged.terminal_results[0].weight -= 1;
+            assert!(matches!(
+                forged.verify(&cfg, &statement(&cfg), &remote)[0],
+                PoDVerify::Fail(_)
+            ));
+            // nor are the proofs valid for another epoch
+            let other_epoch = pod_statement(&cfg, "0x0", 2, 10, 20).unwrap();
+            assert!(matches!(
+                pod_result.verify(&cfg, &other_epoch, &remote)[0],
+                PoDVerify::Fail(_)
+            ));
+            // a plausibility that is not the one of the snapshot
+            let mut forged = pod_result.clone();
+            forged.terminal_results[1].plausibility_percent = 50;
+            assert!(matches!(
+                forged.verify(&cfg, &statement(&cfg), &remote)[1],
+                PoDVerify::Fail(_)
+            ));
+            // nor do the results and the anomalies fit another snapshot
+            let mut other = remote.clone();
+            other.terminals.pop();
+            assert!(matches!(
+                pod_result.verify(&cfg, &statement(&cfg), &other).last(),
+                Some(PoDVerify::Fail(_))
+            ));
+            // an errored terminal has no proof to check, only its zero weight
+            if !aggregate {
+                let mut results = pod_result.terminal_results.clone();
+                results[0] = PoDTerminalResult::new_empty_for_err(
+                    results[0].terminal_address.clone(),
+                    types::Error::ZeroKnownledgeProofErr("no proof".to_string()),
+                );
+                let mut errored = PoDRemoteResult::new_from_results(
+                    results,
+                    pod_result.anomalies.clone(),
+                    pox.pod_max_value.clone(),
+                );
+                assert!(errored
+                    .verify(&cfg, &statement(&cfg), &remote)
+                    .iter()
+                    .all(|v| *v == PoDVerify::Success));
+                errored.terminal_results[0].weight = BigInt::from(1);
+                assert!(matches!(
+                    errored.verify(&cfg, &statement(&cfg), &remote)[0],
+                    PoDVerify::Fail(_)
+                ));
+            }
+            let mut forged = pod_result.clone();
+            forged.anomalies.push(TerminalAnomaly {
+                terminal_address: "0x9".to_string(),
+                kinds: vec![AnomalyKind::Colocated],
+                excluded: false,
+                weight_percent: 100,
+            });
+            assert!(matches!(
+                forged.verify(&cfg, &statement(&cfg), &remote).last(),
+                Some(PoDVerify::Fail(_))
+            ));
+        }
+    }
+    #[test]
     // #[cfg(not(debug_assertions))]
     fn test_pod_benchmark() {
         const N: usize = 5000;
@@ -386,6 +705,7 @@ mod tests {
                     types::Terminal {
                         //random string
                         address: format!("0x{}", i),
+                        service: None,
                         alpha: Alpha { rspr: dec!(-70) },
                         terminal_packets: None,
                         position: Pos2D {
@@ -397,6 +717,7 @@ mod tests {
                 .collect(),
 
             remote_packets: None,
+            bandwidth: None,
             epoch: 1,
             address: "0x123456".to_string(),
             position: types::Pos3D {
@@ -406,7 +727,7 @@ mod tests {
             },
         };
         let remote = Remote::from_with_config(remote, &cfg).unwrap();
-        let pox = PoX::new(remote, TestZK {}, &cfg).unwrap();
+        let pox = PoX::new(remote, TestZK {}, &cfg, statement(&cfg)).unwrap();
         let pod_result = pox.eval_pod();
         assert_eq!(pod_result.terminal_results.len(), N);
     }
@@ -418,6 +739,7 @@ mod tests {
         let remote = Remote::<Decimal> {
             terminals: vec![
                 types::Terminal {
+                    service: None,
                     address: "0x1".to_string(),
                     alpha: Alpha { rspr: dec!(-70) },
                     terminal_packets: Some(types::Packets {
@@ -438,6 +760,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x2".to_string(),
                     alpha: Alpha { rspr: dec!(-80) },
                     terminal_packets: Some(types::Packets {
@@ -458,6 +781,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x3".to_string(),
                     alpha: Alpha { rspr: dec!(-40) },
                     terminal_packets: Some(types::Packets {
@@ -482,6 +806,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x4".to_string(),
                     alpha: Alpha { rspr: dec!(-60) },
                     terminal_packets: Some(types::Packets {
@@ -493,6 +818,7 @@ mod tests {
                     },
                 },
                 types::Terminal {
+                    service: None,
                     address: "0x5".to_string(),
                     alpha: Alpha { rspr: dec!(-50) },
                     terminal_packets: None,
@@ -502,22 +828,21 @@ mod tests {
                     },
                 },
             ],
-            remote_packets: Some(CompletePackets {
-                data: vec![
-                    Packet {
-                        data: "1".as_bytes().to_vec(),
-                    },
-                    Packet {
-                        data: "2".as_bytes().to_vec(),
-                    },
-                    Packet {
-                        data: "3".as_bytes().to_vec(),
-                    },
-                    Packet {
-                        data: "4".as_bytes().to_vec(),
-                    },
-                ],
-            }),
+            remote_packets: Some(CompletePackets::contiguous(vec![
+                Packet {
+                    data: "1".as_bytes().to_vec(),
+                },
+                Packet {
+                    data: "2".as_bytes().to_vec(),
+                },
+                Packet {
+                    data: "3".as_bytes().to_vec(),
+                },
+                Packet {
+                    data: "4".as_bytes().to_vec(),
+                },
+            ])),
+            bandwidth: None,
             epoch: 1,
             address: "0x123456".to_string(),
             position: types::Pos3D {
@@ -529,19 +854,87 @@ mod tests {
         let zk = TestZK {};
 
         let remote = Remote::from_with_config(remote, &cfg).unwrap();
-        let pox = PoX::new(remote, zk, &cfg).unwrap();
+        let pox = PoX::new(remote.clone(), zk.clone(), &cfg, statement(&cfg)).unwrap();
         let r = pox.eval_pof();
         // debug!("{:#?}", r);
-        let vr = r.verify();
+        let vr = r.verify(&cfg, &statement(&cfg));
         assert_eq!(vr[0], PoFVerify::Success);
         assert_eq!(vr[1], PoFVerify::Success);
         assert_eq!(vr[2], PoFVerify::Success);
         assert_eq!(vr[3], PoFVerify::Success);
+        // 0x5 has no packets, which is its status and not a failed proof
+        assert_eq!(vr[4], PoFVerify::Success);
+        assert_eq!(r.value, BigInt::from(8));
+        assert_eq!(vr.len(), 5);
+        assert!(r.terminal_results[..4]
+            .iter()
+            .all(|t| t.status == TerminalStatus::Ok));
+        assert_eq!(r.terminal_results[4].status, TerminalStatus::NoPackets);
+        assert_eq!(r.terminal_results[4].terminal_address, "0x5");
+
+        let failed = |r: &PoFRemoteResult<BigInt>, i: usize| {
+            matches!(
+                r.verify(&cfg, &statement(&cfg)).get(i),
+                Some(PoFVerify::Fail(_))
+            )
+        };
+        // more valid packets than the reference has, the value is no longer the sum either
+        let mut forged = r.clone();
+        forged.terminal_results[1].valid_packets_num += 1;
+        assert!(failed(&forged, 1));
+        assert!(failed(&forged, 5));
+        // all the packets of 0x1 claimed valid without proving anything dropped
+        let mut forged = r.clone();
+        let t = &mut forged.terminal_results[0];
+        t.valid_packets_num = BigInt::from(4);
+        t.invalid_packets_num = BigInt::zero();
+        t.proof.indices_to_prove.clear();
+        t.proof.leaves_to_prove.clear();
+        forged.value = BigInt::from(10);
         assert_eq!(
-            vr[4],
-            PoFVerify::Fail("PoF: Terminal 0x5 Empty proof".to_string())
+            forged.verify(&cfg, &statement(&cfg))[0],
+            PoFVerify::Fail(
+                "PoF: Terminal 0x1 inconsistent: no dropped packets proved, but the roots differ"
+                    .to_string()
+            )
         );
-        assert_eq!(r.value, BigInt::from(8));
+        assert_eq!(forged.verify(&cfg, &statement(&cfg)).len(), 5);
+        // proved against other packets than the rest of the terminals
+        let mut forged = r.clone();
+        forged.terminal_results[2].proof.reference_merkle_tree_root[0] ^= 1;
+        assert!(failed(&forged, 2));
+        let mut forged = r.clone();
+        forged.terminal_results[3].service_percent = 200;
+        assert!(failed(&forged, 3));
+        // a failed terminal can not keep its packets
+        let mut forged = r.clone();
+        forged.terminal_results[3].status = TerminalStatus::MerkleError;
+        assert!(failed(&forged, 3));
+        // nor claim any without them
+        let mut forged = r.clone();
+        forged.terminal_results[4].valid_packets_num = BigInt::from(4);
+        forged.value += 4;
+        assert!(failed(&forged, 4));
+        forged.terminal_results[4].proof.total_leaves_count = 4;
+        assert_eq!(
+            forged.verify(&cfg, &statement(&cfg))[4],
+            PoFVerify::Fail(
+                "PoF: Terminal 0x5 inconsistent: status NoPackets with 4 packets".to_string()
+            )
+        );
+        let mut forged = r.clone();
+        forged.terminal_results[4].invalid_packets_num = BigInt::from(-1);
+        assert!(failed(&forged, 4));
+        let mut forged = r.clone();
+        forged.value += 1;
+        assert!(forged.verify(&cfg, &statement(&cfg))[..5]
+            .iter()
+            .all(|v| *v == PoFVerify::Success));
+        assert_eq!(
+            forged.verify(&cfg, &statement(&cfg))[5],
+            PoFVerify::Fail("PoF: value 9 is not the sum 8 of the terminals".to_string())
+        );
+
         assert_eq!(
             r,
             PoFRemoteResult::decompress_deserialize(
@@ -551,6 +944,378 @@ mod tests {
             )
             .unwrap()
         );
+
+        // every packet challenged, the sample is the whole tree
+        let mut sampled_cfg = cfg.clone();
+        sampled_cfg.pof.mode = PoFModeConfig::Sampled;
+        let pox = PoX::new(
+            remote.clone(),
+            zk.clone(),
+            &sampled_cfg,
+            statement(&sampled_cfg),
+        )
+        .unwrap();
+        let sampled = pox.eval_pof();
+        assert_eq!(sampled.value, r.value);
+        let vr = sampled.verify(&sampled_cfg, &statement(&sampled_cfg));
+        assert!(vr[..4].iter().all(|v| *v == PoFVerify::Success));
+        assert_eq!(vr.len(), 5);
+        assert!(matches!(
+            sampled.verify(&cfg, &statement(&cfg))[0],
+            PoFVerify::Fail(_)
+        ));
+        // 2 of the 4 packets challenged, the valid packets are a lower bound
+        sampled_cfg.pof.sample_count = 2;
+        let pox = PoX::new(remote, zk, &sampled_cfg, statement(&sampled_cfg)).unwrap();
+        let sampled = pox.eval_pof();
+        assert!(sampled.value < r.value);
+        let t = &sampled.terminal_results[2];
+        assert_eq!(t.proof.indices_to_prove.len(), 2);
+        assert_eq!(t.sample.as_ref().unwrap().dropped, 0);
+        let vr = sampled.verify(&sampled_cfg, &statement(&sampled_cfg));
+        assert!(vr[..4].iter().all(|v| *v == PoFVerify::Success));
+        // claims to have dropped nothing at the challenged packets
+        let mut forged = sampled.clone();
+        forged.terminal_results[3].sample.as_mut().unwrap().dropped = 0;
+        assert!(matches!(
+            forged.verify(&sampled_cfg, &statement(&sampled_cfg))[3],
+            PoFVerify::Fail(_)
+        ));
+        // opens the packets it was not challenged on
+        let mut forged = sampled.clone();
+        let proof = &mut forged.terminal_results[1].proof;
+        proof.indices_to_prove = (0..4)
+            .filter(|i| !proof.indices_to_prove.contains(i))
+            .collect();
+        assert!(matches!(
+            forged.verify(&sampled_cfg, &statement(&sampled_cfg))[1],
+            PoFVerify::Fail(_)
+        ));
+    }
+    #[test]
+    fn test_pof_with_gaps() {
+        let _guard = init_logger_for_test!();
+        let cfg = PoxConfig::default();
+        let packet = |s: &str| Packet {
+            data: s.as_bytes().to_vec(),
+        };
+        // by sequence number, the remote lost the row of sequence 2
+        let terminal = |address: &str, x, data: Vec<Option<&str>>| types::Terminal {
+            address: address.to_string(),
+            service: None,
+            alpha: Alpha { rspr: dec!(-70) },
+            terminal_packets: Some(types::Packets {
+                data: data.into_iter().map(|p| p.map(packet)).collect(),
+            }),
+            position: Pos2D { x, y: dec!(0) },
+        };
+        let remote = Remote::<Decimal> {
+            terminals: vec![
+                terminal("0x1", dec!(0), vec![Some("0"), None, None, Some("3")]),
+                terminal("0x2", dec!(1), vec![Some("0"), Some("1"), None, Some("3")]),
+                terminal("0x3", dec!(2), vec![Some("0"), Some("1")]),
+            ],
+            remote_packets: Some(CompletePackets::from_sequenced(vec![
+                (3, packet("3")),
+                (0, packet("0")),
+                (1, packet("1")),
+            ])),
+            bandwidth: None,
+            epoch: 1,
+            address: "0x123456".to_string(),
+            position: types::Pos3D {
+                x: dec!(0),
+                y: dec!(0),
+                height: dec!(10000),
+            },
+        };
+        let remote = Remote::from_with_config(remote, &cfg).unwrap();
+        let pox = PoX::new(remote, TestZK {}, &cfg, statement(&cfg)).unwrap();
+        let r = pox.eval_pof();
+        assert_eq!(r.coverage.attested, 3);
+        assert_eq!(r.coverage.span, 4);
+        assert_eq!(r.coverage.percent(), 75);
+        // the gap is neither valid nor dropped, 0x3 misses the last packet
+        let counts: Vec<(BigInt, BigInt)> = r
+            .terminal_results
+            .iter()
+            .map(|t| (t.valid_packets_num.clone(), t.invalid_packets_num.clone()))
+            .collect();
+        assert_eq!(
+            counts,
+            [(2, 1), (3, 0), (2, 1)]
+                .map(|(v, i)| (BigInt::from(v), BigInt::from(i)))
+                .to_vec()
+        );
+        let vr = r.verify(&cfg, &statement(&cfg));
+        assert!(vr.iter().all(|v| *v == PoFVerify::Success));
+
+        let mut forged = r.clone();
+        forged.coverage.attested = 4;
+        assert!(matches!(
+            forged.verify(&cfg, &statement(&cfg)).last(),
+            Some(PoFVerify::Fail(_))
+        ));
+    }
+    #[test]
+    fn test_pob() {
+        let _guard = init_logger_for_test!();
+        let cfg = PoxConfig::default();
+        let terminal = |address: &str, x, net_traffic| types::Terminal {
+            address: address.to_string(),
+            service: Some(ServiceQuality {
+                connect_time: 0,
+                disconnect_time: None,
+                net_latency: 30,
+                net_bandwidth: 100,
+                net_traffic,
+            }),
+            alpha: Alpha { rspr: dec!(-70) },
+            terminal_packets: None,
+            position: Pos2D { x, y: dec!(0) },
+        };
+        let remote = |ground| Remote::<Decimal> {
+            terminals: vec![
+                terminal("0x1", dec!(0), 300),
+                terminal("0x2", dec!(1), 500),
+                terminal("0x3", dec!(2), 200),
+            ],
+            remote_packets: None,
+            bandwidth: Some(Bandwidth { ground, space: 0 }),
+            epoch: 1,
+            address: "0x123456".to_string(),
+            position: types::Pos3D {
+                x: dec!(0),
+                y: dec!(0),
+                height: dec!(10000),
+            },
+        };
+        let eval = |ground| {
+            let remote = Remote::from_with_config(remote(ground), &cfg).unwrap();
+            PoX::new(remote, TestZK {}, &cfg, statement(&cfg))
+                .unwrap()
+                .eval_pob()
+        };
+        let r = eval(800);
+        assert_eq!(r.observed, BigInt::from(1000));
+        assert_eq!(r.value, BigInt::from(800));
+        assert!(r.verify(&cfg).iter().all(|v| *v == PoBVerify::Success));
+        assert_eq!(
+            r,
+            PoBRemoteResult::decompress_deserialize(
+                &r.serialize_compress::<BrotliCompressor>(&CompressorConfig::default())
+                    .unwrap(),
+                &CompressorConfig::default()
+            )
+            .unwrap()
+        );
+        // claimed far beyond the traffic
+        assert_eq!(eval(2000).value, BigInt::zero());
+
+        // a terminal claiming more traffic than committed
+        let mut forged = r.clone();
+        forged.terminal_results[1].traffic = BigInt::from(600);
+        forged.observed = BigInt::from(1100);
+        let verified = forged.verify(&cfg);
+        assert!(matches!(verified[1], PoBVerify::Fail(_)));
+        assert_eq!(verified[0], PoBVerify::Success);
+        // a value that does not follow from the terminals
+        let mut forged = r.clone();
+        forged.value = BigInt::from(1000);
+        assert!(matches!(
+            forged.verify(&cfg).last(),
+            Some(PoBVerify::Fail(_))
+        ));
+    }
+    // three terminals in a row, in fixed point
+    fn line_remote(cfg: &PoxConfig) -> Remote<BigInt> {
+        let remote = Remote::<Decimal> {
+            terminals: (0..3)
+                .map(|i| types::Terminal {
+                    address: format!("0x{}", i),
+                    service: None,
+                    alpha: Alpha { rspr: dec!(-70) },
+                    terminal_packets: None,
+                    position: Pos2D {
+                        x: Decimal::from(i),
+                        y: dec!(0),
+                    },
+                })
+                .collect(),
+            remote_packets: None,
+            bandwidth: None,
+            epoch: 1,
+            address: "0x123456".to_string(),
+            position: types::Pos3D {
+                x: dec!(0),
+                y: dec!(0),
+                height: dec!(10000),
+            },
+        };
+        Remote::from_with_config(remote, cfg).unwrap()
+    }
+    #[test]
+    fn test_cancelled_eval() {
+        let _guard = init_logger_for_test!();
+        let cfg = PoxConfig::default();
+        let remote = line_remote(&cfg);
+        let cancel = CancellationToken::new();
+        let pox = PoX::new(remote.clone(), TestZK {}, &cfg, statement(&cfg))
+            .unwrap()
+            .with_cancellation(cancel.clone());
+        assert!(pox
+            .eval_pod()
+            .terminal_results
+            .iter()
+            .all(|r| r.weight > BigInt::zero()));
+
+        cancel.cancel();
+        // the terminals are skipped
+        assert!(pox
+            .eval_pod()
+            .terminal_results
+            .iter()
+            .all(|r| r.weight.is_zero()));
+        assert!(matches!(
+            eval_range(
+                vec![(15, remote.clone())],
+                None,
+                TestZK {},
+                &cfg,
+                &statement(&cfg),
+                &cancel
+            ),
+            Err(types::Error::CancelledErr)
+        ));
+
+        // cancelled while proving, none of the terminals is left Ok without a proof
+        let cancel = CancellationToken::new();
+        let pox = PoX::new(remote, CancellingZK(cancel.clone()), &cfg, statement(&cfg))
+            .unwrap()
+            .with_cancellation(cancel.clone());
+        let r = pox.eval_pod();
+        assert!(cancel.is_cancelled());
+        assert!(r
+            .terminal_results
+            .iter()
+            .all(|t| t.status == TerminalStatus::Cancelled && t.weight.is_zero()));
+    }
+    #[test]
+    fn test_range_missing_block() {
+        let _guard = init_logger_for_test!();
+        let cfg = PoxConfig::default();
+        let snapshots: Vec<_> = [11, 14, 17].map(|h| (h, line_remote(&cfg))).into();
+        let (pod, pof) = eval_range(
+            snapshots.clone(),
+            None,
+            TestZK {},
+            &cfg,
+            &statement(&cfg),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        // a result without the block of 14, aggregated over the others
+        let mut blocks = pod.blocks.clone();
+        blocks.remove(1);
+        let forged = PoDRangeResult::new(&cfg, &statement(&cfg), blocks, Vec::new());
+        let vr = forged.verify(&cfg, &statement(&cfg), &snapshots);
+        assert!(vr.contains(&PoDVerify::Fail(
+            "PoD: block 14 verify failed: no block for the snapshot".to_owned()
+        )));
+        assert!(matches!(vr.last(), Some(PoDVerify::Fail(e)) if e.contains("range verify failed")));
+        // the snapshots outside the range are not expected
+        let mut outside = snapshots.clone();
+        outside.push((25, line_remote(&cfg)));
+        assert_eq!(
+            pod.verify(&cfg, &statement(&cfg), &outside).len(),
+            pod.verify(&cfg, &statement(&cfg), &snapshots).len()
+        );
+
+        let heights = [11, 14, 17];
+        assert!(pof
+            .verify(&cfg, &statement(&cfg), &heights)
+            .iter()
+            .all(|v| *v == PoFVerify::Success));
+        let mut blocks = pof.blocks.clone();
+        blocks.remove(1);
+        let forged = PoFRangeResult::new(&cfg, &statement(&cfg), blocks);
+        let vr = forged.verify(&cfg, &statement(&cfg), &heights);
+        assert!(vr.contains(&PoFVerify::Fail(
+            "PoF: block 14 verify failed: no block for the snapshot".to_owned()
+        )));
+        assert!(matches!(vr.last(), Some(PoFVerify::Fail(e)) if e.contains("range verify failed")));
+        // the heights of the result itself are not enough
+        assert!(forged
+            .verify(&cfg, &statement(&cfg), &[11, 17])
+            .iter()
+            .all(|v| *v == PoFVerify::Success));
+    }
+    #[test]
+    fn test_penalty_without_circuit() {
+        let mut cfg = PoxConfig::default();
+        let remote = Remote::<Decimal> {
+            terminals: vec![],
+            remote_packets: None,
+            bandwidth: None,
+            epoch: 1,
+            address: "0x123456".to_string(),
+            position: types::Pos3D {
+                x: dec!(0),
+                y: dec!(0),
+                height: dec!(10000),
+            },
+        };
+        let remote = Remote::from_with_config(remote, &cfg).unwrap();
+        assert!(PoX::new(remote.clone(), TestZK {}, &cfg, statement(&cfg)).is_ok());
+        let scored = line_remote(&cfg);
+        // the circuit proves the linear penalty only, the others are scored unproven
+        for penalty_type in [
+            PenaltyTypeConfig::Quadratic,
+            PenaltyTypeConfig::ExponentialDecay,
+            PenaltyTypeConfig::Huber,
+            PenaltyTypeConfig::Step,
+        ] {
+            cfg.penalty.penalty_type = penalty_type;
+            cfg.penalty.unproven = false;
+            assert!(matches!(
+                PoX::new(remote.clone(), TestZK {}, &cfg, statement(&cfg)),
+                Err(types::Error::ZeroKnownledgeProofErr(_))
+            ));
+            cfg.penalty.unproven = true;
+            let r = PoX::new(scored.clone(), TestZK {}, &cfg, statement(&cfg))
+                .unwrap()
+                .eval_pod();
+            assert!(r.terminal_results.iter().all(|t| t.status.is_ok()
+                && t.weight > BigInt::zero()
+                && t.proof == (vec![], vec![])));
+            assert!(r
+                .verify(&cfg, &statement(&cfg), &scored)
+                .iter()
+                .all(|v| *v == PoDVerify::Success));
+            // the weights are recomputed from the snapshot
+            let mut forged = r.clone();
+            forged.terminal_results[1].weight -= 1;
+            assert!(matches!(
+                forged.verify(&cfg, &statement(&cfg), &scored)[1],
+                PoDVerify::Fail(_)
+            ));
+            let mut forged = r.clone();
+            forged.terminal_results[1].proof = (vec![1], vec![1]);
+            assert_eq!(
+                forged.verify(&cfg, &statement(&cfg), &scored)[1],
+                PoDVerify::Fail(
+                    "PoD: Terminal 0x1 verify failed: proof in the unproven mode".to_owned()
+                )
+            );
+            // nor is an unproven result accepted where proofs are expected
+            let mut proven = cfg.clone();
+            proven.penalty.penalty_type = PenaltyTypeConfig::Linear;
+            proven.penalty.unproven = false;
+            assert!(matches!(
+                r.verify(&proven, &statement(&proven), &scored)[0],
+                PoDVerify::Fail(_)
+            ));
+        }
     }
     ///
     /// cargo test --package pox --lib --release -- tests::tests::test_pof_benchmark --exact --show-output
@@ -566,6 +1331,7 @@ mod tests {
                 .par_iter()
                 // .progress_count(N as u64)
                 .map(|i| types::Terminal {
+                    service: None,
                     address: format!("0x{}", i),
                     alpha: Alpha { rspr: dec!(-70) },
                     terminal_packets: Some(types::Packets {
@@ -590,14 +1356,13 @@ mod tests {
                 })
                 .collect(),
 
-            remote_packets: Some(CompletePackets {
-                data: vec![
-                    Packet {
-                        data: "1".as_bytes().to_vec(),
-                    };
-                    PSIZE
-                ],
-            }),
+            remote_packets: Some(CompletePackets::contiguous(vec![
+                Packet {
+                    data: "1".as_bytes().to_vec(),
+                };
+                PSIZE
+            ])),
+            bandwidth: None,
             epoch: 1,
             address: "0x123456".to_string(),
             position: types::Pos3D {
@@ -609,10 +1374,10 @@ mod tests {
         let zk = TestZK {};
 
         let remote = Remote::from_with_config(remote, &cfg).unwrap();
-        let pox = PoX::new(remote, zk, &cfg).unwrap();
+        let pox = PoX::new(remote, zk, &cfg, statement(&cfg)).unwrap();
         let r = pox.eval_pof();
         // debug!("{:#?}", r);
-        let vr = r.verify();
+        let vr = r.verify(&cfg, &statement(&cfg));
         assert_eq!(vr[0], PoFVerify::Success);
         assert_eq!(vr[1], PoFVerify::Success);
         assert_eq!(vr[2], PoFVerify::Success);
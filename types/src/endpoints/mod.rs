@@ -6,6 +6,8 @@ pub use remote::*;
 
 mod packets;
 pub use packets::*;
+mod orbit;
+pub use orbit::*;
 
 use crate::Error;
 
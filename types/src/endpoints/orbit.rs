@@ -0,0 +1,24 @@
+/// Two-line element set of a remote, line0 is the name of the object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tle {
+    pub line0: String,
+    pub line1: String,
+    pub line2: String,
+}
+/// Position and speed a remote reported at a block, longitude and latitude in degrees,
+/// height in km and speed in km/s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteTrack {
+    pub block_height: u64,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub height: f64,
+    pub speed: f64,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteOrbit {
+    pub address: String,
+    pub tle: Tle,
+    // sorted by block height
+    pub tracks: Vec<RemoteTrack>,
+}
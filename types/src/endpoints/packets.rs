@@ -21,9 +21,79 @@ pub struct Packets {
     // data must be sorted by seq and must be continuous
     pub data: Vec<Option<Packet>>,
 }
+/// Sequence numbers from start to end, end excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceRange {
+    pub start: u64,
+    pub end: u64,
+}
 #[derive(Debug, Clone)]
 pub struct CompletePackets {
+    // the packets of the ranges in sequence order, leaf i of the merkle tree is the i-th
+    // sequence number of the ranges
     pub data: Vec<Packet>,
+    // sorted and disjoint, the sequence numbers the remote attests to
+    pub ranges: Vec<SequenceRange>,
+}
+impl CompletePackets {
+    /// Packets with the sequence numbers from 0, without gaps.
+    pub fn contiguous(data: Vec<Packet>) -> Self {
+        let ranges = if data.is_empty() {
+            Vec::new()
+        } else {
+            vec![SequenceRange {
+                start: 0,
+                end: data.len() as u64,
+            }]
+        };
+        Self { data, ranges }
+    }
+    /// Packets in any order, with gaps between the sequence numbers. The first packet of a
+    /// sequence number reported more than once is kept.
+    pub fn from_sequenced(mut packets: Vec<(u64, Packet)>) -> Self {
+        packets.sort_by_key(|(sequence, _)| *sequence);
+        packets.dedup_by_key(|(sequence, _)| *sequence);
+        let mut ranges: Vec<SequenceRange> = Vec::new();
+        for (sequence, _) in &packets {
+            match ranges.last_mut() {
+                Some(range) if range.end == *sequence => range.end += 1,
+                _ => ranges.push(SequenceRange {
+                    start: *sequence,
+                    end: sequence + 1,
+                }),
+            }
+        }
+        Self {
+            data: packets.into_iter().map(|(_, packet)| packet).collect(),
+            ranges,
+        }
+    }
+    /// Attested sequence numbers in leaf order.
+    pub fn sequences(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ranges.iter().flat_map(|range| range.start..range.end)
+    }
+    pub fn attests(&self, sequence: u64) -> bool {
+        let i = self.ranges.partition_point(|range| range.end <= sequence);
+        self.ranges
+            .get(i)
+            .is_some_and(|range| range.start <= sequence)
+    }
+    /// Sequence numbers from 0 to the last attested one.
+    pub fn span(&self) -> u64 {
+        self.ranges.last().map_or(0, |range| range.end)
+    }
+    /// The ranges are sorted, disjoint and hold as many sequence numbers as there are
+    /// packets.
+    pub fn is_consistent(&self) -> bool {
+        self.ranges.iter().all(|range| range.start < range.end)
+            && self.ranges.windows(2).all(|w| w[0].end < w[1].start)
+            && self
+                .ranges
+                .iter()
+                .map(|range| range.end - range.start)
+                .sum::<u64>()
+                == self.data.len() as u64
+    }
 }
 impl MerkleAble for CompletePackets {
     fn merkle_tree(&self) -> Result<MerkleTree<Sha256>, Error> {
@@ -38,6 +108,27 @@ impl MerkleAble for CompletePackets {
         Ok(MerkleTree::<Sha256>::from_leaves(&leaves))
     }
 }
+impl Packets {
+    /// Merkle tree of the terminal keyed like the one of the remote, a leaf for every
+    /// sequence number the remote attests to. A sequence number beyond the data of the
+    /// terminal counts as dropped.
+    pub fn merkle_tree_over(
+        &self,
+        reference: &CompletePackets,
+    ) -> Result<MerkleTree<Sha256>, Error> {
+        if reference.data.is_empty() {
+            return Err(Error::EmptyMerkleTreeErr);
+        }
+        let leaves = reference
+            .sequences()
+            .map(|sequence| match self.data.get(sequence as usize) {
+                Some(Some(x)) => Sha256::hash(x.data.as_slice()),
+                _ => Sha256::hash(&[]),
+            })
+            .collect::<Vec<_>>();
+        Ok(MerkleTree::<Sha256>::from_leaves(&leaves))
+    }
+}
 impl MerkleAble for Packets {
     fn merkle_tree(&self) -> Result<MerkleTree<Sha256>, Error> {
         if self.data.len() == 0 {
@@ -100,16 +191,14 @@ mod tests {
     }
     #[test]
     fn test_complete_packets_merkle_tree() {
-        let complete_packets = CompletePackets {
-            data: vec![
-                Packet {
-                    data: "hello".as_bytes().to_vec(),
-                },
-                Packet {
-                    data: "world".as_bytes().to_vec(),
-                },
-            ],
-        };
+        let complete_packets = CompletePackets::contiguous(vec![
+            Packet {
+                data: "hello".as_bytes().to_vec(),
+            },
+            Packet {
+                data: "world".as_bytes().to_vec(),
+            },
+        ]);
         let merkle_tree = complete_packets.merkle_tree().unwrap();
 
         //7305db9b2abccd706c256db3d97e5ff48d677cfe4d3a5904afb7da0e3950e1e2
@@ -118,4 +207,47 @@ mod tests {
             "7305db9b2abccd706c256db3d97e5ff48d677cfe4d3a5904afb7da0e3950e1e2"
         );
     }
+    #[test]
+    fn test_complete_packets_with_gaps() {
+        let packet = |s: &str| Packet::from_str(s).unwrap();
+        let packets = CompletePackets::from_sequenced(vec![
+            (5, packet("5")),
+            (0, packet("0")),
+            (2, packet("2")),
+            (1, packet("1")),
+            (2, packet("2'")),
+            (6, packet("6")),
+        ]);
+        assert_eq!(
+            packets.ranges,
+            vec![
+                SequenceRange { start: 0, end: 3 },
+                SequenceRange { start: 5, end: 7 }
+            ]
+        );
+        assert_eq!(packets.data[2], packet("2"));
+        assert!(packets.is_consistent());
+        assert_eq!(packets.sequences().collect::<Vec<_>>(), vec![0, 1, 2, 5, 6]);
+        assert!(packets.attests(5) && !packets.attests(3) && !packets.attests(7));
+        assert_eq!(packets.span(), 7);
+
+        // the terminal has every packet but 5, the gaps are not in its tree
+        let terminal = Packets {
+            data: vec![
+                Some(packet("0")),
+                Some(packet("1")),
+                Some(packet("2")),
+                None,
+                None,
+                None,
+                Some(packet("6")),
+            ],
+        };
+        let tree = terminal.merkle_tree_over(&packets).unwrap();
+        let leaves = tree.leaves().unwrap();
+        assert_eq!(leaves.len(), 5);
+        assert_eq!(leaves[3], Sha256::hash(&[]));
+        assert_eq!(leaves[4], Sha256::hash(b"6"));
+        assert!(CompletePackets::contiguous(Vec::new()).is_consistent());
+    }
 }
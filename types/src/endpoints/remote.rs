@@ -14,6 +14,16 @@ pub struct Remote<T: FixedPoint> {
     pub terminals: Vec<Terminal<T>>,
     // if option == None, it means the remote has not sent packets
     pub remote_packets: Option<CompletePackets>,
+    // None if the remote claimed no bandwidth
+    pub bandwidth: Option<Bandwidth>,
+}
+/// Bandwidth the remote claims at a block, in the units of the terminal traffic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bandwidth {
+    // to the terminals on the ground
+    pub ground: i64,
+    // to the other remotes
+    pub space: i64,
 }
 
 impl EndPointFrom<Remote<Decimal>> for Remote<BigInt> {
@@ -33,6 +43,7 @@ impl EndPointFrom<Remote<Decimal>> for Remote<BigInt> {
                 .map(|t| Terminal::<BigInt>::from_with_config(t.clone(), cfg))
                 .collect::<Result<Vec<_>, _>>()?,
             remote_packets: value.remote_packets,
+            bandwidth: value.bandwidth,
         })
     }
 }
@@ -23,9 +23,21 @@ pub struct Terminal<T: FixedPoint> {
     pub address: String,
     pub position: Pos2D<T>,
     pub alpha: Alpha<T>,
+    // None if the DA layer has no session of the terminal
+    pub service: Option<ServiceQuality>,
     // terminal may do not receive packets
     pub terminal_packets: Option<Packets>,
 }
+/// Session of the terminal with the remote, times in unix seconds and latency in ms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceQuality {
+    pub connect_time: i64,
+    // None while still connected
+    pub disconnect_time: Option<i64>,
+    pub net_latency: i32,
+    pub net_bandwidth: i32,
+    pub net_traffic: i32,
+}
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Alpha<T: FixedPoint> {
     pub rspr: T,
@@ -41,6 +53,7 @@ impl<T: FixedPoint> Terminal<T> {
             address,
             position: Pos2D::<T>::new(x, y),
             alpha,
+            service: None,
             terminal_packets: packets,
         }
     }
@@ -67,6 +80,7 @@ impl Terminal<BigInt> {
             address,
             position: Pos2D::<BigInt>::new_from_decimal(x, y, coor_exp)?,
             alpha: Alpha::<BigInt>::new_from_decimal(alpha, rspr_exp)?,
+            service: None,
             terminal_packets: packets,
         })
     }
@@ -91,6 +105,7 @@ impl Terminal<Decimal> {
             address,
             position: Pos2D::<Decimal>::new_from_f64(x, y)?,
             alpha: Alpha::<Decimal>::new_from_f64(alpha)?,
+            service: None,
             terminal_packets: packets,
         })
     }
@@ -107,6 +122,7 @@ impl EndPointFrom<Terminal<Decimal>> for Terminal<BigInt> {
             alpha: Alpha::<BigInt> {
                 rspr: BigInt::fixed_from_decimal(value.alpha.rspr, cfg.rspr_precision_bigint)?,
             },
+            service: value.service,
             terminal_packets: value.terminal_packets,
         })
     }
@@ -9,8 +9,26 @@ pub enum Error {
     NegativeSqrtErr(String),
     #[error("Error negative {0} for fp")]
     NegativeFpErr(String),
+    #[error("Error {0} out of the signed {1} bits fp range")]
+    FpOutOfRangeErr(String, u32),
     #[error("Error negative/zero sigma: {0}")]
     SigmaZeroOrNegative(String),
+    #[error("Error invalid penalty parameter: {0}")]
+    InvalidPenaltyErr(String),
+    #[error("Error invalid anomaly parameter: {0}")]
+    InvalidAnomalyErr(String),
+    #[error("Error invalid path loss parameter: {0}")]
+    InvalidPathLossErr(String),
+    #[error("Error invalid orbit: {0}")]
+    InvalidOrbitErr(String),
+    #[error("Error invalid service quality parameter: {0}")]
+    InvalidServiceQualityErr(String),
+    #[error("Error invalid PoF parameter: {0}")]
+    InvalidPoFErr(String),
+    #[error("Error evaluation cancelled")]
+    CancelledErr,
+    #[error("Error thread pool: {0}")]
+    ThreadPoolErr(String),
     #[error("Error zkp error: {0}")]
     ZeroKnownledgeProofErr(String),
     #[error("Error conversion from BigInt: {0}, error: {1}")]
@@ -23,4 +41,6 @@ pub enum Error {
     MerkleTreeErr(String),
     #[error("Empty merkle tree")]
     EmptyMerkleTreeErr,
+    #[error("Error no block snapshot in the range {0}..={1}")]
+    EmptyBlockRangeErr(u64, u64),
 }
@@ -11,7 +11,7 @@ use rust_decimal::{prelude::FromPrimitive, Decimal, MathematicalOps};
 use tracing::error;
 
 use crate::Error;
-use halo2curves::ff::Field;
+use halo2curves::ff::PrimeField;
 
 pub trait FixedPointOps<Rhs = Self, Output = Self>:
     Add<Rhs, Output = Output>
@@ -42,8 +42,26 @@ pub trait FixedPoint:
     }
     fn fixed_sqrt(&self) -> Result<Self, Error>;
 }
+/// Signed integers are encoded in Fp by field negation: v >= 0 maps to v and v < 0 to p - |v|.
+/// A value has to be in [-2^(bits - 1), 2^(bits - 1)) for a bit width bits <= FP_SIGNED_BITS,
+/// below p / 2 the encoding is one-to-one. The zkt circuits range check every signed input
+/// the same way, as v + 2^(bits - 1) in [0, 2^bits).
+pub const FP_SIGNED_BITS: u32 = 254;
+fn check_signed_bits(v: &BigInt, bits: u32) -> Result<(), Error> {
+    let bound = BigInt::one() << bits.saturating_sub(1);
+    if bits == 0 || bits > FP_SIGNED_BITS || *v < -bound.clone() || *v >= bound {
+        let e = Error::FpOutOfRangeErr(v.to_string(), bits);
+        error!("{:?}", e);
+        return Err(e);
+    }
+    Ok(())
+}
 pub trait FixedPointInteger: FixedPoint {
+    // the signed encoding with the widest bound, FP_SIGNED_BITS
     fn to_fp(&self) -> Result<Fp, Error>;
+    fn to_fp_signed(&self, bits: u32) -> Result<Fp, Error>;
+    // inverse of to_fp_signed, it fails outside of the bound
+    fn from_fp_signed(fp: &Fp, bits: u32) -> Result<Self, Error>;
     fn fixed_magnitude_to_u64(&self) -> Result<u64, Error>;
     fn fixed_log_magnitude_to_u64(&self) -> Result<u64, Error>;
     fn fixed_to_decimal(&self, exp: u32) -> Result<Decimal, Error>;
@@ -164,27 +182,32 @@ impl FixedPointInteger for BigInt {
         Ok(self.magnitude().to_string().len() as u64)
     }
     fn to_fp(&self) -> Result<Fp, Error> {
-        let (sig, mut bytes) = self.to_u64_digits();
-        if bytes.len() == 0 {
-            return Ok(Fp::zero());
-        }
-        bytes.resize(((bytes.len() - 1) / 4 + 1) * 4, 0);
-        let mut result = Fp::zero();
-        for i in 0..bytes.len() / 4 {
-            let fp = Fp::from_raw([
-                bytes[i * 4],
-                bytes[i * 4 + 1],
-                bytes[i * 4 + 2],
-                bytes[i * 4 + 3],
-            ]);
-            result += fp * Fp::from(2).pow(vec![64 * 4 * i as u64]);
-        }
-        if sig == Sign::Minus {
-            Ok(-result)
+        self.to_fp_signed(FP_SIGNED_BITS)
+    }
+    fn to_fp_signed(&self, bits: u32) -> Result<Fp, Error> {
+        check_signed_bits(self, bits)?;
+        let (sign, bytes) = self.to_bytes_le();
+        // at most 32 bytes below 2^253
+        let mut repr = [0u8; 32];
+        repr[..bytes.len()].copy_from_slice(&bytes);
+        let fp = Option::<Fp>::from(Fp::from_repr(repr)).ok_or_else(|| {
+            let e = Error::FpOutOfRangeErr(self.to_string(), bits);
+            error!("{:?}", e);
+            e
+        })?;
+        if sign == Sign::Minus {
+            Ok(-fp)
         } else {
-            Ok(result)
+            Ok(fp)
         }
     }
+    fn from_fp_signed(fp: &Fp, bits: u32) -> Result<Self, Error> {
+        let pos = BigInt::from_bytes_le(Sign::Plus, fp.to_repr().as_ref());
+        let neg = BigInt::from_bytes_le(Sign::Plus, (-*fp).to_repr().as_ref());
+        let v = if pos <= neg { pos } else { -neg };
+        check_signed_bits(&v, bits)?;
+        Ok(v)
+    }
     // limited by the size of decimal
     fn fixed_from_decimal(value: Decimal, exp: u32) -> Result<Self, Error> {
         // TODO: use shift_left instead of powi
@@ -415,9 +438,38 @@ mod tests {
         assert_eq!(Fp::one(), BigInt::from(1).to_fp().unwrap());
         assert_eq!(Fp::zero(), BigInt::from(0).to_fp().unwrap());
         assert_eq!(Fp::from(25), BigInt::from(25).to_fp().unwrap());
+        assert_eq!(-Fp::from(25), BigInt::from(-25).to_fp().unwrap());
         assert_eq!(
-            -Fp::from(52914).pow(vec![30000]),
-            -BigInt::from(52914).pow(30000_u64).to_fp().unwrap()
+            Fp::from(52914).pow(vec![16]),
+            BigInt::from(52914).pow(16_u64).to_fp().unwrap()
         );
+        // values wrapping around the modulus are rejected instead of reduced
+        assert!(matches!(
+            BigInt::from(52914).pow(30000_u64).to_fp(),
+            Err(Error::FpOutOfRangeErr(_, FP_SIGNED_BITS))
+        ));
+        let max = (BigInt::one() << (FP_SIGNED_BITS - 1)) - 1;
+        for v in [max.clone(), -max.clone() - 1, BigInt::from(-700000)] {
+            let fp = v.to_fp().unwrap();
+            assert_eq!(BigInt::from_fp_signed(&fp, FP_SIGNED_BITS).unwrap(), v);
+        }
+        assert!(matches!(
+            (max.clone() + 1).to_fp(),
+            Err(Error::FpOutOfRangeErr(_, _))
+        ));
+        assert!(matches!(
+            (-max - 2).to_fp(),
+            Err(Error::FpOutOfRangeErr(_, _))
+        ));
+
+        // a narrower bound, as the circuits check for rspr
+        assert!(BigInt::from(-128).to_fp_signed(8).is_ok());
+        assert!(BigInt::from(127).to_fp_signed(8).is_ok());
+        assert!(BigInt::from(128).to_fp_signed(8).is_err());
+        assert!(BigInt::from(-129).to_fp_signed(8).is_err());
+        assert!(BigInt::from(0).to_fp_signed(0).is_err());
+        let fp = BigInt::from(200).to_fp().unwrap();
+        assert!(BigInt::from_fp_signed(&fp, 8).is_err());
+        assert_eq!(BigInt::from_fp_signed(&fp, 9).unwrap(), BigInt::from(200));
     }
 }
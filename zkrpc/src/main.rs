@@ -10,6 +10,7 @@ use clap::{Args, Parser, Subcommand};
 use pb::zk_service_client::ZkServiceClient;
 use serde::{Deserialize, Serialize};
 use tracing::{debug_span, error, info, Instrument};
+use zkt::{ZkTraitHalo2, ZKT};
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -60,6 +61,8 @@ async fn main() -> Result<()> {
                 _ => cfg.log.log_level = LogLevel::Info,
             }
             let _guard = initialize_logger(&cfg.log);
+            // generate (or load) the zk params and keys before accepting any request
+            debug_span!("zk_setup").in_scope(|| ZKT::new(&cfg.pox)?.setup())?;
             let rpc_server = ZkRpcServer::new(&cfg)
                 .instrument(debug_span!("init_rpc"))
                 .await?;
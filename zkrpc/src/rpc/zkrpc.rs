@@ -4,14 +4,15 @@ pub mod pb {
 use config::Config;
 use da_layer::{ DaLayerTrait, MockLocalDB};
 use pb::*;
-use pox::{PoDRemoteResult, PoFRemoteResult};
+use pox::{CancellationToken, PoDRangeResult, PoFRangeResult};
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use tokio::time::{timeout, Instant};
 use tonic::{Request, Response, Status};
-use tracing::{debug, debug_span, error, info, info_span, warn, Instrument};
-use types::{EndPointFrom, Remote};
+use tracing::{debug, debug_span, error, info, info_span, warn, Instrument, Span};
+use num_bigint::BigInt;
+use types::{EndPointFrom, Remote, RemoteOrbit};
 use util::blockchain::address_brief;
 use util::compressor::BrotliCompressor;
 use util::serde_bin::SerdeBinTrait;
@@ -43,62 +44,59 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
         let zkrpc_proof = async move {
             info!(message = "!!!!!!!!!!!!!!!!!!!  Received zk proof !!!!!!!!!!!!!!!!!!!");
             let start_time = Instant::now();
-            debug!(message = "start fetching data from DA-layer");
             let remote_address = zk_request.remote_address;
             let fetch_start_time = Instant::now();
-            let mut remote = self
-                .db
-                .fetch_remote_with_terminals_block_from_to(
-                    remote_address.as_ref(),
-                    block_height_from_for_proof as u64,
-                    block_height_to_for_proof as u64,
+            let remote = self
+                .fetch_remotes(
+                    &remote_address,
+                    block_height_from_for_proof,
+                    block_height_to_for_proof,
                 )
-                .instrument(debug_span!("fetch_remote_with_terminals_block_from_to"))
-                .await
-                .map_err(|e| Status::internal(e.to_string()))?;
+                .await?;
+            let orbit = self
+                .fetch_orbit(
+                    &remote_address,
+                    block_height_from_for_proof,
+                    block_height_to_for_proof,
+                )
+                .await?;
             let fetch_time = fetch_start_time.elapsed();
+            let blocks_num = remote.len();
+            let terminals_num = remote
+                .iter()
+                .map(|(_, r)| r.terminals.len())
+                .max()
+                .unwrap_or_default();
             debug!(
-                message = "data fetched from DA-layer",
-                block_found = remote.len(),
-                ?fetch_time
-            );
-            let block_heights = remote.iter().map(|(k, _)| k).collect::<Vec<_>>();
-            debug!(block_heights = ?block_heights);
-            remote.sort_by(|a, b| a.0.cmp(&b.0));
-            if remote.is_empty() {
-                return Err(Status::data_loss("No remote found"));
-            }
-            let remote = remote[0].1.clone();
-            debug!(
-                message = "use the remote with min height, start evaluating PoD",
-                blocknum = remote.epoch,
-                terminal_num = remote.terminals.len(),
-                address = address_brief(&remote.address),
-                position = ?remote.position,
-
+                message = "start evaluating PoD and PoF of every block",
+                blocks_num,
+                terminals_num,
             );
-            let remote = Remote::from_with_config(remote, &self.cfg.pox).map_err(|e| {
-                Status::internal(format!("Error converting Remote: {}", e.to_string()))
+            let zkp = ZKT::new(&self.cfg.pox).map_err(|e| {
+                Status::internal(format!("Error creating ZKT: {}", e))
             })?;
-            let terminals_num = remote.terminals.len();
-            let zkp = ZKT {};
 
-            let pox = pox::PoX::new(remote, zkp, &self.cfg.pox)
-                .map_err(|e| Status::internal(format!("Error creating PoX: {}", e.to_string())))?;
+            let statement = pox::pod_statement(
+                &self.cfg.pox,
+                &remote_address,
+                epoch_for_proof,
+                block_height_from_for_proof,
+                block_height_to_for_proof,
+            )
+            .map_err(|e| Status::internal(format!("Error creating statement: {}", e)))?;
 
-            let pod_start_time = Instant::now();
-            let pod = pox.eval_pod();
-            let pod_time = pod_start_time.elapsed();
-            debug!(
-                message = "evaluating PoD done, start evaluating PoF ",
-                ?pod_time
-            );
-            let pof_start_time = Instant::now();
-            let pof = pox.eval_pof();
-            let pof_time = pof_start_time.elapsed();
+            let pox_start_time = Instant::now();
+            let cfg = self.cfg.pox.clone();
+            let (pod, pof) = self
+                .spawn_pox(move |cancel| {
+                    pox::eval_range(remote, orbit.as_ref(), zkp, &cfg, &statement, cancel)
+                })
+                .await
+                .map_err(|e| Status::internal(format!("Error evaluating PoX: {}", e)))?;
+            let pox_time = pox_start_time.elapsed();
             debug!(
-                message = "evaluating PoF done, start compressing PoD and PoF",
-                ?pof_time
+                message = "evaluating PoD and PoF done, start compressing PoD and PoF",
+                ?pox_time
             );
             let com_ser_start_time = Instant::now();
             let pod_s = pod
@@ -114,12 +112,16 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
             let compression_serialization_time = com_ser_start_time.elapsed();
             debug!(message="PoD and PoF compressed",compression_time=?compression_serialization_time);
             let mut pof_hashmap = HashMap::new();
-            pof.terminal_results.iter().for_each(|t| {
-                pof_hashmap.insert(
-                    t.terminal_address.clone(),
-                    t.invalid_packets_num.clone() + t.valid_packets_num.clone(),
-                );
+            pof.terminal_packets.iter().for_each(|t| {
+                pof_hashmap.insert(t.terminal_address.clone(), t.value.clone());
             });
+            let pod_status = pod.terminal_status();
+            let pof_status = pof.terminal_status();
+            let status = |m: &BTreeMap<String, pox::TerminalStatus>, address: &String| {
+                m.get(address)
+                    .map_or(pox::TerminalStatus::Ok, |s| *s)
+                    .to_string()
+            };
             let response = ZkGenProofResponse {
                 alpha_proof_merkle_root: hex::encode(pod_s),
                 beta_proof_merkle_root: hex::encode(pof_s),
@@ -136,12 +138,12 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                     ))
                 })?,
                 terminal_weights: pod
-                    .terminal_results
+                    .terminal_weights
                     .iter()
                     .map(|t| -> Result<ZkWeight, Status> {
                         Ok(ZkWeight {
                             address: t.terminal_address.clone(),
-                            alpha_weight: t.weight.to_string().parse::<u64>().map_err(|e| {
+                            alpha_weight: t.value.to_string().parse::<u64>().map_err(|e| {
                                 Status::internal(format!(
                                     "Error parsing terminal alpha_weight: {}",
                                     e.to_string()
@@ -159,19 +161,21 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                                     None => 0,
                                 }
                             },
+                            alpha_status: status(&pod_status, &t.terminal_address),
+                            beta_status: status(&pof_status, &t.terminal_address),
                         })
                     })
                     .collect::<Result<Vec<_>, Status>>()?,
             };
             let total_time = start_time.elapsed();
             info!(message="!!!!!!!!!!!!!!!!!!!   zk genproof done  !!!!!!!!!!!!!!!!!!!",
+                blocks_num,
                 terminals_num,
                 alpha_weight=?pod.score,
                 beta_weight=?pof.value, 
                 ?total_time,
                 ?fetch_time,  
-                ?pod_time, 
-                ?pof_time,
+                ?pox_time,
                 ?compression_serialization_time);
             Ok(Response::new(response))
         }
@@ -228,11 +232,11 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                     e.to_string()
                 ))
             })?;
-            let pod = PoDRemoteResult::decompress_deserialize(&pod_s, &self.cfg.compressor)
+            let pod = PoDRangeResult::decompress_deserialize(&pod_s, &self.cfg.compressor)
                 .map_err(|e| {
                     Status::internal(format!("Error deserializing PoD: {}", e.to_string()))
                 })?;
-            let pof = PoFRemoteResult::decompress_deserialize(&pof_s, &self.cfg.compressor)
+            let pof = PoFRangeResult::decompress_deserialize(&pof_s, &self.cfg.compressor)
                 .map_err(|e| {
                     Status::internal(format!("Error deserializing PoF: {}", e.to_string()))
                 })?;
@@ -241,18 +245,68 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                 message = "PoD and PoF deserialized and decompressed",
                 ?deserialization_decompression_time
             );
-            let pod_start_time = Instant::now();
-            let pod_result: Vec<pox::PoDVerify> = pod.verify();
+            let statement = pox::pod_statement(
+                &self.cfg.pox,
+                &zk_request.remote_address,
+                epoch_for_proof,
+                block_height_from_for_proof,
+                block_height_to_for_proof,
+            )
+            .map_err(|e| Status::internal(format!("Error creating statement: {}", e)))?;
+            // the verifier is trusted with the snapshots, the input commitments of the proofs,
+            // the plausibility and the anomalies are recomputed from them
+            let remotes = self
+                .fetch_remotes(
+                    &zk_request.remote_address,
+                    block_height_from_for_proof,
+                    block_height_to_for_proof,
+                )
+                .await?;
+            let heights: Vec<u64> = remotes.iter().map(|(height, _)| *height).collect();
+            let orbit = self
+                .fetch_orbit(
+                    &zk_request.remote_address,
+                    block_height_from_for_proof,
+                    block_height_to_for_proof,
+                )
+                .await?;
+            // the halo2 verification is as heavy as the evaluation, keep it off the runtime
+            let cfg = self.cfg.pox.clone();
+            let (pod, pof, pod_result, pof_result, pod_verification_time, pof_verification_time) =
+                self.spawn_pox(move |_| {
+                    let pod_start_time = Instant::now();
+                    let mut pod_result: Vec<pox::PoDVerify> =
+                        pod.verify(&cfg, &statement, &remotes);
+                    if cfg.orbit.enabled {
+                        pod_result.push(pod.verify_orbit(orbit.as_ref(), &cfg));
+                    }
+                    let pod_verification_time = pod_start_time.elapsed();
+                    let pof_start_time = Instant::now();
+                    let pof_result = pof.verify(&cfg, &statement, &heights);
+                    let pof_verification_time = pof_start_time.elapsed();
+                    Ok((
+                        pod,
+                        pof,
+                        pod_result,
+                        pof_result,
+                        pod_verification_time,
+                        pof_verification_time,
+                    ))
+                })
+                .await
+                .map_err(|e| Status::internal(format!("Error verifying PoX: {}", e)))?;
             let pod_verf = pod_result.iter().all(|x| *x == pox::PoDVerify::Success);
             let pod_success = pod_result
                 .iter()
                 .enumerate()
-                .filter_map(|(_, r)| match r {
+                .filter_map(|(i, r)| match r {
                     pox::PoDVerify::Success => Some(()),
-                    pox::PoDVerify::Fail => None,
+                    pox::PoDVerify::Fail(f) => {
+                        warn!(message = format!("Verification {} failed", i), reason = f);
+                        None
+                    }
                 })
                 .count();
-            let pod_verification_time = pod_start_time.elapsed();
             debug!(
                 message = format!(
                     "PoD Verification result {}/{}",
@@ -262,8 +316,6 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                 ?pod_verification_time
             );
 
-            let pof_start_time = Instant::now();
-            let pof_result = pof.verify();
             let pof_verf = pof_result.iter().all(|x| *x == pox::PoFVerify::Success);
             let pof_success = pof_result
                 .iter()
@@ -276,7 +328,6 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                     }
                 })
                 .count();
-            let pof_verification_time = pof_start_time.elapsed();
             debug!(
                 message = format!(
                     "PoF Verification Result {}/{}",
@@ -285,8 +336,16 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                 ),
                 ?pof_verification_time
             );
+            let (pod_status, pof_status) = (pod.status_counts(), pof.status_counts());
+            let counts = |m: &BTreeMap<pox::TerminalStatus, usize>| {
+                m.iter()
+                    .map(|(status, count)| (status.to_string(), *count as u64))
+                    .collect::<HashMap<_, _>>()
+            };
             let response = ZkVerifyProofResponse {
                 is_valid: pof_verf && pod_verf,
+                alpha_status_counts: counts(&pod_status),
+                beta_status_counts: counts(&pof_status),
             };
             let total_time = start_time.elapsed();
             info!(
@@ -294,6 +353,8 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
                 success = pof_verf && pod_verf,
                 pod_result = %format!("{}/{}", pod_success, pod_result.len()),
                 pof_result = %format!("{}/{}", pof_success, pof_result.len()),
+                ?pod_status,
+                ?pof_status,
                 ?total_time,
                 ?deserialization_decompression_time,
                 ?pod_verification_time,
@@ -322,6 +383,67 @@ impl pb::zk_service_server::ZkService for ZkRpcServer {
             }            
         }
     }
+    async fn explain_pod(
+        &self,
+        request: Request<ZkExplainPodRequest>,
+    ) -> Result<Response<ZkExplainPodResponse>, Status> {
+        let ip = request
+            .remote_addr()
+            .map_or("unknow".to_string(), |addr| addr.ip().to_string());
+        let zk_request = request.into_inner();
+        let remote_address = zk_request.remote_address.clone();
+        let epoch_for_proof = zk_request.epoch_for_proof;
+        let block_height_from_for_proof = zk_request.block_height_from_for_proof;
+        let block_height_to_for_proof = zk_request.block_height_to_for_proof;
+        let zkrpc_explain = async move {
+            info!(message = "Received PoD explanation request");
+            let start_time = Instant::now();
+            let remote = self
+                .fetch_remotes(
+                    &remote_address,
+                    block_height_from_for_proof,
+                    block_height_to_for_proof,
+                )
+                .await?;
+            let zkp = ZKT::new(&self.cfg.pox).map_err(|e| {
+                Status::internal(format!("Error creating ZKT: {}", e))
+            })?;
+            let statement = pox::pod_statement(
+                &self.cfg.pox,
+                &remote_address,
+                epoch_for_proof,
+                block_height_from_for_proof,
+                block_height_to_for_proof,
+            )
+            .map_err(|e| Status::internal(format!("Error creating statement: {}", e)))?;
+            let cfg = self.cfg.pox.clone();
+            let explanation = self
+                .spawn_pox(move |cancel| pox::explain_range(remote, zkp, &cfg, &statement, cancel))
+                .await
+                .map_err(|e| Status::internal(format!("Error explaining PoD: {}", e)))?;
+            let explanation = serde_json::to_string(&explanation).map_err(|e| {
+                Status::internal(format!("Error serializing explanation: {}", e))
+            })?;
+            info!(message = "PoD explanation done", total_time = ?start_time.elapsed());
+            Ok(Response::new(ZkExplainPodResponse { explanation }))
+        }
+        .instrument(info_span!(
+            "explain_pod",
+            ip,
+            s_addr = %address_brief(&zk_request.remote_address),
+            epoch = epoch_for_proof,
+            from = block_height_from_for_proof,
+            to = block_height_to_for_proof
+        ));
+        let zkrpc_explain = timeout(Duration::from_secs(self.cfg.rpc.timeout), zkrpc_explain).await;
+        match zkrpc_explain {
+            Ok(r) => r,
+            Err(e) => {
+                error!(message = "zkRPC Explanation Timeout", ?e);
+                Err(Status::deadline_exceeded("zkRPC Explanation Timeout"))
+            }
+        }
+    }
 }
 impl ZkRpcServer {
     pub async fn new(cfg: &Config) -> color_eyre::Result<Self> {
@@ -333,6 +455,86 @@ impl ZkRpcServer {
             cfg: cfg.clone(),
         })
     }
+    /// Block snapshots of the remote in the range, in fixed point.
+    async fn fetch_remotes(
+        &self,
+        remote_address: &str,
+        block_height_from: u64,
+        block_height_to: u64,
+    ) -> Result<Vec<(u64, Remote<BigInt>)>, Status> {
+        debug!(message = "start fetching data from DA-layer");
+        let remote = self
+            .db
+            .fetch_remote_with_terminals_block_from_to(
+                remote_address,
+                block_height_from,
+                block_height_to,
+            )
+            .instrument(debug_span!("fetch_remote_with_terminals_block_from_to"))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let block_heights = remote.iter().map(|(k, _)| k).collect::<Vec<_>>();
+        debug!(
+            message = "data fetched from DA-layer",
+            block_found = remote.len(),
+            block_heights = ?block_heights
+        );
+        if remote.is_empty() {
+            return Err(Status::data_loss("No remote found"));
+        }
+        remote
+            .into_iter()
+            .map(|(height, remote)| {
+                Remote::from_with_config(remote, &self.cfg.pox)
+                    .map(|remote| (height as u64, remote))
+                    .map_err(|e| {
+                        Status::internal(format!("Error converting Remote: {}", e))
+                    })
+            })
+            .collect()
+    }
+    /// Runs a PoX evaluation on the blocking executor, in a thread pool of its own. The
+    /// evaluation is cancelled when the returned future is dropped, on a timeout.
+    async fn spawn_pox<T, F>(&self, f: F) -> Result<T, types::Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&CancellationToken) -> Result<T, types::Error> + Send + 'static,
+    {
+        let cancel = CancellationToken::new();
+        let _guard = cancel.drop_guard();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.cfg.pox.request_num_threads)
+            .build()
+            .map_err(|e| types::Error::ThreadPoolErr(e.to_string()))?;
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _span = span.entered();
+            pool.install(|| f(&cancel))
+        })
+        .await
+        .map_err(|e| types::Error::ThreadPoolErr(e.to_string()))?
+    }
+    /// TLE and tracks of the remote in the range, only fetched if the orbit check is enabled.
+    async fn fetch_orbit(
+        &self,
+        remote_address: &str,
+        block_height_from: u64,
+        block_height_to: u64,
+    ) -> Result<Option<RemoteOrbit>, Status> {
+        if !self.cfg.pox.orbit.enabled {
+            return Ok(None);
+        }
+        let orbit = self
+            .db
+            .fetch_remote_orbit_block_from_to(remote_address, block_height_from, block_height_to)
+            .instrument(debug_span!("fetch_remote_orbit_block_from_to"))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        if orbit.is_none() {
+            warn!(message = "remote has no TLE, its blocks earn no PoD score", remote_address);
+        }
+        Ok(orbit)
+    }
     pub async fn start(&self) -> color_eyre::Result<()> {
         let t: ZkRpcServer = self.clone();
         tonic::transport::Server::builder()
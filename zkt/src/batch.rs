@@ -0,0 +1,688 @@
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    pasta::{group::ff::PrimeField, Fp},
+    plonk::{self, Circuit, ConstraintSystem},
+};
+use num_bigint::BigInt;
+
+use crate::commitment::{pod_input_commitment, PoDConfig};
+use crate::gadgets::{bigint_to_fp, fp_to_biguint};
+use crate::kernel::{KernelParams, Neighbour, Point};
+use crate::pod::{check_commitment, read_output, PoDChip, PoDParams, TerminalWitness, POD_MIN_K};
+use crate::prover::{self, ProverOptions};
+use crate::setup::keys_for;
+use crate::statement::Statement;
+use crate::traits::Error;
+use crate::MAX_K;
+
+// instance layout: | max_diff | max_dis_sqr | coefs[0] | ... | coefs[order] |
+//                  | weight_0 | value_0 | commitment_0 | ... | commitment_{n-1} |
+// n is the capacity of the circuit, the slots after the proved terminals hold padding terminals,
+// which are committed too
+const INSTANCE_PARAMS: usize = 0;
+// instance rows of each terminal slot
+const TERMINAL_INSTANCE_LEN: usize = 3;
+// | k | order | slots | len |
+const HEADER_LEN: usize = 16;
+
+/// Inputs of a terminal in the aggregated proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoDTerminal {
+    pub pos: Point,
+    pub rspr: BigInt,
+    pub neighbours: Vec<Neighbour>,
+}
+
+impl PoDTerminal {
+    /// pod_input_commitment of the terminal, the one its proof is verified against.
+    pub fn commitment(&self) -> Result<Fp, Error> {
+        pod_input_commitment(&self.pos, &self.rspr, &self.neighbours)
+    }
+}
+
+/// Weight and value_for_remote of a terminal, and the commitment of its inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoDTerminalOutput {
+    pub weight: BigInt,
+    pub value: BigInt,
+    // pod_input_commitment of the terminal
+    pub commitment: Fp,
+}
+
+/// Public output of the aggregated proof, one entry per terminal in the order they were given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoDBatchPublicOutput {
+    pub terminals: Vec<PoDTerminalOutput>,
+    pub k: u32,
+    // neighbour slots of every terminal, a power of two
+    pub slots: u32,
+    pub params: PoDParams,
+}
+
+// a terminal alone at the origin, it fills the unused slots
+fn padding_terminal() -> PoDTerminal {
+    let origin = Point {
+        x: BigInt::from(0),
+        y: BigInt::from(0),
+    };
+    PoDTerminal {
+        pos: origin.clone(),
+        rspr: BigInt::from(0),
+        neighbours: vec![Neighbour {
+            pos: origin,
+            rspr: BigInt::from(0),
+        }],
+    }
+}
+
+// output of the padding slots, the verifier does not get them from the prover
+fn padding_output(params: &PoDParams) -> Result<PoDTerminalOutput, Error> {
+    let padding = padding_terminal();
+    let (weight, value) = params.eval(&padding.pos, &padding.rspr, &padding.neighbours);
+    Ok(PoDTerminalOutput {
+        weight,
+        value,
+        commitment: padding.commitment()?,
+    })
+}
+
+#[derive(Debug, Clone)]
+struct PoDBatchCircuit {
+    chip: PoDChip,
+    terminals: Vec<TerminalWitness>,
+}
+
+impl PoDBatchCircuit {
+    /// Number of terminals with slots neighbours each that fit in a circuit with 2^k rows.
+    fn capacity(k: u32, chip: &PoDChip, slots: usize) -> usize {
+        let rows = PoDChip::usable_rows(k);
+        let gate = rows.saturating_sub(chip.params_rows()) / chip.terminal_rows(slots);
+        // the poseidon columns fill up on their own
+        gate.min(rows / PoDChip::hash_rows(slots))
+    }
+
+    fn shape(k: u32, chip: PoDChip, slots: usize) -> Self {
+        Self {
+            chip,
+            terminals: vec![TerminalWitness::unknown(slots); Self::capacity(k, &chip, slots)],
+        }
+    }
+}
+
+impl Circuit<Fp> for PoDBatchCircuit {
+    type Config = PoDConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        let slots = self.terminals.first().map_or(0, |t| t.slots());
+        Self {
+            chip: self.chip,
+            terminals: vec![TerminalWitness::unknown(slots); self.terminals.len()],
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PoDConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), plonk::Error> {
+        let PoDConfig {
+            gate: config,
+            commit,
+        } = config;
+        config.load_table(&mut layouter)?;
+        let params =
+            self.chip
+                .load_params(&config, layouter.namespace(|| "params"), INSTANCE_PARAMS)?;
+        let offset = INSTANCE_PARAMS + 3 + self.chip.kernel.order;
+        for (i, t) in self.terminals.iter().enumerate() {
+            let (weight, value, commitment) = self.chip.terminal(
+                &config,
+                &commit,
+                layouter.namespace(|| "terminal"),
+                &params,
+                t,
+            )?;
+
+            //expose public
+            let row = offset + TERMINAL_INSTANCE_LEN * i;
+            layouter.namespace(|| "expose weight").constrain_instance(
+                weight.cell(),
+                config.instance,
+                row,
+            )?;
+            layouter.namespace(|| "expose value").constrain_instance(
+                value.cell(),
+                config.instance,
+                row + 1,
+            )?;
+            layouter
+                .namespace(|| "expose commitment")
+                .constrain_instance(commitment.cell(), config.instance, row + 2)?;
+        }
+        Ok(())
+    }
+}
+
+fn circuit_id(params: &PoDParams, chip: &PoDChip, slots: usize) -> String {
+    format!(
+        "pod-batch-o{}-l{}-s{}",
+        params.kernel.order(),
+        chip.kernel.numer_limbs,
+        slots
+    )
+}
+
+/// Smallest k whose batch circuit fits len terminals.
+fn select_batch_k(len: usize, chip: &PoDChip, slots: usize, max_k: u32) -> Result<u32, Error> {
+    (POD_MIN_K..=max_k)
+        .find(|k| PoDBatchCircuit::capacity(*k, chip, slots) >= len)
+        .ok_or_else(|| Error::CircuitTooLarge {
+            len,
+            max_len: PoDBatchCircuit::capacity(max_k, chip, slots),
+            max_k,
+        })
+}
+
+impl PoDBatchPublicOutput {
+    fn instance(&self) -> Result<Vec<Fp>, Error> {
+        let chip = PoDChip::new(&self.params)?;
+        let capacity = PoDBatchCircuit::capacity(self.k, &chip, self.slots as usize);
+        if self.terminals.len() > capacity {
+            return Err(Error::InvalidPublicOutput(format!(
+                "{} terminals, at most {} fit in 2^{} rows",
+                self.terminals.len(),
+                capacity,
+                self.k
+            )));
+        }
+        let padding = padding_output(&self.params)?;
+        let mut instance = [&self.params.max_diff, &self.params.kernel.max_dis_sqr]
+            .into_iter()
+            .chain(self.params.kernel.coefs.iter())
+            .map(bigint_to_fp)
+            .collect::<Result<Vec<_>, _>>()?;
+        for t in self
+            .terminals
+            .iter()
+            .chain(std::iter::repeat(&padding))
+            .take(capacity)
+        {
+            instance.push(bigint_to_fp(&t.weight)?);
+            instance.push(bigint_to_fp(&t.value)?);
+            instance.push(t.commitment);
+        }
+        Ok(instance)
+    }
+
+    /// Layout: | k | order | slots | len (u32 le) | max_diff | max_dis_sqr | coefs |
+    /// | weight_0 | value_0 | commitment_0 | ... |, every field element takes 32 bytes
+    /// (Fp repr).
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(
+            HEADER_LEN
+                + 32 * (self.params.kernel.coefs.len()
+                    + 2
+                    + TERMINAL_INSTANCE_LEN * self.terminals.len()),
+        );
+        for v in [
+            self.k,
+            self.params.kernel.order() as u32,
+            self.slots,
+            self.terminals.len() as u32,
+        ] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [&self.params.max_diff, &self.params.kernel.max_dis_sqr]
+            .into_iter()
+            .chain(self.params.kernel.coefs.iter())
+        {
+            bytes.extend_from_slice(bigint_to_fp(v)?.to_repr().as_ref());
+        }
+        for t in &self.terminals {
+            for fp in [
+                bigint_to_fp(&t.weight)?,
+                bigint_to_fp(&t.value)?,
+                t.commitment,
+            ] {
+                bytes.extend_from_slice(fp.to_repr().as_ref());
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::InvalidPublicOutput(format!(
+                "expected at least {} bytes, got {}",
+                HEADER_LEN,
+                bytes.len()
+            )));
+        }
+        let read_u32 = |i: usize| {
+            u32::from_le_bytes([
+                bytes[4 * i],
+                bytes[4 * i + 1],
+                bytes[4 * i + 2],
+                bytes[4 * i + 3],
+            ])
+        };
+        let (k, order, slots, len) = (read_u32(0), read_u32(1) as usize, read_u32(2), read_u32(3));
+        if !(POD_MIN_K..=MAX_K).contains(&k) {
+            return Err(Error::InvalidPublicOutput(format!(
+                "unsupported circuit size k = {}",
+                k
+            )));
+        }
+        // every slots value has its own keys
+        if !slots.is_power_of_two() {
+            return Err(Error::InvalidPublicOutput(format!(
+                "neighbour slots {} is not a power of two",
+                slots
+            )));
+        }
+        let expected = (order + 3)
+            .checked_add(TERMINAL_INSTANCE_LEN * len as usize)
+            .and_then(|n| n.checked_mul(32))
+            .and_then(|n| n.checked_add(HEADER_LEN));
+        if expected != Some(bytes.len()) {
+            return Err(Error::InvalidPublicOutput(format!(
+                "unexpected {} bytes for order {} and {} terminals",
+                bytes.len(),
+                order,
+                len
+            )));
+        }
+        let fps = bytes[HEADER_LEN..]
+            .chunks(32)
+            .map(|chunk| {
+                let mut repr = [0u8; 32];
+                repr.copy_from_slice(chunk);
+                Option::<Fp>::from(Fp::from_repr(repr)).ok_or_else(|| {
+                    Error::InvalidPublicOutput(format!("non canonical field element {:?}", repr))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (params, outputs) = fps.split_at(order + 3);
+        let params: Vec<_> = params
+            .iter()
+            .map(|fp| BigInt::from(fp_to_biguint(fp)))
+            .collect();
+        let terminals = outputs
+            .chunks(TERMINAL_INSTANCE_LEN)
+            .map(|c| {
+                read_output(&c[0], &c[1]).map(|(weight, value)| PoDTerminalOutput {
+                    weight,
+                    value,
+                    commitment: c[2],
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            terminals,
+            k,
+            slots,
+            params: PoDParams {
+                max_diff: params[0].clone(),
+                kernel: KernelParams {
+                    max_dis_sqr: params[1].clone(),
+                    coefs: params[2..].to_vec(),
+                },
+            },
+        })
+    }
+}
+
+fn circuit_witness(
+    params: &PoDParams,
+    terminals: &[PoDTerminal],
+    max_k: u32,
+) -> Result<(PoDBatchCircuit, PoDBatchPublicOutput), Error> {
+    let chip = PoDChip::new(params)?;
+    let slots = terminals
+        .iter()
+        .map(|t| t.neighbours.len())
+        .max()
+        .unwrap_or(0)
+        .max(1)
+        .next_power_of_two();
+    let k = select_batch_k(terminals.len(), &chip, slots, max_k)?;
+    let capacity = PoDBatchCircuit::capacity(k, &chip, slots);
+    let padding = padding_terminal();
+    let mut witnesses = Vec::with_capacity(capacity);
+    let mut outputs = Vec::with_capacity(terminals.len());
+    for (i, t) in terminals
+        .iter()
+        .chain(std::iter::repeat(&padding))
+        .take(capacity)
+        .enumerate()
+    {
+        let (witness, weight, value) =
+            TerminalWitness::new(params, &t.pos, &t.rspr, &t.neighbours, slots)?;
+        witnesses.push(witness);
+        if i < terminals.len() {
+            outputs.push(PoDTerminalOutput {
+                weight,
+                value,
+                commitment: t.commitment()?,
+            });
+        }
+    }
+    let out = PoDBatchPublicOutput {
+        terminals: outputs,
+        k,
+        slots: slots as u32,
+        params: params.clone(),
+    };
+    let circuit = PoDBatchCircuit {
+        chip,
+        terminals: witnesses,
+    };
+    Ok((circuit, out))
+}
+
+// the statement names the proved terminals in order
+fn check_statement(statement: &Statement, len: usize) -> Result<(), Error> {
+    if statement.terminals.len() != len {
+        return Err(Error::InvalidPublicOutput(format!(
+            "statement has {} terminals, proof has {}",
+            statement.terminals.len(),
+            len
+        )));
+    }
+    Ok(())
+}
+
+/// Proves the weights and values of all the terminals of a remote in a single proof.
+pub fn gen_batch_pod_proof(
+    params: &PoDParams,
+    statement: &Statement,
+    terminals: &[PoDTerminal],
+    opts: ProverOptions,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    check_statement(statement, terminals.len())?;
+    let (circuit, out) = circuit_witness(params, terminals, opts.max_k)?;
+    let instance = out.instance()?;
+    let (k, slots) = (out.k, out.slots as usize);
+    let chip = circuit.chip;
+    let keys = keys_for(&circuit_id(params, &chip, slots), k, || {
+        PoDBatchCircuit::shape(k, chip, slots)
+    })?;
+    let proof = prover::prove(&keys, circuit, &instance, Some(statement), opts)?;
+    Ok((out.encode()?, proof))
+}
+
+/// Verifies an aggregated proof made with params, in at most 2^max_k rows, from the inputs
+/// the commitments of the terminals were computed from, in the order of the statement.
+pub fn verify_batch_pod_proof(
+    params: &PoDParams,
+    max_k: u32,
+    statement: &Statement,
+    commitments: &[Fp],
+    out: Vec<u8>,
+    proof: Vec<u8>,
+) -> Result<PoDBatchPublicOutput, Error> {
+    let out = PoDBatchPublicOutput::decode(&out)?;
+    check_statement(statement, out.terminals.len())?;
+    out.params.check_expected(out.k, params, max_k)?;
+    if commitments.len() != out.terminals.len() {
+        return Err(Error::InvalidPublicOutput(format!(
+            "{} expected commitments, proof has {} terminals",
+            commitments.len(),
+            out.terminals.len()
+        )));
+    }
+    for (t, expected) in out.terminals.iter().zip(commitments) {
+        check_commitment(&t.commitment, expected)?;
+    }
+    let chip = PoDChip::new(&out.params)?;
+    // checks the number of terminals before any key is generated
+    let instance = out.instance()?;
+    let (k, slots) = (out.k, out.slots as usize);
+    let keys = keys_for(&circuit_id(&out.params, &chip, slots), k, || {
+        PoDBatchCircuit::shape(k, chip, slots)
+    })?;
+    prover::verify(&keys, &instance, Some(statement), &proof)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::dev::MockProver;
+
+    fn params() -> PoDParams {
+        PoDParams {
+            kernel: KernelParams {
+                coefs: vec![BigInt::from(25), BigInt::from(1)],
+                max_dis_sqr: BigInt::from(25),
+            },
+            max_diff: BigInt::from(100000),
+        }
+    }
+
+    fn point(x: i64, y: i64) -> Point {
+        Point {
+            x: BigInt::from(x),
+            y: BigInt::from(y),
+        }
+    }
+
+    // every terminal sees all the others
+    fn terminals() -> Vec<PoDTerminal> {
+        let all: Vec<_> = [((0, 0), -700000), ((3, -4), -800000), ((4, 0), -750000)]
+            .iter()
+            .map(|((x, y), rspr)| Neighbour {
+                pos: point(*x, *y),
+                rspr: BigInt::from(*rspr),
+            })
+            .collect();
+        all.iter()
+            .map(|t| PoDTerminal {
+                pos: t.pos.clone(),
+                rspr: t.rspr.clone(),
+                neighbours: all.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_circuit() {
+        let params = params();
+        let terminals = terminals();
+        let (circuit, out) = circuit_witness(&params, &terminals, MAX_K).unwrap();
+        assert_eq!(out.slots, 4);
+        assert_eq!(out.terminals.len(), 3);
+        for (t, o) in terminals.iter().zip(out.terminals.iter()) {
+            let (weight, value) = params.eval(&t.pos, &t.rspr, &t.neighbours);
+            let commitment = t.commitment().unwrap();
+            assert_eq!(
+                o,
+                &PoDTerminalOutput {
+                    weight,
+                    value,
+                    commitment
+                }
+            );
+        }
+        let instance = out.instance().unwrap();
+        let prover = MockProver::run(out.k, &circuit, vec![instance.clone()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        // a forged weight or commitment of any terminal is rejected
+        let mut forged = out.clone();
+        forged.terminals[1].weight += 1;
+        let prover = MockProver::run(out.k, &circuit, vec![forged.instance().unwrap()]).unwrap();
+        assert!(prover.verify().is_err());
+        let mut forged = out.clone();
+        forged.terminals[1].commitment = forged.terminals[0].commitment;
+        let prover = MockProver::run(out.k, &circuit, vec![forged.instance().unwrap()]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // the padding slots are committed too, they can not hold other inputs
+        let mut forged = instance;
+        let last = forged.len() - 1;
+        forged[last] = out.terminals[0].commitment;
+        let prover = MockProver::run(out.k, &circuit, vec![forged]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_batch_public_output() {
+        let params = params();
+        let (_, out) = circuit_witness(&params, &terminals(), MAX_K).unwrap();
+        let bytes = out.encode().unwrap();
+        assert_eq!(PoDBatchPublicOutput::decode(&bytes).unwrap(), out);
+        assert!(PoDBatchPublicOutput::decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut other = out.clone();
+        other.slots = 3;
+        assert!(PoDBatchPublicOutput::decode(&other.encode().unwrap()).is_err());
+        other.slots = 4;
+        other.terminals = vec![other.terminals[0].clone(); 1 << 10];
+        assert!(matches!(
+            other.instance(),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+    }
+
+    #[test]
+    fn test_gen_verify_batch_pod_proof() {
+        let params = params();
+        let statement = Statement {
+            remote: "remote".to_owned(),
+            terminals: vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+            epoch: 1,
+            block_from: 10,
+            block_to: 20,
+            config: vec![0],
+        };
+        let commitments: Vec<_> = terminals()
+            .iter()
+            .map(|t| t.commitment().unwrap())
+            .collect();
+        let (out, proof) =
+            gen_batch_pod_proof(&params, &statement, &terminals(), ProverOptions::new(MAX_K))
+                .unwrap();
+        let decoded = verify_batch_pod_proof(
+            &params,
+            MAX_K,
+            &statement,
+            &commitments,
+            out.clone(),
+            proof.clone(),
+        )
+        .unwrap();
+        assert_eq!(decoded.terminals.len(), 3);
+        assert_eq!(decoded.params, params);
+
+        // the weights can not be moved to other terminals, nor the proof to another epoch
+        let swapped = statement.for_terminals(vec!["b".to_owned(), "a".to_owned(), "c".to_owned()]);
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &params,
+                MAX_K,
+                &swapped,
+                &commitments,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::Verification(_))
+        ));
+        let other_epoch = Statement {
+            epoch: 2,
+            ..statement.clone()
+        };
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &params,
+                MAX_K,
+                &other_epoch,
+                &commitments,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::Verification(_))
+        ));
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &params,
+                MAX_K,
+                &statement.for_terminals(vec![]),
+                &[],
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+
+        // the verifier expects other params, or smaller circuits
+        let other_params = PoDParams {
+            max_diff: BigInt::from(200000),
+            ..params.clone()
+        };
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &other_params,
+                MAX_K,
+                &statement,
+                &commitments,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &params,
+                decoded.k - 1,
+                &statement,
+                &commitments,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+
+        // nor be proved from other inputs than the expected ones
+        let mut swapped = commitments.clone();
+        swapped.swap(0, 1);
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &swapped,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+
+        let mut other = decoded;
+        let (a, b) = (other.terminals[0].clone(), other.terminals[1].clone());
+        other.terminals[0] = PoDTerminalOutput {
+            commitment: a.commitment,
+            ..b.clone()
+        };
+        other.terminals[1] = PoDTerminalOutput {
+            commitment: b.commitment,
+            ..a
+        };
+        assert!(matches!(
+            verify_batch_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &commitments,
+                other.encode().unwrap(),
+                proof
+            ),
+            Err(Error::Verification(_))
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+use halo2_gadgets::poseidon::{
+    primitives::{self as poseidon, ConstantLength, P128Pow5T3},
+    Hash, Pow5Chip, Pow5Config,
+};
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::Layouter,
+    pasta::Fp,
+    plonk::{self, ConstraintSystem},
+};
+use num_bigint::BigInt;
+
+use crate::gadgets::{bigint_to_fp, Cell, Coeffs, GateConfig};
+use crate::kernel::{check_rspr, Neighbour, Point};
+use crate::traits::Error;
+
+const WIDTH: usize = 3;
+const RATE: usize = 2;
+// coordinates and rspr take at most 40 bits (signed), a point is packed in a single element as
+// x + 2^PACK_BITS * y + 2^(2 * PACK_BITS) * rspr, which is one-to-one well below the modulus
+const PACK_BITS: usize = 64;
+// rows of a hash on the poseidon columns: initial state (1), input (3) and the permutation,
+// 1 + R_F + R_P / 2 with R_F = 8 and R_P = 56
+pub(crate) const HASH_ROWS: usize = 41;
+
+/// Commitment to the inputs of a PoD proof: h = H(terminal, 0), then h = H(h, neighbour) for
+/// every neighbour in order, with the points packed with their rspr. The proof exposes it, so
+/// that it only verifies for the positions and rspr the commitment was computed from.
+/// It is binding, not hiding: whoever holds the snapshot recomputes it.
+pub fn pod_input_commitment(
+    pos: &Point,
+    rspr: &BigInt,
+    neighbours: &[Neighbour],
+) -> Result<Fp, Error> {
+    let mut h = hash(pack(pos, rspr)?, Fp::zero());
+    for n in neighbours {
+        h = hash(h, pack(&n.pos, &n.rspr)?);
+    }
+    Ok(h)
+}
+
+fn pack(pos: &Point, rspr: &BigInt) -> Result<Fp, Error> {
+    pos.check_range()?;
+    check_rspr(rspr)?;
+    bigint_to_fp(&(&pos.x + (&pos.y << PACK_BITS) + (rspr << (2 * PACK_BITS))))
+}
+
+fn hash(a: Fp, b: Fp) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, WIDTH, RATE>::init().hash([a, b])
+}
+
+/// Poseidon on its own columns, its regions do not take rows of the gate columns.
+#[derive(Debug, Clone)]
+pub(crate) struct CommitConfig {
+    poseidon: Pow5Config<Fp, WIDTH, RATE>,
+}
+
+impl CommitConfig {
+    pub(crate) fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let state = [(); WIDTH].map(|_| meta.advice_column());
+        let partial_sbox = meta.advice_column();
+        let rc_a = [(); WIDTH].map(|_| meta.fixed_column());
+        let rc_b = [(); WIDTH].map(|_| meta.fixed_column());
+        // the inputs are copied from the gate columns
+        for c in &state {
+            meta.enable_equality(*c);
+        }
+        Self {
+            poseidon: Pow5Chip::configure::<P128Pow5T3>(meta, state, partial_sbox, rc_a, rc_b),
+        }
+    }
+
+    /// Packs a point and its rspr the same as pod_input_commitment, the caller range checks
+    /// them.
+    pub(crate) fn pack(
+        &self,
+        config: &GateConfig,
+        layouter: impl Layouter<Fp>,
+        x: &Cell,
+        y: &Cell,
+        rspr: &Cell,
+    ) -> Result<Cell, plonk::Error> {
+        let shift = Fp::from(2).pow_vartime([PACK_BITS as u64]);
+        config.gate(
+            layouter,
+            x,
+            y,
+            Some(rspr),
+            Coeffs {
+                l: Fp::one(),
+                r: shift,
+                e: shift.square(),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub(crate) fn hash(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        a: &Cell,
+        b: &Cell,
+    ) -> Result<Cell, plonk::Error> {
+        let chip = Pow5Chip::construct(self.poseidon.clone());
+        Hash::<_, _, P128Pow5T3, ConstantLength<2>, WIDTH, RATE>::init(
+            chip,
+            layouter.namespace(|| "init"),
+        )?
+        .hash(layouter.namespace(|| "hash"), [a.clone(), b.clone()])
+    }
+
+    /// h = active ? H(h, packed) : h, so that the padding slots leave the commitment as is.
+    pub(crate) fn chain(
+        &self,
+        config: &GateConfig,
+        mut layouter: impl Layouter<Fp>,
+        h: &Cell,
+        packed: &Cell,
+        active: &Cell,
+    ) -> Result<Cell, plonk::Error> {
+        let one = Fp::one();
+        let next = self.hash(layouter.namespace(|| "H(h, neighbour)"), h, packed)?;
+        let d = config.gate(
+            layouter.namespace(|| "next - h"),
+            &next,
+            h,
+            None,
+            Coeffs {
+                l: one,
+                r: -one,
+                ..Default::default()
+            },
+        )?;
+        config.gate(
+            layouter.namespace(|| "active * d + h"),
+            active,
+            &d,
+            Some(h),
+            Coeffs {
+                m: one,
+                e: one,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// Config of the PoD circuits: the arithmetic gate and the commitment of the inputs.
+#[derive(Debug, Clone)]
+pub(crate) struct PoDConfig {
+    pub(crate) gate: GateConfig,
+    pub(crate) commit: CommitConfig,
+}
+
+impl PoDConfig {
+    pub(crate) fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        Self {
+            gate: GateConfig::configure(meta),
+            commit: CommitConfig::configure(meta),
+        }
+    }
+}
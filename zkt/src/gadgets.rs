@@ -0,0 +1,546 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::{group::ff::PrimeField, Fp},
+    plonk::{
+        Advice, Column, ConstraintSystem, Constraints, Error, Fixed, Instance, Selector,
+        TableColumn,
+    },
+    poly::Rotation,
+};
+use num_bigint::{BigInt, BigUint, Sign};
+
+use crate::traits;
+
+// width of a range check limb, the lookup table holds [0, 2^LIMB_BITS)
+pub(crate) const LIMB_BITS: usize = 8;
+
+pub(crate) type Cell = AssignedCell<Fp, Fp>;
+
+/// Gate design:
+/// | a | b | e | c   | s_gate | q_l | q_r | q_m | q_e | q_c |
+/// |---|---|---|-----|--------|-----|-----|-----|-----|-----|
+/// | a | b | e | out |   1    | q_l | q_r | q_m | q_e | q_c |
+/// out = q_l * a + q_r * b + q_m * a * b + q_e * e + q_c
+///
+/// Range check design (running sum, z_0 = v, z_{i+1} = (z_i - limb_i) / 2^LIMB_BITS):
+/// | a   | b      | s_range |
+/// |-----|--------|---------|
+/// | z_0 | limb_0 |    1    |
+/// | ... | ...    |    1    |
+/// | z_n |        |         |  z_n = 0
+#[derive(Debug, Clone)]
+pub(crate) struct GateConfig {
+    pub(crate) instance: Column<Instance>,
+    advice: [Column<Advice>; 4],
+    s_gate: Selector,
+    q: [Column<Fixed>; 5],
+    s_range: Selector,
+    table: TableColumn,
+}
+
+/// Coefficients of the gate, out = l * a + r * b + m * a * b + e * e + c
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Coeffs {
+    pub(crate) l: Fp,
+    pub(crate) r: Fp,
+    pub(crate) m: Fp,
+    pub(crate) e: Fp,
+    pub(crate) c: Fp,
+}
+impl Coeffs {
+    pub(crate) fn eval(&self, a: Fp, b: Fp, e: Fp) -> Fp {
+        self.l * a + self.r * b + self.m * a * b + self.e * e + self.c
+    }
+}
+
+impl GateConfig {
+    pub(crate) fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let advice = [
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+        ];
+        let instance = meta.instance_column();
+        let constant = meta.fixed_column();
+        meta.enable_equality(instance);
+        meta.enable_constant(constant);
+        for c in &advice {
+            meta.enable_equality(*c);
+        }
+        let q = [
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+        ];
+        let s_gate = meta.selector();
+        let s_range = meta.complex_selector();
+        let table = meta.lookup_table_column();
+
+        meta.create_gate("arith_gate", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let e = meta.query_advice(advice[2], Rotation::cur());
+            let out = meta.query_advice(advice[3], Rotation::cur());
+            let [q_l, q_r, q_m, q_e, q_c] = q.map(|q| meta.query_fixed(q, Rotation::cur()));
+            let s_gate = meta.query_selector(s_gate);
+            Constraints::with_selector(
+                s_gate,
+                vec![q_l * a.clone() + q_r * b.clone() + q_m * a * b + q_e * e + q_c - out],
+            )
+        });
+
+        meta.create_gate("running_sum_gate", |meta| {
+            let z_cur = meta.query_advice(advice[0], Rotation::cur());
+            let limb = meta.query_advice(advice[1], Rotation::cur());
+            let z_next = meta.query_advice(advice[0], Rotation::next());
+            let s_range = meta.query_selector(s_range);
+            Constraints::with_selector(
+                s_range,
+                vec![z_cur - limb - z_next * Fp::from(1u64 << LIMB_BITS)],
+            )
+        });
+
+        meta.lookup(|meta| {
+            let limb = meta.query_advice(advice[1], Rotation::cur());
+            let s_range = meta.query_selector(s_range);
+            vec![(s_range * limb, table)]
+        });
+
+        Self {
+            instance,
+            advice,
+            s_gate,
+            q,
+            s_range,
+            table,
+        }
+    }
+
+    pub(crate) fn load_table(&self, layouter: &mut impl Layouter<Fp>) -> Result<(), Error> {
+        layouter.assign_table(
+            || "limb table",
+            |mut table| {
+                for i in 0..1usize << LIMB_BITS {
+                    table.assign_cell(
+                        || "limb",
+                        self.table,
+                        i,
+                        || Value::known(Fp::from(i as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Witness up to 4 values in a single row, nothing is constrained.
+    pub(crate) fn load_private(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        values: &[Value<Fp>],
+    ) -> Result<Vec<Cell>, Error> {
+        assert!(values.len() <= self.advice.len());
+        layouter.assign_region(
+            || "load private",
+            |mut region| {
+                values
+                    .iter()
+                    .zip(self.advice.iter())
+                    .map(|(v, col)| region.assign_advice(|| "private", *col, 0, || *v))
+                    .collect()
+            },
+        )
+    }
+
+    pub(crate) fn load_instance(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        row: usize,
+    ) -> Result<Cell, Error> {
+        layouter.assign_region(
+            || "load instance",
+            |mut region| {
+                region.assign_advice_from_instance(
+                    || "instance",
+                    self.instance,
+                    row,
+                    self.advice[0],
+                    0,
+                )
+            },
+        )
+    }
+
+    pub(crate) fn load_constant(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        constant: Fp,
+    ) -> Result<Cell, Error> {
+        layouter.assign_region(
+            || "load constant",
+            |mut region| {
+                region.assign_advice_from_constant(|| "constant", self.advice[0], 0, constant)
+            },
+        )
+    }
+
+    /// out = l * a + r * b + m * a * b + e * e + c, with a, b and e copied from existing cells.
+    pub(crate) fn gate(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        a: &Cell,
+        b: &Cell,
+        e: Option<&Cell>,
+        coeffs: Coeffs,
+    ) -> Result<Cell, Error> {
+        layouter.assign_region(
+            || "gate",
+            |mut region| {
+                self.s_gate.enable(&mut region, 0)?;
+                let q = [coeffs.l, coeffs.r, coeffs.m, coeffs.e, coeffs.c];
+                for (col, q) in self.q.iter().zip(q) {
+                    region.assign_fixed(|| "q", *col, 0, || Value::known(q))?;
+                }
+                a.copy_advice(|| "a", &mut region, self.advice[0], 0)?;
+                b.copy_advice(|| "b", &mut region, self.advice[1], 0)?;
+                let e_value = match e {
+                    Some(e) => e.copy_advice(|| "e", &mut region, self.advice[2], 0)?,
+                    None => region.assign_advice(
+                        || "e",
+                        self.advice[2],
+                        0,
+                        || Value::known(Fp::zero()),
+                    )?,
+                };
+                let out = a
+                    .value()
+                    .zip(b.value())
+                    .zip(e_value.value())
+                    .map(|((a, b), e)| coeffs.eval(*a, *b, *e));
+                region.assign_advice(|| "out", self.advice[3], 0, || out)
+            },
+        )
+    }
+
+    pub(crate) fn mul(
+        &self,
+        layouter: impl Layouter<Fp>,
+        a: &Cell,
+        b: &Cell,
+    ) -> Result<Cell, Error> {
+        self.gate(
+            layouter,
+            a,
+            b,
+            None,
+            Coeffs {
+                m: Fp::one(),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub(crate) fn constrain_equal(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        a: &Cell,
+        b: &Cell,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "constrain equal",
+            |mut region| region.constrain_equal(a.cell(), b.cell()),
+        )
+    }
+
+    pub(crate) fn constrain_zero(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        cell: &Cell,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "constrain zero",
+            |mut region| region.constrain_constant(cell.cell(), Fp::zero()),
+        )
+    }
+
+    /// b * b - b = 0
+    pub(crate) fn assert_bool(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        b: &Cell,
+    ) -> Result<(), Error> {
+        let out = self.gate(
+            layouter.namespace(|| "b * b - b"),
+            b,
+            b,
+            None,
+            Coeffs {
+                l: -Fp::one(),
+                m: Fp::one(),
+                ..Default::default()
+            },
+        )?;
+        self.constrain_zero(layouter.namespace(|| "bool"), &out)
+    }
+
+    /// Constrain 0 <= v < 2^(LIMB_BITS * limbs).
+    pub(crate) fn range_check(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        v: &Cell,
+        limbs: usize,
+    ) -> Result<(), Error> {
+        let z_last = layouter.assign_region(
+            || "range check",
+            |mut region| {
+                let limb_inv = Fp::from(1u64 << LIMB_BITS).invert().unwrap();
+                let mut z = v.copy_advice(|| "z_0", &mut region, self.advice[0], 0)?;
+                for i in 0..limbs {
+                    self.s_range.enable(&mut region, i)?;
+                    let limb = z.value().map(low_limb);
+                    region.assign_advice(|| "limb", self.advice[1], i, || limb)?;
+                    let z_next = z.value().zip(limb).map(|(z, limb)| (*z - limb) * limb_inv);
+                    z = region.assign_advice(|| "z", self.advice[0], i + 1, || z_next)?;
+                }
+                Ok(z)
+            },
+        )?;
+        self.constrain_zero(layouter.namespace(|| "z_n"), &z_last)
+    }
+
+    /// Constrain -2^(LIMB_BITS * limbs - 1) <= v < 2^(LIMB_BITS * limbs - 1).
+    pub(crate) fn signed_range_check(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        v: &Cell,
+        limbs: usize,
+    ) -> Result<(), Error> {
+        let shifted = self.gate(
+            layouter.namespace(|| "v + 2^(bits - 1)"),
+            v,
+            v,
+            None,
+            Coeffs {
+                l: Fp::one(),
+                c: Fp::from(2).pow_vartime([(LIMB_BITS * limbs - 1) as u64]),
+                ..Default::default()
+            },
+        )?;
+        self.range_check(layouter.namespace(|| "shifted range"), &shifted, limbs)
+    }
+
+    /// Returns b = (a <= c), t = b ? c - a : a - c - 1 is checked in [0, 2^(LIMB_BITS * limbs)).
+    /// a and c have to be non negative and below 2^(LIMB_BITS * limbs).
+    pub(crate) fn is_le(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        a: &Cell,
+        c: &Cell,
+        limbs: usize,
+    ) -> Result<Cell, Error> {
+        let one = Fp::one();
+        let b = a
+            .value()
+            .zip(c.value())
+            .map(|(a, c)| bool_to_fp(fp_to_biguint(a) <= fp_to_biguint(c)));
+        let b = self
+            .load_private(layouter.namespace(|| "load b"), &[b])?
+            .remove(0);
+        self.assert_bool(layouter.namespace(|| "b"), &b)?;
+        let u = self.gate(
+            layouter.namespace(|| "2 * c - 2 * a + 1"),
+            c,
+            a,
+            None,
+            Coeffs {
+                l: Fp::from(2),
+                r: -Fp::from(2),
+                c: one,
+                ..Default::default()
+            },
+        )?;
+        let v = self.mul(layouter.namespace(|| "b * u"), &b, &u)?;
+        let t = self.gate(
+            layouter.namespace(|| "v + a - c - 1"),
+            &v,
+            a,
+            Some(c),
+            Coeffs {
+                l: one,
+                r: one,
+                e: -one,
+                c: -one,
+                ..Default::default()
+            },
+        )?;
+        self.range_check(layouter.namespace(|| "t range"), &t, limbs)?;
+        Ok(b)
+    }
+
+    /// Returns (s, |v|) with s = (v >= 0), |v| is checked in [0, 2^(LIMB_BITS * limbs)).
+    pub(crate) fn abs(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        v: &Cell,
+        limbs: usize,
+    ) -> Result<(Cell, Cell), Error> {
+        let s = v
+            .value()
+            .map(|v| bool_to_fp(fp_to_bigint(v).sign() != Sign::Minus));
+        let s = self
+            .load_private(layouter.namespace(|| "load s"), &[s])?
+            .remove(0);
+        self.assert_bool(layouter.namespace(|| "s"), &s)?;
+        let abs = self.gate(
+            layouter.namespace(|| "(2 * s - 1) * v"),
+            &s,
+            v,
+            None,
+            Coeffs {
+                r: -Fp::one(),
+                m: Fp::from(2),
+                ..Default::default()
+            },
+        )?;
+        self.range_check(layouter.namespace(|| "abs range"), &abs, limbs)?;
+        Ok((s, abs))
+    }
+
+    /// Returns q = n / d with n = q * d + r and 0 <= r < d.
+    /// q is checked below 2^(LIMB_BITS * q_limbs) and r below 2^(LIMB_BITS * r_limbs),
+    /// q * d + r must not wrap around the modulus.
+    pub(crate) fn div_rem(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        n: &Cell,
+        d: &Cell,
+        q_limbs: usize,
+        r_limbs: usize,
+    ) -> Result<Cell, Error> {
+        let one = Fp::one();
+        let qr = n.value().zip(d.value()).map(|(n, d)| {
+            let (n, d) = (fp_to_biguint(n), fp_to_biguint(d));
+            if d == BigUint::from(0u32) {
+                return (Fp::zero(), Fp::zero());
+            }
+            let to_fp = |v: BigUint| bigint_to_fp(&BigInt::from(v)).unwrap_or(Fp::zero());
+            (to_fp(&n / &d), to_fp(&n % &d))
+        });
+        let (q, r) = qr.unzip();
+        let cells = self.load_private(layouter.namespace(|| "load q r"), &[q, r])?;
+        let (q, r) = (&cells[0], &cells[1]);
+        self.range_check(layouter.namespace(|| "q range"), q, q_limbs)?;
+        self.range_check(layouter.namespace(|| "r range"), r, r_limbs)?;
+        let gap = self.gate(
+            layouter.namespace(|| "d - r - 1"),
+            d,
+            r,
+            None,
+            Coeffs {
+                l: one,
+                r: -one,
+                c: -one,
+                ..Default::default()
+            },
+        )?;
+        self.range_check(layouter.namespace(|| "gap range"), &gap, r_limbs)?;
+        let qdr = self.gate(
+            layouter.namespace(|| "q * d + r"),
+            q,
+            d,
+            Some(r),
+            Coeffs {
+                m: one,
+                e: one,
+                ..Default::default()
+            },
+        )?;
+        self.constrain_equal(layouter.namespace(|| "n = q * d + r"), &qdr, n)?;
+        Ok(q.clone())
+    }
+}
+
+// the lowest LIMB_BITS of the canonical representation
+fn low_limb(v: &Fp) -> Fp {
+    Fp::from(v.to_repr()[0] as u64)
+}
+
+/// Rows taken by range_check.
+pub(crate) const fn range_check_rows(limbs: usize) -> usize {
+    limbs + 1
+}
+/// Rows taken by signed_range_check.
+pub(crate) const fn signed_range_check_rows(limbs: usize) -> usize {
+    1 + range_check_rows(limbs)
+}
+/// Rows taken by is_le.
+pub(crate) const fn is_le_rows(limbs: usize) -> usize {
+    5 + range_check_rows(limbs)
+}
+/// Rows taken by abs.
+pub(crate) const fn abs_rows(limbs: usize) -> usize {
+    3 + range_check_rows(limbs)
+}
+/// Rows taken by div_rem.
+pub(crate) const fn div_rem_rows(q_limbs: usize, r_limbs: usize) -> usize {
+    1 + range_check_rows(q_limbs) + 2 * range_check_rows(r_limbs) + 2
+}
+
+/// Signed integers are encoded by field negation: v >= 0 maps to v and v < 0 to p - |v|. It is
+/// one-to-one for v in [-2^(SIGNED_FP_BITS - 1), 2^(SIGNED_FP_BITS - 1)), which is below p / 2,
+/// values out of it are rejected instead of wrapped. In circuit a signed value is bounded by
+/// signed_range_check, an unsigned one by range_check.
+pub(crate) const SIGNED_FP_BITS: usize = 254;
+
+pub(crate) fn bigint_to_fp(v: &BigInt) -> Result<Fp, traits::Error> {
+    if !fits_signed(v, SIGNED_FP_BITS) {
+        return Err(traits::Error::InputOutOfRange(format!(
+            "{} is not in the signed {} bits range of the field",
+            v, SIGNED_FP_BITS
+        )));
+    }
+    let (sign, bytes) = v.to_bytes_le();
+    let mut repr = [0u8; 32];
+    repr[..bytes.len()].copy_from_slice(&bytes);
+    let fp = Option::<Fp>::from(Fp::from_repr(repr)).ok_or_else(|| {
+        traits::Error::InputOutOfRange(format!("{} does not fit in the field", v))
+    })?;
+    if sign == Sign::Minus {
+        Ok(-fp)
+    } else {
+        Ok(fp)
+    }
+}
+
+/// v in [-2^(bits - 1), 2^(bits - 1)).
+pub(crate) fn fits_signed(v: &BigInt, bits: usize) -> bool {
+    let bound = BigInt::from(1) << (bits - 1);
+    -&bound <= *v && *v < bound
+}
+
+pub(crate) fn fp_to_biguint(v: &Fp) -> BigUint {
+    BigUint::from_bytes_le(v.to_repr().as_ref())
+}
+
+// the upper half of the field is read as negative
+pub(crate) fn fp_to_bigint(v: &Fp) -> BigInt {
+    let pos = fp_to_biguint(v);
+    let neg = fp_to_biguint(&-*v);
+    if pos <= neg {
+        BigInt::from(pos)
+    } else {
+        -BigInt::from(neg)
+    }
+}
+
+pub(crate) fn bool_to_fp(b: bool) -> Fp {
+    if b {
+        Fp::one()
+    } else {
+        Fp::zero()
+    }
+}
@@ -0,0 +1,352 @@
+use halo2_proofs::{
+    circuit::{Layouter, Value},
+    pasta::Fp,
+    plonk,
+};
+use num_bigint::{BigInt, Sign};
+
+use crate::gadgets::{
+    bool_to_fp, fp_to_biguint, is_le_rows, range_check_rows, signed_range_check_rows, Cell, Coeffs,
+    GateConfig, LIMB_BITS,
+};
+use crate::traits::Error;
+
+// coordinates are in [-2^COORD_BITS, 2^COORD_BITS)
+pub const COORD_BITS: usize = 39;
+const COORD_LIMBS: usize = (COORD_BITS + 1) / LIMB_BITS;
+// the squared distance is below 2^(2 * COORD_BITS + 3), the comparison with
+// max_dis_sqr is range checked on DIST_LIMBS limbs
+const DIST_LIMBS: usize = 11;
+pub const MAX_DIS_SQR_BITS: u64 = (DIST_LIMBS * LIMB_BITS) as u64;
+// rspr is in [-2^RSPR_BITS, 2^RSPR_BITS)
+pub const RSPR_BITS: usize = 39;
+pub(crate) const RSPR_LIMBS: usize = (RSPR_BITS + 1) / LIMB_BITS;
+// the weight chain works on NUMER_BITS + 64 bits, which have to stay below the modulus
+pub(crate) const MAX_NUMER_LIMBS: usize = 23;
+
+/// Kernel numerator as a polynomial of the squared distance d:
+/// numer(d) = sum(coefs[i] * (-d)^i) if d <= max_dis_sqr and numer(d) >= 0, otherwise 0.
+/// Quadratic is coefs = [max_dis_sqr, 1], the Taylor Gaussian is its series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelParams {
+    pub coefs: Vec<BigInt>,
+    pub max_dis_sqr: BigInt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Point {
+    pub x: BigInt,
+    pub y: BigInt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbour {
+    pub pos: Point,
+    pub rspr: BigInt,
+}
+
+impl KernelParams {
+    pub fn order(&self) -> usize {
+        self.coefs.len() - 1
+    }
+
+    pub fn eval_numer(&self, dis_sqr: &BigInt) -> BigInt {
+        if dis_sqr > &self.max_dis_sqr {
+            return BigInt::from(0);
+        }
+        let numer = self
+            .coefs
+            .iter()
+            .rev()
+            .fold(BigInt::from(0), |acc, c| -acc * dis_sqr + c);
+        if numer.sign() == Sign::Minus {
+            return BigInt::from(0);
+        }
+        numer
+    }
+
+    /// Number of limbs needed to check the sign of the numerator, it fails when the
+    /// numerator does not fit in the field.
+    pub fn numer_limbs(&self) -> Result<usize, Error> {
+        if self.coefs.is_empty() {
+            return Err(Error::InvalidKernel("no coefficients".to_owned()));
+        }
+        if self.max_dis_sqr.sign() == Sign::Minus || self.max_dis_sqr.bits() > MAX_DIS_SQR_BITS {
+            return Err(Error::InvalidKernel(format!(
+                "max_dis_sqr {} is not in [0, 2^{})",
+                self.max_dis_sqr, MAX_DIS_SQR_BITS
+            )));
+        }
+        if self.coefs.iter().any(|c| c.sign() == Sign::Minus) {
+            return Err(Error::InvalidKernel("negative coefficient".to_owned()));
+        }
+        // |numer(d)| <= sum(coefs[i] * max_dis_sqr^i) for d in [0, max_dis_sqr]
+        let bound = self
+            .coefs
+            .iter()
+            .rev()
+            .fold(BigInt::from(0), |acc, c| acc * &self.max_dis_sqr + c);
+        let limbs = ((bound.bits() as usize).max(1) + LIMB_BITS - 1) / LIMB_BITS;
+        if limbs > MAX_NUMER_LIMBS {
+            return Err(Error::InvalidKernel(format!(
+                "numerator needs {} bits, at most {} fit in the field",
+                bound.bits(),
+                MAX_NUMER_LIMBS * LIMB_BITS
+            )));
+        }
+        Ok(limbs)
+    }
+}
+
+impl Point {
+    pub fn dist_sqr(&self, target: &Self) -> BigInt {
+        let dx = &self.x - &target.x;
+        let dy = &self.y - &target.y;
+        &dx * &dx + &dy * &dy
+    }
+
+    pub(crate) fn check_range(&self) -> Result<(), Error> {
+        let bound = BigInt::from(1) << COORD_BITS;
+        for c in [&self.x, &self.y] {
+            if c < &-bound.clone() || c >= &bound {
+                return Err(Error::InputOutOfRange(format!(
+                    "coordinate {} is not in [-2^{}, 2^{})",
+                    c, COORD_BITS, COORD_BITS
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn check_rspr(rspr: &BigInt) -> Result<(), Error> {
+    let bound = BigInt::from(1) << RSPR_BITS;
+    if rspr < &-bound.clone() || rspr >= &bound {
+        return Err(Error::InputOutOfRange(format!(
+            "rspr {} is not in [-2^{}, 2^{})",
+            rspr, RSPR_BITS, RSPR_BITS
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct NeighbourWitness {
+    pub(crate) x: Value<Fp>,
+    pub(crate) y: Value<Fp>,
+    pub(crate) rspr: Value<Fp>,
+    // padding slots are inactive and do not count
+    pub(crate) active: Value<Fp>,
+}
+
+impl NeighbourWitness {
+    pub(crate) fn unknown() -> Self {
+        Self {
+            x: Value::unknown(),
+            y: Value::unknown(),
+            rspr: Value::unknown(),
+            active: Value::unknown(),
+        }
+    }
+
+    pub(crate) fn padding() -> Self {
+        let zero = Value::known(Fp::zero());
+        Self {
+            x: zero,
+            y: zero,
+            rspr: zero,
+            active: zero,
+        }
+    }
+}
+
+/// Kernel numerator of a neighbour, computed from the positions in the witness.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KernelChip {
+    pub(crate) order: usize,
+    pub(crate) numer_limbs: usize,
+}
+
+impl KernelChip {
+    // rows of coord_check
+    pub(crate) const COORD_ROWS: usize = signed_range_check_rows(COORD_LIMBS);
+
+    // rows taken by each neighbour, it must follow neighbour_coef
+    pub(crate) fn neighbour_rows(&self) -> usize {
+        // load, active bit, coordinates, rspr, dx, dy, dx^2, d
+        1 + 1 + 2 * Self::COORD_ROWS + signed_range_check_rows(RSPR_LIMBS) + 4
+            // comparison with max_dis_sqr, masked d
+            + is_le_rows(DIST_LIMBS) + 1
+            // Horner
+            + self.order
+            // sign bit of the numerator
+            + 2 + 2 + range_check_rows(self.numer_limbs)
+            // coef
+            + 3
+    }
+
+    // -2^COORD_BITS <= c < 2^COORD_BITS
+    pub(crate) fn coord_check(
+        config: &GateConfig,
+        layouter: impl Layouter<Fp>,
+        c: &Cell,
+    ) -> Result<(), plonk::Error> {
+        config.signed_range_check(layouter, c, COORD_LIMBS)
+    }
+
+    // -2^RSPR_BITS <= rspr < 2^RSPR_BITS
+    pub(crate) fn rspr_check(
+        config: &GateConfig,
+        layouter: impl Layouter<Fp>,
+        rspr: &Cell,
+    ) -> Result<(), plonk::Error> {
+        config.signed_range_check(layouter, rspr, RSPR_LIMBS)
+    }
+
+    /// Returns the coef and the loaded x, y, rspr and active of the neighbour.
+    pub(crate) fn neighbour_coef(
+        &self,
+        config: &GateConfig,
+        mut layouter: impl Layouter<Fp>,
+        pos: &[Cell],
+        max_dis_sqr: &Cell,
+        coefs: &[Cell],
+        n: &NeighbourWitness,
+    ) -> Result<(Cell, Vec<Cell>), plonk::Error> {
+        let one = Fp::one();
+        let cells = config.load_private(
+            layouter.namespace(|| "load neighbour"),
+            &[n.x, n.y, n.rspr, n.active],
+        )?;
+        let (x, y, rspr, active) = (&cells[0], &cells[1], &cells[2], &cells[3]);
+        config.assert_bool(layouter.namespace(|| "active"), active)?;
+        Self::coord_check(config, layouter.namespace(|| "x"), x)?;
+        Self::coord_check(config, layouter.namespace(|| "y"), y)?;
+        Self::rspr_check(config, layouter.namespace(|| "rspr"), rspr)?;
+
+        let sub = Coeffs {
+            l: one,
+            r: -one,
+            ..Default::default()
+        };
+        let dx = config.gate(layouter.namespace(|| "dx"), &pos[0], x, None, sub)?;
+        let dy = config.gate(layouter.namespace(|| "dy"), &pos[1], y, None, sub)?;
+        let dx2 = config.mul(layouter.namespace(|| "dx^2"), &dx, &dx)?;
+        let d = config.gate(
+            layouter.namespace(|| "d = dy^2 + dx^2"),
+            &dy,
+            &dy,
+            Some(&dx2),
+            Coeffs {
+                m: one,
+                e: one,
+                ..Default::default()
+            },
+        )?;
+
+        let b = config.is_le(
+            layouter.namespace(|| "d <= max_dis_sqr"),
+            &d,
+            max_dis_sqr,
+            DIST_LIMBS,
+        )?;
+
+        // out of range neighbours evaluate the polynomial at 0, so that the numerator stays bounded
+        let d = config.mul(layouter.namespace(|| "b * d"), &b, &d)?;
+        let mut numer = coefs[self.order].clone();
+        for c in coefs[..self.order].iter().rev() {
+            numer = config.gate(
+                layouter.namespace(|| "numer * (-d) + c"),
+                &numer,
+                &d,
+                Some(c),
+                Coeffs {
+                    m: -one,
+                    e: one,
+                    ..Default::default()
+                },
+            )?;
+        }
+
+        // s = numer >= 0, t = s ? numer : -numer - 1 is never negative
+        let numer_bits = (self.numer_limbs * LIMB_BITS) as u64;
+        let s = numer
+            .value()
+            .map(|n| bool_to_fp(fp_to_biguint(n).bits() <= numer_bits));
+        let s = config
+            .load_private(layouter.namespace(|| "load s"), &[s])?
+            .remove(0);
+        config.assert_bool(layouter.namespace(|| "s"), &s)?;
+        let w = config.gate(
+            layouter.namespace(|| "s * (2 * numer + 1)"),
+            &s,
+            &numer,
+            None,
+            Coeffs {
+                l: one,
+                m: Fp::from(2),
+                ..Default::default()
+            },
+        )?;
+        let t = config.gate(
+            layouter.namespace(|| "w - numer - 1"),
+            &w,
+            &numer,
+            None,
+            Coeffs {
+                l: one,
+                r: -one,
+                c: -one,
+                ..Default::default()
+            },
+        )?;
+        config.range_check(layouter.namespace(|| "sign range"), &t, self.numer_limbs)?;
+
+        let g = config.mul(layouter.namespace(|| "active * b"), active, &b)?;
+        let g = config.mul(layouter.namespace(|| "g * s"), &g, &s)?;
+        let coef = config.mul(layouter.namespace(|| "coef"), &g, &numer)?;
+        Ok((coef, cells))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_numer() {
+        // 1 - x/(2s) + x^2/(8s^2) - x^3/(48s^3) with s = 16, multiplied by 48 * s^3
+        let params = KernelParams {
+            coefs: vec![
+                BigInt::from(196608),
+                BigInt::from(6144),
+                BigInt::from(96),
+                BigInt::from(1),
+            ],
+            max_dis_sqr: BigInt::from(144),
+        };
+        assert_eq!(params.eval_numer(&BigInt::from(1)), BigInt::from(190559));
+        assert_eq!(params.eval_numer(&BigInt::from(25)), BigInt::from(87383));
+        // negative numerator and out of range
+        assert_eq!(params.eval_numer(&BigInt::from(100)), BigInt::from(0));
+        assert_eq!(params.eval_numer(&BigInt::from(145)), BigInt::from(0));
+        assert_eq!(params.numer_limbs(), Ok(3));
+    }
+
+    #[test]
+    fn test_kernel_params_out_of_field() {
+        // order 20 Taylor coefficients with sigma = 500000
+        let sigma_sqr = BigInt::from(500000) * BigInt::from(500000);
+        let mut coefs = vec![BigInt::from(1); 21];
+        let mut b = BigInt::from(1);
+        for i in (0..20).rev() {
+            b *= BigInt::from((i + 1) * 2) * &sigma_sqr;
+            coefs[i] = b.clone();
+        }
+        let params = KernelParams {
+            coefs,
+            max_dis_sqr: sigma_sqr * 9,
+        };
+        assert!(matches!(params.numer_limbs(), Err(Error::InvalidKernel(_))));
+    }
+}
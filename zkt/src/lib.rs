@@ -3,21 +3,41 @@
 use halo2_proofs::{
     arithmetic::Field,
     circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
-    plonk::{
-        create_proof, keygen_pk, keygen_vk, Advice, Circuit, Column, ConstraintSystem, Constraints,
-        Error, Instance, Selector,
-    },
-    poly::{commitment::Params, Rotation},
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Instance, Selector},
+    poly::Rotation,
 };
 
-use halo2_proofs::pasta::{EqAffine, Fp};
-use halo2_proofs::transcript::{Blake2bWrite, Challenge255};
+use halo2_proofs::pasta::group::ff::PrimeField;
+use halo2_proofs::pasta::Fp;
 
+// Proofs are IPA over Pasta only. There is no KZG/BN256 backend and no Solidity verifier:
+// they need a halo2 with KZG commitments, a verifier generator and an EVM to check the
+// generated contract, none of which the workspace depends on, and the circuits would have to
+// move to the BN256 scalar field. Proofs can not be checked on an EVM chain.
 // use halo2curves::bn256::{Bn256, Fr, G1Affine};
-use rand_core::OsRng;
+use config::PoxConfig;
+use num_bigint::BigInt;
 use std::fmt::Debug;
 
+pub mod batch;
+mod commitment;
+mod gadgets;
+pub mod kernel;
+pub mod pod;
+pub mod prover;
+pub mod setup;
+pub mod statement;
 pub mod traits;
+pub use batch::{
+    gen_batch_pod_proof, verify_batch_pod_proof, PoDBatchPublicOutput, PoDTerminal,
+    PoDTerminalOutput,
+};
+pub use commitment::pod_input_commitment;
+pub use kernel::{KernelParams, Neighbour, Point};
+pub use pod::{gen_pod_proof, verify_pod_proof, PoDParams, PoDPublicOutput};
+pub use prover::{mock_prove, ProverOptions};
+pub use setup::circuit_keys;
+pub use statement::Statement;
 pub use traits::ZkTraitHalo2;
 
 // use halo2_proofs::{dev::MockProver, pasta::Fp};
@@ -46,6 +66,28 @@ struct MyCircuit<F: Field> {
     xs: Vec<Value<F>>,
 }
 
+// every pair takes 5 rows: load (1), mul (2) and add (2)
+const ROWS_PER_PAIR: usize = 5;
+
+impl<F: Field> MyCircuit<F> {
+    /// Number of coef/x pairs that fit in a circuit with 2^k rows.
+    fn capacity(k: u32) -> usize {
+        let mut cs = ConstraintSystem::<F>::default();
+        Self::configure(&mut cs);
+        ((1usize << k).saturating_sub(cs.minimum_rows())) / ROWS_PER_PAIR
+    }
+
+    /// Circuit without witnesses, padded to the capacity of 2^k rows.
+    /// All the proofs for the same k share this shape, hence the same keys.
+    fn shape(k: u32) -> Self {
+        let len = Self::capacity(k);
+        Self {
+            coefs: vec![Value::unknown(); len],
+            xs: vec![Value::unknown(); len],
+        }
+    }
+}
+
 fn load_private<F: Field>(
     config: &CircuitConfig,
     mut layouter: impl Layouter<F>,
@@ -235,11 +277,62 @@ impl<F: Field> Circuit<F> for MyCircuit<F> {
     }
 }
 
-pub fn gen_proof(coefs: Vec<Fp>, xs: Vec<Fp>) -> Result<(Vec<u8>, Vec<u8>), traits::Error> {
+// The number of rows in our circuit cannot exceed 2^k, k is picked from the number
+// of pairs between MIN_K and the configured max_k.
+pub(crate) const MIN_K: u32 = 7;
+// hard upper bound, also the largest k a verifier accepts
+pub const MAX_K: u32 = 20;
+
+/// Smallest k whose circuit fits len pairs.
+pub fn select_k(len: usize, max_k: u32) -> Result<u32, traits::Error> {
+    (MIN_K..=max_k)
+        .find(|k| MyCircuit::<Fp>::capacity(*k) >= len)
+        .ok_or_else(|| traits::Error::CircuitTooLarge {
+            len,
+            max_len: MyCircuit::<Fp>::capacity(max_k),
+            max_k,
+        })
+}
+
+/// Public output layout: | out (32 bytes, Fp repr) | k (u32 le) |
+/// The verifier needs k to pick the verifying key.
+fn encode_public_output(out: &Fp, k: u32) -> Vec<u8> {
+    let mut bytes = out.to_repr().to_vec();
+    bytes.extend_from_slice(&k.to_le_bytes());
+    bytes
+}
+
+fn decode_public_output(bytes: &[u8]) -> Result<(Fp, u32), traits::Error> {
+    if bytes.len() != 36 {
+        return Err(traits::Error::InvalidPublicOutput(format!(
+            "expected 36 bytes, got {}",
+            bytes.len()
+        )));
+    }
+    let mut repr = [0u8; 32];
+    repr.copy_from_slice(&bytes[..32]);
+    let out = Option::<Fp>::from(Fp::from_repr(repr)).ok_or_else(|| {
+        traits::Error::InvalidPublicOutput(format!("non canonical field element {:?}", repr))
+    })?;
+    let k = u32::from_le_bytes([bytes[32], bytes[33], bytes[34], bytes[35]]);
+    // do not let a peer trigger a keygen for an arbitrarily large circuit
+    if !(MIN_K..=MAX_K).contains(&k) {
+        return Err(traits::Error::InvalidPublicOutput(format!(
+            "unsupported circuit size k = {}",
+            k
+        )));
+    }
+    Ok((out, k))
+}
+
+pub fn gen_proof(
+    coefs: Vec<Fp>,
+    xs: Vec<Fp>,
+    opts: ProverOptions,
+) -> Result<(Vec<u8>, Vec<u8>), traits::Error> {
     // ANCHOR: test-circuit
-    // The number of rows in our circuit cannot exceed 2^k. Since our example
-    // circuit is very small, we can pick a very small value here.
-    let k = 7;
+    let k = select_k(coefs.len().max(xs.len()), opts.max_k)?;
+    let keys = circuit_keys(k)?;
 
     // Prepare the private and public inputs to the circuit!
     use std::iter::zip;
@@ -251,52 +344,61 @@ pub fn gen_proof(coefs: Vec<Fp>, xs: Vec<Fp>) -> Result<(Vec<u8>, Vec<u8>), trai
     // println!("Public out=:{:?}", out);
     let pubinputs = vec![out];
 
-    let coefs = coefs.into_iter().map(Value::known).collect();
-    let xs = xs.into_iter().map(Value::known).collect();
+    // pad with zero pairs to the shape the keys were generated for, it does not change out
+    let len = MyCircuit::<Fp>::capacity(k);
+    let coefs = coefs
+        .into_iter()
+        .chain(std::iter::repeat(Fp::zero()))
+        .take(len)
+        .map(Value::known)
+        .collect();
+    let xs = xs
+        .into_iter()
+        .chain(std::iter::repeat(Fp::zero()))
+        .take(len)
+        .map(Value::known)
+        .collect();
 
     // Instantiate the circuit with the private inputs.
     let circuit = MyCircuit { coefs, xs };
 
-    let params: Params<EqAffine> = Params::new(k);
-    let vk = keygen_vk(&params, &circuit).expect("vk should not fail");
-    let pk = keygen_pk(&params, vk, &circuit).expect("pk should not fail");
-
-    let instances: &[&[Fp]] = &[&pubinputs];
-    // let vu8_out = out.to_raw_bytes();
-    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-    // Create a proof
-    create_proof(
-        &params,
-        &pk,
-        &[circuit.clone()],
-        &[instances],
-        OsRng,
-        &mut transcript,
-    )
-    .expect("proof generation should not fail");
-    let proof: Vec<u8> = transcript.finalize();
-
-    // std::fs::write("./tests/plonk_api_proof.bin", &proof[..])
-    // .expect("should succeed to write new proof");
-
-    // Check that a hardcoded proof is satisfied
-    // let proof = std::fs::read("./tests/plonk_api_proof.bin").expect("should succeed to read proof");
-    // let strategy = SingleVerifier::new(&params);
-    // let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-    // assert!(verify_proof(
-    //     &params,
-    //     pk.get_vk(),
-    //     strategy,
-    //     &[&[&pubinputs[..]], &[&pubinputs[..]]],
-    //     &mut transcript,
-    // )
-    // .is_ok());
-
-    let vecu8_out = format!("{:?}", out).as_bytes().to_vec();
+    let proof = prover::prove(&keys, circuit, &pubinputs, None, opts)?;
+
+    let vecu8_out = encode_public_output(&out, k);
     Ok((vecu8_out, proof))
 }
-#[derive(Debug)]
-pub struct ZKT;
+
+pub fn verify_proof(out: Vec<u8>, proof: Vec<u8>) -> Result<(), traits::Error> {
+    let (out, k) = decode_public_output(&out)?;
+    let keys = circuit_keys(k)?;
+
+    prover::verify(&keys, &[out], None, &proof)
+}
+#[derive(Debug, Clone)]
+pub struct ZKT {
+    opts: ProverOptions,
+}
+impl ZKT {
+    pub fn new(cfg: &PoxConfig) -> Result<Self, traits::Error> {
+        if !(MIN_K..=MAX_K).contains(&cfg.zk.max_k) {
+            return Err(traits::Error::InvalidConfig(format!(
+                "zk.max_k should be in [{}, {}], got {}",
+                MIN_K, MAX_K, cfg.zk.max_k
+            )));
+        }
+        Ok(Self {
+            opts: ProverOptions {
+                max_k: cfg.zk.max_k,
+                debug: cfg.zk.debug,
+            },
+        })
+    }
+}
+impl Default for ZKT {
+    fn default() -> Self {
+        Self::new(&PoxConfig::default()).expect("default zk config should be valid")
+    }
+}
 
 impl traits::ZkTraitHalo2 for ZKT {
     type F = Fp;
@@ -307,7 +409,57 @@ impl traits::ZkTraitHalo2 for ZKT {
         // TODO: add other parameters
         // e.g. setup parameters
     ) -> Result<(Vec<u8>, Vec<u8>), traits::Error> {
-        gen_proof(coefs, xs)
+        gen_proof(coefs, xs, self.opts)
+    }
+
+    fn verify_proof(out: Vec<u8>, proof: Vec<u8>) -> Result<(), traits::Error> {
+        verify_proof(out, proof)
+    }
+
+    fn gen_pod_proof(
+        &self,
+        params: &PoDParams,
+        statement: &Statement,
+        pos: &Point,
+        rspr: &BigInt,
+        neighbours: &[Neighbour],
+    ) -> Result<(Vec<u8>, Vec<u8>), traits::Error> {
+        gen_pod_proof(params, statement, pos, rspr, neighbours, self.opts)
+    }
+
+    fn verify_pod_proof(
+        params: &PoDParams,
+        max_k: u32,
+        statement: &Statement,
+        commitment: &Fp,
+        out: Vec<u8>,
+        proof: Vec<u8>,
+    ) -> Result<PoDPublicOutput, traits::Error> {
+        verify_pod_proof(params, max_k, statement, commitment, out, proof)
+    }
+
+    fn gen_batch_pod_proof(
+        &self,
+        params: &PoDParams,
+        statement: &Statement,
+        terminals: &[PoDTerminal],
+    ) -> Result<(Vec<u8>, Vec<u8>), traits::Error> {
+        gen_batch_pod_proof(params, statement, terminals, self.opts)
+    }
+
+    fn verify_batch_pod_proof(
+        params: &PoDParams,
+        max_k: u32,
+        statement: &Statement,
+        commitments: &[Fp],
+        out: Vec<u8>,
+        proof: Vec<u8>,
+    ) -> Result<PoDBatchPublicOutput, traits::Error> {
+        verify_batch_pod_proof(params, max_k, statement, commitments, out, proof)
+    }
+
+    fn setup(&self) -> Result<(), traits::Error> {
+        (MIN_K..=self.opts.max_k).try_for_each(|k| circuit_keys(k).map(|_| ()))
     }
 }
 
@@ -396,4 +548,152 @@ mod tests {
         )
         .is_ok());
     }
+
+    #[test]
+    fn test_gen_verify_proof() {
+        let coefs: Vec<_> = (1..10).map(Fp::from).collect();
+        let xs: Vec<_> = (1..10).map(Fp::from).collect();
+        let (out, proof) = ZKT::default().gen_proof(coefs, xs).unwrap();
+        assert_eq!(ZKT::verify_proof(out.clone(), proof.clone()), Ok(()));
+
+        // a different public output must not verify against the same proof
+        let (other_out, _) = gen_proof(
+            vec![Fp::from(2)],
+            vec![Fp::from(3)],
+            ProverOptions::new(MIN_K),
+        )
+        .unwrap();
+        let mut forged = other_out[..32].to_vec();
+        forged.extend_from_slice(&out[32..]);
+        assert!(matches!(
+            ZKT::verify_proof(forged, proof.clone()),
+            Err(traits::Error::Verification(_))
+        ));
+        assert!(matches!(
+            ZKT::verify_proof(out[..8].to_vec(), proof.clone()),
+            Err(traits::Error::InvalidPublicOutput(_))
+        ));
+        let mut other_k = out.clone();
+        other_k[32] = MAX_K as u8 + 1;
+        assert!(matches!(
+            ZKT::verify_proof(other_k, proof),
+            Err(traits::Error::InvalidPublicOutput(_))
+        ));
+    }
+
+    #[test]
+    fn test_zkt_pod_proof() {
+        // the smallest PoD circuit, with real IPA keys
+        let mut cfg = PoxConfig::default();
+        cfg.zk.max_k = pod::POD_MIN_K;
+        let zkt = ZKT::new(&cfg).unwrap();
+        let params = PoDParams {
+            kernel: KernelParams {
+                coefs: vec![BigInt::from(25), BigInt::from(1)],
+                max_dis_sqr: BigInt::from(25),
+            },
+            max_diff: BigInt::from(100000),
+        };
+        let point = |x: i64, y: i64| Point {
+            x: BigInt::from(x),
+            y: BigInt::from(y),
+        };
+        let t = PoDTerminal {
+            pos: point(0, 0),
+            rspr: BigInt::from(-650000),
+            neighbours: vec![
+                Neighbour {
+                    pos: point(3, -4),
+                    rspr: BigInt::from(-800000),
+                },
+                Neighbour {
+                    pos: point(4, 0),
+                    rspr: BigInt::from(-750000),
+                },
+            ],
+        };
+        let statement = Statement {
+            remote: "remote".to_owned(),
+            terminals: vec!["a".to_owned()],
+            epoch: 1,
+            block_from: 10,
+            block_to: 20,
+            config: vec![0],
+        };
+
+        let commitment = t.commitment().unwrap();
+        let (out, proof) = zkt
+            .gen_pod_proof(&params, &statement, &t.pos, &t.rspr, &t.neighbours)
+            .unwrap();
+        let verified = ZKT::verify_pod_proof(
+            &params,
+            cfg.zk.max_k,
+            &statement,
+            &commitment,
+            out.clone(),
+            proof.clone(),
+        )
+        .unwrap();
+        assert_eq!(verified.k, pod::POD_MIN_K);
+        assert_eq!(
+            (verified.weight, verified.value),
+            params.eval(&t.pos, &t.rspr, &t.neighbours)
+        );
+        // bound to the terminal
+        let other = statement.for_terminals(vec!["b".to_owned()]);
+        assert!(matches!(
+            ZKT::verify_pod_proof(&params, cfg.zk.max_k, &other, &commitment, out, proof),
+            Err(traits::Error::Verification(_))
+        ));
+
+        let (out, proof) = zkt
+            .gen_batch_pod_proof(&params, &statement, &[t.clone()])
+            .unwrap();
+        let verified = ZKT::verify_batch_pod_proof(
+            &params,
+            cfg.zk.max_k,
+            &statement,
+            &[commitment],
+            out,
+            proof,
+        )
+        .unwrap();
+        assert_eq!(verified.terminals.len(), 1);
+        assert_eq!(
+            (
+                verified.terminals[0].weight.clone(),
+                verified.terminals[0].value.clone()
+            ),
+            params.eval(&t.pos, &t.rspr, &t.neighbours)
+        );
+    }
+
+    #[test]
+    fn test_select_k() {
+        let cap = MyCircuit::<Fp>::capacity(MIN_K);
+        assert!(cap > 0);
+        assert_eq!(select_k(1, MIN_K), Ok(MIN_K));
+        assert_eq!(select_k(cap, MIN_K + 2), Ok(MIN_K));
+        assert_eq!(select_k(cap + 1, MIN_K + 2), Ok(MIN_K + 1));
+        assert!(MyCircuit::<Fp>::capacity(MIN_K + 1) > cap);
+        assert_eq!(
+            select_k(cap + 1, MIN_K),
+            Err(traits::Error::CircuitTooLarge {
+                len: cap + 1,
+                max_len: cap,
+                max_k: MIN_K,
+            })
+        );
+
+        // more pairs than MIN_K can hold get a bigger circuit instead of a failing proof
+        let coefs: Vec<_> = (0..cap as u64 + 1).map(Fp::from).collect();
+        let (out, proof) =
+            gen_proof(coefs.clone(), coefs.clone(), ProverOptions::new(MIN_K + 1)).unwrap();
+        assert_eq!(out[32..], (MIN_K + 1).to_le_bytes());
+        assert_eq!(verify_proof(out, proof), Ok(()));
+        assert!(matches!(
+            gen_proof(coefs.clone(), coefs, ProverOptions::new(MIN_K)),
+            Err(traits::Error::CircuitTooLarge { .. })
+        ));
+    }
 }
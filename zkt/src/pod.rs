@@ -0,0 +1,1125 @@
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    pasta::{group::ff::PrimeField, Fp},
+    plonk::{self, Circuit, ConstraintSystem},
+};
+use num_bigint::{BigInt, Sign};
+
+use crate::commitment::{pod_input_commitment, CommitConfig, PoDConfig, HASH_ROWS};
+use crate::gadgets::{
+    abs_rows, bigint_to_fp, div_rem_rows, fp_to_bigint, fp_to_biguint, is_le_rows,
+    range_check_rows, signed_range_check_rows, Cell, Coeffs, GateConfig, LIMB_BITS,
+};
+use crate::kernel::{
+    check_rspr, KernelChip, KernelParams, Neighbour, NeighbourWitness, Point, RSPR_BITS, RSPR_LIMBS,
+};
+use crate::prover::{self, ProverOptions};
+use crate::setup::keys_for;
+use crate::statement::Statement;
+use crate::traits::Error;
+use crate::MAX_K;
+
+// the limb table takes 2^LIMB_BITS rows
+pub(crate) const POD_MIN_K: u32 = 9;
+// max_diff is in [0, 2^MAX_DIFF_BITS)
+pub const MAX_DIFF_BITS: usize = RSPR_BITS;
+const MAX_DIFF_LIMBS: usize = (MAX_DIFF_BITS + LIMB_BITS - 1) / LIMB_BITS;
+// diff and value_for_remote are bounded by max_diff and rspr
+const QUOTIENT_LIMBS: usize = RSPR_LIMBS;
+// total weight and value take up to 20 bits more than a numerator (2^20 neighbours), rspr 40 more
+const EXTRA_LIMBS: usize = 8;
+
+// instance layout:
+// | weight | value | commitment | max_diff | max_dis_sqr | coefs[0] | ... | coefs[order] |
+const INSTANCE_WEIGHT: usize = 0;
+const INSTANCE_VALUE: usize = 1;
+const INSTANCE_COMMITMENT: usize = 2;
+const INSTANCE_MAX_DIFF: usize = 3;
+// rows of each neighbour slot besides the kernel: value and weight accumulators, packed point
+// and commitment chain
+const SLOT_EXTRA_ROWS: usize = 5;
+// | weight | value | commitment | k | order | of the encoded public output
+const HEADER_LEN: usize = 104;
+
+/// Parameters of the PoD weight: the kernel and the linear penalty max_diff - diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoDParams {
+    pub kernel: KernelParams,
+    pub max_diff: BigInt,
+}
+
+/// Public output of the PoD proof, the positions and rspr of the terminals are only bound by
+/// the commitment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoDPublicOutput {
+    // max(max_diff - |total_weight * rspr - total_value| / total_weight, 0)
+    pub weight: BigInt,
+    // total_value / total_weight, rounded toward zero
+    pub value: BigInt,
+    // pod_input_commitment of the terminal and its neighbours
+    pub commitment: Fp,
+    pub k: u32,
+    pub params: PoDParams,
+}
+
+impl PoDParams {
+    /// Number of limbs of the kernel numerator, it fails when the chain does not fit in the field.
+    pub fn numer_limbs(&self) -> Result<usize, Error> {
+        if self.max_diff.sign() == Sign::Minus || self.max_diff.bits() > MAX_DIFF_BITS as u64 {
+            return Err(Error::InvalidKernel(format!(
+                "max_diff {} is not in [0, 2^{})",
+                self.max_diff, MAX_DIFF_BITS
+            )));
+        }
+        self.kernel.numer_limbs()
+    }
+
+    /// Rejects the public output of a proof made for other params, or for more than 2^max_k
+    /// rows, before the keys of its circuit are generated.
+    pub(crate) fn check_expected(
+        &self,
+        k: u32,
+        expected: &PoDParams,
+        max_k: u32,
+    ) -> Result<(), Error> {
+        if self != expected {
+            return Err(Error::InvalidPublicOutput(
+                "params do not match the expected ones".to_owned(),
+            ));
+        }
+        if k > max_k {
+            return Err(Error::InvalidPublicOutput(format!(
+                "circuit size k = {} is above max_k {}",
+                k, max_k
+            )));
+        }
+        Ok(())
+    }
+
+    /// Weight and value_for_remote of a terminal, the same as the circuit computes.
+    pub fn eval(&self, pos: &Point, rspr: &BigInt, neighbours: &[Neighbour]) -> (BigInt, BigInt) {
+        let (mut total_value, mut total_weight) = (BigInt::from(0), BigInt::from(0));
+        for n in neighbours {
+            let coef = self.kernel.eval_numer(&pos.dist_sqr(&n.pos));
+            total_value += &coef * &n.rspr;
+            total_weight += coef;
+        }
+        if total_weight.sign() == Sign::NoSign {
+            return (BigInt::from(0), BigInt::from(0));
+        }
+        let diff = BigInt::from(
+            (&total_weight * rspr - &total_value).magnitude() / total_weight.magnitude(),
+        );
+        let weight = if diff > self.max_diff {
+            BigInt::from(0)
+        } else {
+            &self.max_diff - diff
+        };
+        (weight, total_value / total_weight)
+    }
+
+    fn circuit_id(&self, limbs: usize) -> String {
+        format!("pod-o{}-l{}", self.kernel.order(), limbs)
+    }
+}
+
+/// Witness of a terminal and its neighbours.
+#[derive(Debug, Clone)]
+pub(crate) struct TerminalWitness {
+    pos: [Value<Fp>; 2],
+    rspr: Value<Fp>,
+    neighbours: Vec<NeighbourWitness>,
+}
+
+impl TerminalWitness {
+    pub(crate) fn unknown(slots: usize) -> Self {
+        Self {
+            pos: [Value::unknown(); 2],
+            rspr: Value::unknown(),
+            neighbours: vec![NeighbourWitness::unknown(); slots],
+        }
+    }
+
+    pub(crate) fn slots(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    /// Checks the inputs and pads the neighbours with inactive slots, returns the witness
+    /// with the weight and value it proves.
+    pub(crate) fn new(
+        params: &PoDParams,
+        pos: &Point,
+        rspr: &BigInt,
+        neighbours: &[Neighbour],
+        slots: usize,
+    ) -> Result<(Self, BigInt, BigInt), Error> {
+        pos.check_range()?;
+        check_rspr(rspr)?;
+        let mut witnesses = Vec::with_capacity(slots);
+        for n in neighbours {
+            n.pos.check_range()?;
+            check_rspr(&n.rspr)?;
+            witnesses.push(NeighbourWitness {
+                x: Value::known(bigint_to_fp(&n.pos.x)?),
+                y: Value::known(bigint_to_fp(&n.pos.y)?),
+                rspr: Value::known(bigint_to_fp(&n.rspr)?),
+                active: Value::known(Fp::one()),
+            });
+        }
+        if neighbours
+            .iter()
+            .all(|n| params.kernel.eval_numer(&pos.dist_sqr(&n.pos)).sign() == Sign::NoSign)
+        {
+            return Err(Error::InputOutOfRange(
+                "total weight of the neighbours is zero".to_owned(),
+            ));
+        }
+        let (weight, value) = params.eval(pos, rspr, neighbours);
+
+        // pad with inactive neighbours to the shape the keys were generated for
+        witnesses.resize(slots, NeighbourWitness::padding());
+        let witness = Self {
+            pos: [
+                Value::known(bigint_to_fp(&pos.x)?),
+                Value::known(bigint_to_fp(&pos.y)?),
+            ],
+            rspr: Value::known(bigint_to_fp(rspr)?),
+            neighbours: witnesses,
+        };
+        Ok((witness, weight, value))
+    }
+}
+
+/// Cells of the PoD params, loaded once per circuit.
+pub(crate) struct ParamCells {
+    max_diff: Cell,
+    max_dis_sqr: Cell,
+    coefs: Vec<Cell>,
+    zero: Cell,
+}
+
+/// Weight chain of a terminal: kernel sum -> diff -> clamped linear penalty.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PoDChip {
+    pub(crate) kernel: KernelChip,
+}
+
+impl PoDChip {
+    pub(crate) fn new(params: &PoDParams) -> Result<Self, Error> {
+        Ok(Self {
+            kernel: KernelChip {
+                order: params.kernel.order(),
+                numer_limbs: params.numer_limbs()?,
+            },
+        })
+    }
+
+    fn total_limbs(&self) -> usize {
+        self.kernel.numer_limbs + EXTRA_LIMBS
+    }
+
+    /// Rows of the circuit left to the regions.
+    pub(crate) fn usable_rows(k: u32) -> usize {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        PoDConfig::configure(&mut cs);
+        (1usize << k).saturating_sub(cs.minimum_rows())
+    }
+
+    // rows of a neighbour slot in terminal
+    pub(crate) fn slot_rows(&self) -> usize {
+        self.kernel.neighbour_rows() + SLOT_EXTRA_ROWS
+    }
+
+    // rows of terminal on the poseidon columns, a hash per neighbour slot and the terminal
+    pub(crate) fn hash_rows(neighbours: usize) -> usize {
+        (neighbours + 1) * HASH_ROWS
+    }
+
+    // rows of load_params
+    pub(crate) fn params_rows(&self) -> usize {
+        // max_diff, max_dis_sqr, coefs, zero
+        1 + range_check_rows(MAX_DIFF_LIMBS) + 1 + (self.kernel.order + 1) + 1
+    }
+
+    // rows of terminal, it must follow the regions assigned there
+    pub(crate) fn terminal_rows(&self, neighbours: usize) -> usize {
+        let wl = self.total_limbs();
+        // position and rspr of the terminal, packed point
+        1 + 2 * KernelChip::COORD_ROWS + signed_range_check_rows(RSPR_LIMBS) + 1
+            // neighbours with the accumulators and the commitment chain
+            + neighbours * self.slot_rows()
+            // b, |b|, bound, comparison, masked |b|, diff, weight
+            + 1 + abs_rows(wl) + 1 + is_le_rows(wl) + 1 + div_rem_rows(QUOTIENT_LIMBS, wl) + 1
+            // |value|, quotient, value
+            + abs_rows(wl) + div_rem_rows(QUOTIENT_LIMBS, wl) + 1
+    }
+
+    /// Loads max_diff, max_dis_sqr and the coefs from consecutive instance rows.
+    pub(crate) fn load_params(
+        &self,
+        config: &GateConfig,
+        mut layouter: impl Layouter<Fp>,
+        offset: usize,
+    ) -> Result<ParamCells, plonk::Error> {
+        let max_diff = config.load_instance(layouter.namespace(|| "max_diff"), offset)?;
+        config.range_check(
+            layouter.namespace(|| "max_diff range"),
+            &max_diff,
+            MAX_DIFF_LIMBS,
+        )?;
+        let max_dis_sqr = config.load_instance(layouter.namespace(|| "max_dis_sqr"), offset + 1)?;
+        let coefs = (0..=self.kernel.order)
+            .map(|i| config.load_instance(layouter.namespace(|| "coef"), offset + 2 + i))
+            .collect::<Result<Vec<_>, _>>()?;
+        let zero = config.load_constant(layouter.namespace(|| "zero"), Fp::zero())?;
+        Ok(ParamCells {
+            max_diff,
+            max_dis_sqr,
+            coefs,
+            zero,
+        })
+    }
+
+    /// Returns the weight, the value_for_remote and the input commitment of the terminal.
+    pub(crate) fn terminal(
+        &self,
+        config: &GateConfig,
+        commit: &CommitConfig,
+        mut layouter: impl Layouter<Fp>,
+        p: &ParamCells,
+        t: &TerminalWitness,
+    ) -> Result<(Cell, Cell, Cell), plonk::Error> {
+        let one = Fp::one();
+        let wl = self.total_limbs();
+        let terminal = config.load_private(
+            layouter.namespace(|| "load terminal"),
+            &[t.pos[0], t.pos[1], t.rspr],
+        )?;
+        let (pos, rspr) = (&terminal[..2], &terminal[2]);
+        for c in pos {
+            KernelChip::coord_check(config, layouter.namespace(|| "pos"), c)?;
+        }
+        KernelChip::rspr_check(config, layouter.namespace(|| "terminal rspr"), rspr)?;
+        let packed = commit.pack(
+            config,
+            layouter.namespace(|| "pack terminal"),
+            &pos[0],
+            &pos[1],
+            rspr,
+        )?;
+        let mut commitment =
+            commit.hash(layouter.namespace(|| "H(terminal, 0)"), &packed, &p.zero)?;
+
+        let (mut value, mut weight) = (p.zero.clone(), p.zero.clone());
+        for n in &t.neighbours {
+            let (coef, cells) = self.kernel.neighbour_coef(
+                config,
+                layouter.namespace(|| "neighbour"),
+                pos,
+                &p.max_dis_sqr,
+                &p.coefs,
+                n,
+            )?;
+            let (n_rspr, active) = (&cells[2], &cells[3]);
+            let packed = commit.pack(
+                config,
+                layouter.namespace(|| "pack neighbour"),
+                &cells[0],
+                &cells[1],
+                n_rspr,
+            )?;
+            commitment = commit.chain(
+                config,
+                layouter.namespace(|| "commitment"),
+                &commitment,
+                &packed,
+                active,
+            )?;
+            value = config.gate(
+                layouter.namespace(|| "value + coef * rspr"),
+                &coef,
+                n_rspr,
+                Some(&value),
+                Coeffs {
+                    m: one,
+                    e: one,
+                    ..Default::default()
+                },
+            )?;
+            weight = config.gate(
+                layouter.namespace(|| "weight + coef"),
+                &coef,
+                &coef,
+                Some(&weight),
+                Coeffs {
+                    l: one,
+                    e: one,
+                    ..Default::default()
+                },
+            )?;
+        }
+
+        // diff = |weight * rspr - value| / weight, it is only divided when diff <= max_diff,
+        // i.e. |b| <= (max_diff + 1) * weight - 1, so that the quotient stays small
+        let b = config.gate(
+            layouter.namespace(|| "b = weight * rspr - value"),
+            &weight,
+            rspr,
+            Some(&value),
+            Coeffs {
+                m: one,
+                e: -one,
+                ..Default::default()
+            },
+        )?;
+        let (_, b) = config.abs(layouter.namespace(|| "|b|"), &b, wl)?;
+        let bound = config.gate(
+            layouter.namespace(|| "max_diff * weight + weight - 1"),
+            &p.max_diff,
+            &weight,
+            None,
+            Coeffs {
+                r: one,
+                m: one,
+                c: -one,
+                ..Default::default()
+            },
+        )?;
+        let g = config.is_le(layouter.namespace(|| "|b| <= bound"), &b, &bound, wl)?;
+        let b = config.mul(layouter.namespace(|| "g * |b|"), &g, &b)?;
+        // a zero total weight leaves no valid remainder, such terminals can not be proved
+        let diff = config.div_rem(
+            layouter.namespace(|| "diff"),
+            &b,
+            &weight,
+            QUOTIENT_LIMBS,
+            wl,
+        )?;
+        // diff = 0 when g = 0
+        let pod_weight = config.gate(
+            layouter.namespace(|| "g * max_diff - diff"),
+            &g,
+            &p.max_diff,
+            Some(&diff),
+            Coeffs {
+                m: one,
+                e: -one,
+                ..Default::default()
+            },
+        )?;
+
+        // value_for_remote = sign(value) * (|value| / weight)
+        let (s, value) = config.abs(layouter.namespace(|| "|value|"), &value, wl)?;
+        let q = config.div_rem(
+            layouter.namespace(|| "|value| / weight"),
+            &value,
+            &weight,
+            QUOTIENT_LIMBS,
+            wl,
+        )?;
+        let pod_value = config.gate(
+            layouter.namespace(|| "(2 * s - 1) * q"),
+            &s,
+            &q,
+            None,
+            Coeffs {
+                r: -one,
+                m: Fp::from(2),
+                ..Default::default()
+            },
+        )?;
+        Ok((pod_weight, pod_value, commitment))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PoDCircuit {
+    chip: PoDChip,
+    terminal: TerminalWitness,
+}
+
+impl PoDCircuit {
+    /// Number of neighbours that fit in a circuit with 2^k rows.
+    fn capacity(k: u32, chip: &PoDChip) -> usize {
+        let rows = PoDChip::usable_rows(k);
+        let gate =
+            rows.saturating_sub(chip.params_rows() + chip.terminal_rows(0)) / chip.slot_rows();
+        // the poseidon columns fill up on their own
+        let hash = (rows / HASH_ROWS).saturating_sub(1);
+        gate.min(hash)
+    }
+
+    fn shape(k: u32, chip: PoDChip) -> Self {
+        Self {
+            chip,
+            terminal: TerminalWitness::unknown(Self::capacity(k, &chip)),
+        }
+    }
+}
+
+impl Circuit<Fp> for PoDCircuit {
+    type Config = PoDConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            chip: self.chip,
+            terminal: TerminalWitness::unknown(self.terminal.neighbours.len()),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PoDConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), plonk::Error> {
+        let PoDConfig {
+            gate: config,
+            commit,
+        } = config;
+        config.load_table(&mut layouter)?;
+        let params =
+            self.chip
+                .load_params(&config, layouter.namespace(|| "params"), INSTANCE_MAX_DIFF)?;
+        let (weight, value, commitment) = self.chip.terminal(
+            &config,
+            &commit,
+            layouter.namespace(|| "terminal"),
+            &params,
+            &self.terminal,
+        )?;
+
+        //expose public
+        layouter.namespace(|| "expose weight").constrain_instance(
+            weight.cell(),
+            config.instance,
+            INSTANCE_WEIGHT,
+        )?;
+        layouter.namespace(|| "expose value").constrain_instance(
+            value.cell(),
+            config.instance,
+            INSTANCE_VALUE,
+        )?;
+        layouter
+            .namespace(|| "expose commitment")
+            .constrain_instance(commitment.cell(), config.instance, INSTANCE_COMMITMENT)
+    }
+}
+
+/// Smallest k whose PoD circuit fits len neighbours.
+fn select_pod_k(len: usize, chip: &PoDChip, max_k: u32) -> Result<u32, Error> {
+    (POD_MIN_K..=max_k)
+        .find(|k| PoDCircuit::capacity(*k, chip) >= len)
+        .ok_or_else(|| Error::CircuitTooLarge {
+            len,
+            max_len: PoDCircuit::capacity(max_k, chip),
+            max_k,
+        })
+}
+
+impl PoDPublicOutput {
+    fn instance(&self) -> Result<Vec<Fp>, Error> {
+        let mut instance = [&self.weight, &self.value]
+            .into_iter()
+            .map(bigint_to_fp)
+            .collect::<Result<Vec<_>, _>>()?;
+        instance.push(self.commitment);
+        for v in [&self.params.max_diff, &self.params.kernel.max_dis_sqr]
+            .into_iter()
+            .chain(self.params.kernel.coefs.iter())
+        {
+            instance.push(bigint_to_fp(v)?);
+        }
+        Ok(instance)
+    }
+
+    /// Layout: | weight | value | commitment | k (u32 le) | order (u32 le) | max_diff |
+    /// max_dis_sqr | coefs |, every field element takes 32 bytes (Fp repr).
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let instance = self.instance()?;
+        let mut bytes = Vec::with_capacity(8 + 32 * instance.len());
+        for (i, fp) in instance.iter().enumerate() {
+            if i == INSTANCE_MAX_DIFF {
+                bytes.extend_from_slice(&self.k.to_le_bytes());
+                bytes.extend_from_slice(&(self.params.kernel.order() as u32).to_le_bytes());
+            }
+            bytes.extend_from_slice(fp.to_repr().as_ref());
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::InvalidPublicOutput(format!(
+                "expected at least {} bytes, got {}",
+                HEADER_LEN,
+                bytes.len()
+            )));
+        }
+        let read_fp = |chunk: &[u8]| -> Result<Fp, Error> {
+            let mut repr = [0u8; 32];
+            repr.copy_from_slice(chunk);
+            Option::<Fp>::from(Fp::from_repr(repr)).ok_or_else(|| {
+                Error::InvalidPublicOutput(format!("non canonical field element {:?}", repr))
+            })
+        };
+        let k = u32::from_le_bytes([bytes[96], bytes[97], bytes[98], bytes[99]]);
+        let order = u32::from_le_bytes([bytes[100], bytes[101], bytes[102], bytes[103]]) as usize;
+        if !(POD_MIN_K..=MAX_K).contains(&k) {
+            return Err(Error::InvalidPublicOutput(format!(
+                "unsupported circuit size k = {}",
+                k
+            )));
+        }
+        if bytes.len() != HEADER_LEN + 32 * (order + 3) {
+            return Err(Error::InvalidPublicOutput(format!(
+                "expected {} bytes for order {}, got {}",
+                HEADER_LEN + 32 * (order + 3),
+                order,
+                bytes.len()
+            )));
+        }
+        let params = bytes[HEADER_LEN..]
+            .chunks(32)
+            .map(|c| read_fp(c).map(|fp| BigInt::from(fp_to_biguint(&fp))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (weight, value) = read_output(&read_fp(&bytes[..32])?, &read_fp(&bytes[32..64])?)?;
+        Ok(Self {
+            weight,
+            value,
+            commitment: read_fp(&bytes[64..96])?,
+            k,
+            params: PoDParams {
+                max_diff: params[0].clone(),
+                kernel: KernelParams {
+                    max_dis_sqr: params[1].clone(),
+                    coefs: params[2..].to_vec(),
+                },
+            },
+        })
+    }
+}
+
+/// Rejects the public output of a proof made from other inputs than the expected commitment.
+pub(crate) fn check_commitment(commitment: &Fp, expected: &Fp) -> Result<(), Error> {
+    if commitment != expected {
+        return Err(Error::InvalidPublicOutput(
+            "input commitment does not match the expected one".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a proved weight and value, rejecting the field elements the circuit can not output:
+/// weight is in [0, 2^MAX_DIFF_BITS) and |value| below 2^(LIMB_BITS * QUOTIENT_LIMBS).
+pub(crate) fn read_output(weight: &Fp, value: &Fp) -> Result<(BigInt, BigInt), Error> {
+    let (weight, value) = (fp_to_bigint(weight), fp_to_bigint(value));
+    if weight.sign() == Sign::Minus || weight.bits() > MAX_DIFF_BITS as u64 {
+        return Err(Error::InvalidPublicOutput(format!(
+            "weight {} is not in [0, 2^{})",
+            weight, MAX_DIFF_BITS
+        )));
+    }
+    if value.bits() > (LIMB_BITS * QUOTIENT_LIMBS) as u64 {
+        return Err(Error::InvalidPublicOutput(format!(
+            "value {} does not fit in {} bits",
+            value,
+            LIMB_BITS * QUOTIENT_LIMBS
+        )));
+    }
+    Ok((weight, value))
+}
+
+fn circuit_witness(
+    params: &PoDParams,
+    pos: &Point,
+    rspr: &BigInt,
+    neighbours: &[Neighbour],
+    k: u32,
+) -> Result<(PoDCircuit, PoDPublicOutput), Error> {
+    let chip = PoDChip::new(params)?;
+    let slots = PoDCircuit::capacity(k, &chip);
+    let (terminal, weight, value) = TerminalWitness::new(params, pos, rspr, neighbours, slots)?;
+    let out = PoDPublicOutput {
+        weight,
+        value,
+        commitment: pod_input_commitment(pos, rspr, neighbours)?,
+        k,
+        params: params.clone(),
+    };
+    Ok((PoDCircuit { chip, terminal }, out))
+}
+
+/// Proves the weight and value_for_remote of the terminal at pos, from its neighbours.
+pub fn gen_pod_proof(
+    params: &PoDParams,
+    statement: &Statement,
+    pos: &Point,
+    rspr: &BigInt,
+    neighbours: &[Neighbour],
+    opts: ProverOptions,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let chip = PoDChip::new(params)?;
+    let k = select_pod_k(neighbours.len(), &chip, opts.max_k)?;
+    let (circuit, out) = circuit_witness(params, pos, rspr, neighbours, k)?;
+    let instance = out.instance()?;
+
+    let keys = keys_for(&params.circuit_id(chip.kernel.numer_limbs), k, || {
+        PoDCircuit::shape(k, chip)
+    })?;
+    let proof = prover::prove(&keys, circuit, &instance, Some(statement), opts)?;
+    Ok((out.encode()?, proof))
+}
+
+/// Verifies a proof made with params, in at most 2^max_k rows, from the inputs the commitment
+/// was computed from.
+pub fn verify_pod_proof(
+    params: &PoDParams,
+    max_k: u32,
+    statement: &Statement,
+    commitment: &Fp,
+    out: Vec<u8>,
+    proof: Vec<u8>,
+) -> Result<PoDPublicOutput, Error> {
+    let out = PoDPublicOutput::decode(&out)?;
+    out.params.check_expected(out.k, params, max_k)?;
+    check_commitment(&out.commitment, commitment)?;
+    let chip = PoDChip::new(&out.params)?;
+    let k = out.k;
+    let keys = keys_for(&out.params.circuit_id(chip.kernel.numer_limbs), k, || {
+        PoDCircuit::shape(k, chip)
+    })?;
+    let instance = out.instance()?;
+    prover::verify(&keys, &instance, Some(statement), &proof)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::SIGNED_FP_BITS;
+    use crate::prover::mock_prove;
+    use halo2_proofs::dev::MockProver;
+
+    fn quadratic(max_diff: i64) -> PoDParams {
+        PoDParams {
+            kernel: KernelParams {
+                coefs: vec![BigInt::from(25), BigInt::from(1)],
+                max_dis_sqr: BigInt::from(25),
+            },
+            max_diff: BigInt::from(max_diff),
+        }
+    }
+
+    fn point(x: i64, y: i64) -> Point {
+        Point {
+            x: BigInt::from(x),
+            y: BigInt::from(y),
+        }
+    }
+
+    fn neighbours() -> Vec<Neighbour> {
+        [
+            ((0, 0), -700000),
+            ((3, -4), -800000),
+            ((-6, 0), -400000),
+            ((4, 0), -750000),
+        ]
+        .iter()
+        .map(|((x, y), rspr)| Neighbour {
+            pos: point(*x, *y),
+            rspr: BigInt::from(*rspr),
+        })
+        .collect()
+    }
+
+    fn mock(
+        params: &PoDParams,
+        pos: &Point,
+        rspr: i64,
+        neighbours: &[Neighbour],
+    ) -> (
+        PoDPublicOutput,
+        Result<(), Vec<halo2_proofs::dev::VerifyFailure>>,
+    ) {
+        let k = POD_MIN_K;
+        let (circuit, out) =
+            circuit_witness(params, pos, &BigInt::from(rspr), neighbours, k).unwrap();
+        let prover = MockProver::run(k, &circuit, vec![out.instance().unwrap()]).unwrap();
+        (out, prover.verify())
+    }
+
+    #[test]
+    fn test_pod_circuit_quadratic() {
+        let params = quadratic(100000);
+        // coefs 25, 0, 0, 9: value = (25 * -700000 + 9 * -750000) / 34 = -713235.29
+        let (out, res) = mock(&params, &point(0, 0), -650000, &neighbours());
+        assert_eq!(res, Ok(()));
+        assert_eq!(out.value, BigInt::from(-713235));
+        // diff = |34 * -650000 + 24250000| / 34 = 63235
+        assert_eq!(out.weight, BigInt::from(100000 - 63235));
+
+        // diff above max_diff gives a zero weight
+        let (out, res) = mock(&quadratic(60000), &point(0, 0), -650000, &neighbours());
+        assert_eq!(res, Ok(()));
+        assert_eq!(out.weight, BigInt::from(0));
+
+        // a positive value rounds toward zero too
+        let positive: Vec<_> = neighbours()
+            .into_iter()
+            .map(|n| Neighbour { rspr: -n.rspr, ..n })
+            .collect();
+        let (out, res) = mock(&params, &point(0, 0), 713235, &positive);
+        assert_eq!(res, Ok(()));
+        assert_eq!(out.value, BigInt::from(713235));
+        assert_eq!(out.weight, BigInt::from(100000));
+    }
+
+    #[test]
+    fn test_pod_public_output_range() {
+        let out = PoDPublicOutput {
+            weight: BigInt::from(36765),
+            value: BigInt::from(-713235),
+            commitment: pod_input_commitment(&point(0, 0), &BigInt::from(-650000), &neighbours())
+                .unwrap(),
+            k: POD_MIN_K,
+            params: quadratic(100000),
+        };
+        assert_eq!(
+            PoDPublicOutput::decode(&out.encode().unwrap()),
+            Ok(out.clone())
+        );
+        // field elements the circuit can not output are not read as integers
+        for (weight, value) in [(-1i64, 0i64), (1 << 39, 0), (0, 1 << 40), (0, -(1 << 40))] {
+            let other = PoDPublicOutput {
+                weight: BigInt::from(weight),
+                value: BigInt::from(value),
+                ..out.clone()
+            };
+            assert!(matches!(
+                PoDPublicOutput::decode(&other.encode().unwrap()),
+                Err(Error::InvalidPublicOutput(_))
+            ));
+        }
+        // p - 1 and -1 would share an encoding
+        let bound = BigInt::from(1) << (SIGNED_FP_BITS - 1);
+        assert!(bigint_to_fp(&-&bound).is_ok());
+        assert!(matches!(
+            bigint_to_fp(&bound),
+            Err(Error::InputOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_pod_circuit_forged_weight() {
+        let params = quadratic(100000);
+        let k = POD_MIN_K;
+        let (circuit, out) = circuit_witness(
+            &params,
+            &point(0, 0),
+            &BigInt::from(-650000),
+            &neighbours(),
+            k,
+        )
+        .unwrap();
+        for forged in [
+            PoDPublicOutput {
+                weight: &out.weight + 1,
+                ..out.clone()
+            },
+            PoDPublicOutput {
+                value: &out.value - 1,
+                ..out.clone()
+            },
+            PoDPublicOutput {
+                params: quadratic(100001),
+                ..out.clone()
+            },
+        ] {
+            // the debug mode locates the failures
+            match mock_prove(k, &circuit, &forged.instance().unwrap()) {
+                Err(Error::Constraints(failures)) => {
+                    assert!(failures.iter().all(|f| f.row.is_some()), "{:?}", failures)
+                }
+                res => panic!("forged output accepted: {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_pod_circuit_forged_inputs() {
+        let params = quadratic(100000);
+        let k = POD_MIN_K;
+        let rspr = BigInt::from(-650000);
+        let committed = pod_input_commitment(&point(0, 0), &rspr, &neighbours()).unwrap();
+        // a terminal that claims the rspr of its neighbours, or to be its own only neighbour,
+        // gets a better weight from a witness that is valid on its own
+        let forged_rspr: Vec<_> = neighbours()
+            .into_iter()
+            .map(|n| Neighbour {
+                rspr: rspr.clone(),
+                ..n
+            })
+            .collect();
+        let own = vec![Neighbour {
+            pos: point(0, 0),
+            rspr: rspr.clone(),
+        }];
+        for forged in [forged_rspr.clone(), own] {
+            let (circuit, out) = circuit_witness(&params, &point(0, 0), &rspr, &forged, k).unwrap();
+            assert_eq!(out.weight, params.max_diff);
+            assert_eq!(mock_prove(k, &circuit, &out.instance().unwrap()), Ok(()));
+            // but not against the commitment of the snapshot
+            let out = PoDPublicOutput {
+                commitment: committed,
+                ..out
+            };
+            assert!(matches!(
+                mock_prove(k, &circuit, &out.instance().unwrap()),
+                Err(Error::Constraints(_))
+            ));
+        }
+
+        // a real proof of the forged rspr does not verify against the committed instance
+        let statement = statement();
+        let (out, proof) = gen_pod_proof(
+            &params,
+            &statement,
+            &point(0, 0),
+            &rspr,
+            &forged_rspr,
+            ProverOptions::new(POD_MIN_K),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &committed,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+        let mut claimed = PoDPublicOutput::decode(&out).unwrap();
+        claimed.commitment = committed;
+        assert!(matches!(
+            verify_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &committed,
+                claimed.encode().unwrap(),
+                proof
+            ),
+            Err(Error::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_pod_circuit_taylor() {
+        // 1 - x/(2s) + x^2/(8s^2) - x^3/(48s^3) with s = 16, multiplied by 48 * s^3
+        let params = PoDParams {
+            kernel: KernelParams {
+                coefs: vec![
+                    BigInt::from(196608),
+                    BigInt::from(6144),
+                    BigInt::from(96),
+                    BigInt::from(1),
+                ],
+                max_dis_sqr: BigInt::from(144),
+            },
+            max_diff: BigInt::from(5),
+        };
+        // d = 1, 25, 100 (numer < 0), 145 (out of range)
+        let neighbours: Vec<_> = [((2, 2), -10), ((4, 6), -20), ((11, 2), -30), ((13, 3), -40)]
+            .iter()
+            .map(|((x, y), rspr)| Neighbour {
+                pos: point(*x, *y),
+                rspr: BigInt::from(*rspr),
+            })
+            .collect();
+        let (out, res) = mock(&params, &point(1, 2), -12, &neighbours);
+        assert_eq!(res, Ok(()));
+        // (190559 * -10 + 87383 * -20) / 277942 = -13.14
+        assert_eq!(out.value, BigInt::from(-13));
+        // |277942 * -12 + 3653250| / 277942 = 1
+        assert_eq!(out.weight, BigInt::from(4));
+    }
+
+    #[test]
+    fn test_pod_circuit_capacity() {
+        let params = quadratic(100000);
+        let chip = PoDChip::new(&params).unwrap();
+        let len = PoDCircuit::capacity(POD_MIN_K, &chip);
+        assert!(len > 0);
+        let neighbours: Vec<_> = neighbours().into_iter().cycle().take(len).collect();
+        let (_, res) = mock(&params, &point(0, 0), -650000, &neighbours);
+        assert_eq!(res, Ok(()));
+        assert_eq!(
+            select_pod_k(len + 1, &chip, POD_MIN_K),
+            Err(Error::CircuitTooLarge {
+                len: len + 1,
+                max_len: len,
+                max_k: POD_MIN_K
+            })
+        );
+    }
+
+    fn statement() -> Statement {
+        Statement {
+            remote: "remote".to_owned(),
+            terminals: vec!["terminal".to_owned()],
+            epoch: 1,
+            block_from: 10,
+            block_to: 20,
+            config: vec![0],
+        }
+    }
+
+    #[test]
+    fn test_gen_verify_pod_proof() {
+        let params = quadratic(100000);
+        let statement = statement();
+        let rspr = BigInt::from(-650000);
+        let commitment = pod_input_commitment(&point(0, 0), &rspr, &neighbours()).unwrap();
+        let (out, proof) = gen_pod_proof(
+            &params,
+            &statement,
+            &point(0, 0),
+            &rspr,
+            &neighbours(),
+            ProverOptions::new(POD_MIN_K),
+        )
+        .unwrap();
+        let decoded = verify_pod_proof(
+            &params,
+            MAX_K,
+            &statement,
+            &commitment,
+            out.clone(),
+            proof.clone(),
+        )
+        .unwrap();
+        assert_eq!(decoded.weight, BigInt::from(36765));
+        assert_eq!(decoded.value, BigInt::from(-713235));
+        assert_eq!(decoded.params, params);
+
+        // the proof does not verify for another terminal or block range
+        for other in [
+            statement.for_terminals(vec!["other".to_owned()]),
+            Statement {
+                block_to: 21,
+                ..statement.clone()
+            },
+        ] {
+            assert!(matches!(
+                verify_pod_proof(
+                    &params,
+                    MAX_K,
+                    &other,
+                    &commitment,
+                    out.clone(),
+                    proof.clone()
+                ),
+                Err(Error::Verification(_))
+            ));
+        }
+
+        // claiming another weight for the same proof fails
+        let mut other = PoDPublicOutput::decode(&out).unwrap();
+        other.weight = BigInt::from(100000);
+        assert!(matches!(
+            verify_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &commitment,
+                other.encode().unwrap(),
+                proof.clone()
+            ),
+            Err(Error::Verification(_))
+        ));
+
+        // the proof is not for the inputs the verifier expects, whatever the claimed commitment
+        let moved: Vec<_> = neighbours()
+            .into_iter()
+            .map(|n| Neighbour {
+                pos: Point {
+                    x: &n.pos.x + 1,
+                    y: n.pos.y.clone(),
+                },
+                ..n
+            })
+            .collect();
+        let expected = pod_input_commitment(&point(0, 0), &rspr, &moved).unwrap();
+        assert!(matches!(
+            verify_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &expected,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+        let mut other = PoDPublicOutput::decode(&out).unwrap();
+        other.commitment = expected;
+        assert!(matches!(
+            verify_pod_proof(
+                &params,
+                MAX_K,
+                &statement,
+                &expected,
+                other.encode().unwrap(),
+                proof.clone()
+            ),
+            Err(Error::Verification(_))
+        ));
+
+        // the verifier expects other params, or smaller circuits
+        assert!(matches!(
+            verify_pod_proof(
+                &quadratic(200000),
+                MAX_K,
+                &statement,
+                &commitment,
+                out.clone(),
+                proof.clone()
+            ),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+        assert!(matches!(
+            verify_pod_proof(&params, POD_MIN_K - 1, &statement, &commitment, out, proof),
+            Err(Error::InvalidPublicOutput(_))
+        ));
+        assert!(matches!(
+            gen_pod_proof(
+                &params,
+                &statement,
+                &point(1 << 40, 0),
+                &rspr,
+                &neighbours(),
+                ProverOptions::new(POD_MIN_K)
+            ),
+            Err(Error::InputOutOfRange(_))
+        ));
+        assert!(matches!(
+            gen_pod_proof(
+                &params,
+                &statement,
+                &point(100, 100),
+                &rspr,
+                &neighbours(),
+                ProverOptions::new(POD_MIN_K)
+            ),
+            Err(Error::InputOutOfRange(_))
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use halo2_proofs::{
+    dev::{FailureLocation, MockProver, VerifyFailure},
+    pasta::Fp,
+    plonk::{self, create_proof, Circuit, SingleVerifier},
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+};
+use rand_core::OsRng;
+use tracing::warn;
+
+use crate::setup::CircuitKeys;
+use crate::statement::Statement;
+use crate::traits::{ConstraintFailure, Error};
+
+/// Options of the prover, the same for all the circuits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProverOptions {
+    // upper bound of the circuit size (2^max_k rows)
+    pub max_k: u32,
+    // run the MockProver before proving, so that a failing proof names its constraints
+    pub debug: bool,
+}
+impl ProverOptions {
+    pub fn new(max_k: u32) -> Self {
+        Self {
+            max_k,
+            debug: false,
+        }
+    }
+}
+
+impl From<&VerifyFailure> for ConstraintFailure {
+    fn from(failure: &VerifyFailure) -> Self {
+        let location = match failure {
+            VerifyFailure::ConstraintNotSatisfied { location, .. }
+            | VerifyFailure::Lookup { location, .. }
+            | VerifyFailure::Permutation { location, .. } => Some(location),
+            _ => None,
+        };
+        let (region, row) = match location {
+            Some(FailureLocation::InRegion { region, offset }) => {
+                (Some(region.to_string()), Some(*offset))
+            }
+            Some(FailureLocation::OutsideRegion { row }) => (None, Some(*row)),
+            None => match failure {
+                VerifyFailure::CellNotAssigned {
+                    region,
+                    gate_offset,
+                    ..
+                } => (Some(region.to_string()), Some(*gate_offset)),
+                _ => (None, None),
+            },
+        };
+        Self {
+            failure: failure.to_string(),
+            region,
+            row,
+        }
+    }
+}
+
+/// Runs the circuit with the MockProver, the unsatisfied constraints become the error.
+pub fn mock_prove<C: Circuit<Fp>>(k: u32, circuit: &C, instance: &[Fp]) -> Result<(), Error> {
+    let prover = MockProver::run(k, circuit, vec![instance.to_vec()])
+        .map_err(|e| Error::Synthesis(e.to_string()))?;
+    prover.verify().map_err(|failures| {
+        let failures: Vec<ConstraintFailure> = failures.iter().map(Into::into).collect();
+        failures
+            .iter()
+            .for_each(|f| warn!("zk constraint failure: {}", f));
+        Error::Constraints(failures)
+    })
+}
+
+/// Proves the circuit with a single instance column, the statement (if any) is absorbed
+/// into the transcript first.
+pub(crate) fn prove<C: Circuit<Fp>>(
+    keys: &CircuitKeys,
+    circuit: C,
+    instance: &[Fp],
+    statement: Option<&Statement>,
+    opts: ProverOptions,
+) -> Result<Vec<u8>, Error> {
+    if opts.debug {
+        mock_prove(keys.k, &circuit, instance)?;
+    }
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+    if let Some(statement) = statement {
+        statement.bind(&mut transcript)?;
+    }
+    create_proof(
+        &keys.params,
+        &keys.pk,
+        &[circuit],
+        &[&[instance]],
+        OsRng,
+        &mut transcript,
+    )
+    .map_err(|e| Error::Proving(e.to_string()))?;
+    Ok(transcript.finalize())
+}
+
+pub(crate) fn verify(
+    keys: &CircuitKeys,
+    instance: &[Fp],
+    statement: Option<&Statement>,
+    proof: &[u8],
+) -> Result<(), Error> {
+    let strategy = SingleVerifier::new(&keys.params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+    if let Some(statement) = statement {
+        statement.bind(&mut transcript)?;
+    }
+    plonk::verify_proof(
+        &keys.params,
+        keys.vk(),
+        strategy,
+        &[&[instance]],
+        &mut transcript,
+    )
+    .map_err(|e| Error::Verification(e.to_string()))
+}
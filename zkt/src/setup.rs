@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use config::BASE_CONFIG;
+use halo2_proofs::pasta::{EqAffine, Fp};
+use halo2_proofs::plonk::{keygen_pk, keygen_vk, Circuit, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::Params;
+use tracing::{debug, info, warn};
+
+use crate::traits::Error;
+use crate::MyCircuit;
+
+// bump it whenever the circuit layout changes, so that stale files on disk are ignored
+pub const SETUP_VERSION: u32 = 2;
+
+/// Params and keys of the circuit with 2^k rows.
+/// The verifying key is saved with the params, halo2 has no serialization of the proving
+/// key: it is derived (deterministically) from the saved verifying key once per process and
+/// then shared by all the workers.
+#[derive(Debug)]
+pub struct CircuitKeys {
+    pub k: u32,
+    pub params: Params<EqAffine>,
+    pub pk: ProvingKey<EqAffine>,
+}
+impl CircuitKeys {
+    pub fn vk(&self) -> &VerifyingKey<EqAffine> {
+        self.pk.get_vk()
+    }
+}
+
+// a slot per key, its own lock is held while the value is generated
+type Slot<T> = Arc<Mutex<T>>;
+
+fn get_slot<K: Eq + Hash, T: Default>(
+    slots: &Mutex<HashMap<K, Slot<T>>>,
+    key: K,
+) -> Result<Slot<T>, Error> {
+    let mut slots = slots
+        .lock()
+        .map_err(|e| Error::Keygen(format!("key store poisoned: {}", e)))?;
+    Ok(slots.entry(key).or_default().clone())
+}
+
+/// Keys of the circuits generated by this process, the params and verifying keys are saved
+/// in dir. Callers of the same circuit wait for a single keygen, the other circuits are not
+/// blocked.
+pub(crate) struct KeyStore {
+    dir: PathBuf,
+    // keyed by the circuit id and k
+    keys: Mutex<HashMap<(String, u32), Slot<Option<Arc<CircuitKeys>>>>>,
+    // the circuits with the same k share the params
+    params: Mutex<HashMap<u32, Slot<Option<Params<EqAffine>>>>>,
+}
+
+lazy_static::lazy_static! {
+    static ref KEY_STORE: KeyStore = KeyStore::new(params_dir());
+}
+
+pub fn params_dir() -> PathBuf {
+    BASE_CONFIG.root_path.join("data").join("zkt")
+}
+
+fn params_path(dir: &Path, k: u32) -> PathBuf {
+    dir.join(format!("params-v{}-k{}.bin", SETUP_VERSION, k))
+}
+
+fn vk_path(dir: &Path, id: &str, k: u32) -> PathBuf {
+    dir.join(format!("vk-v{}-{}-k{}.bin", SETUP_VERSION, id, k))
+}
+
+fn read_params(path: &Path, k: u32) -> Result<Params<EqAffine>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|e| Error::Io(path.to_string_lossy().to_string(), e.to_string()))?;
+    // check k before reading, Params::read allocates 2^k points
+    if bytes.len() < 4 || u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != k {
+        return Err(Error::Io(
+            path.to_string_lossy().to_string(),
+            format!("params file is not for k = {}", k),
+        ));
+    }
+    Params::read(&mut bytes.as_slice())
+        .map_err(|e| Error::Io(path.to_string_lossy().to_string(), e.to_string()))
+}
+
+fn params_bytes(params: &Params<EqAffine>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // writing to a vec does not fail
+    let _ = params.write(&mut bytes);
+    bytes
+}
+
+fn read_vk<C: Circuit<Fp>>(path: &Path, k: u32) -> Result<VerifyingKey<EqAffine>, Error> {
+    let io_err = |e: std::io::Error| Error::Io(path.to_string_lossy().to_string(), e.to_string());
+    let mut reader = BufReader::new(File::open(path).map_err(io_err)?);
+    let vk = VerifyingKey::<EqAffine>::read::<_, C>(&mut reader).map_err(io_err)?;
+    if vk.get_domain().k() != k {
+        return Err(Error::Io(
+            path.to_string_lossy().to_string(),
+            format!("verifying key is not for k = {}", k),
+        ));
+    }
+    Ok(vk)
+}
+
+/// Writes through a temporary file, so that a crash never leaves a truncated file.
+fn write_file(
+    dir: &Path,
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<(), Error> {
+    let io_err = |e: std::io::Error| Error::Io(path.to_string_lossy().to_string(), e.to_string());
+    fs::create_dir_all(dir).map_err(io_err)?;
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp).map_err(io_err)?);
+    write(&mut writer).map_err(io_err)?;
+    writer.flush().map_err(io_err)?;
+    fs::rename(&tmp, path).map_err(io_err)
+}
+
+impl KeyStore {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            keys: Mutex::new(HashMap::new()),
+            params: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The params of the IPA commitment are derived by hashing, without any trusted setup:
+    /// they are generated once per process and the saved file is only kept if it is the
+    /// same, so that a tampered or corrupted file is never used.
+    fn params(&self, k: u32) -> Result<Params<EqAffine>, Error> {
+        let slot = get_slot(&self.params, k)?;
+        let mut params = slot
+            .lock()
+            .map_err(|e| Error::Keygen(format!("params lock poisoned: {}", e)))?;
+        if let Some(params) = params.as_ref() {
+            return Ok(params.clone());
+        }
+        let generated: Params<EqAffine> = Params::new(k);
+        let path = params_path(&self.dir, k);
+        let checked = match read_params(&path, k) {
+            Ok(saved) if params_bytes(&saved) == params_bytes(&generated) => Ok(()),
+            Ok(_) => Err(format!(
+                "{} is not Params::new({})",
+                path.to_string_lossy(),
+                k
+            )),
+            Err(e) => Err(e.to_string()),
+        };
+        match checked {
+            Ok(()) => debug!(message = "zk params checked", path = %path.to_string_lossy(), k),
+            Err(e) => {
+                if path.exists() {
+                    warn!("{}, replacing the params", e);
+                }
+                match write_file(&self.dir, &path, |w| generated.write(w)) {
+                    Ok(()) => {
+                        debug!(message = "zk params saved", path = %path.to_string_lossy(), k)
+                    }
+                    // the params are still usable, they just will not be on disk
+                    Err(e) => warn!("{}, params not saved", e),
+                }
+            }
+        }
+        *params = Some(generated.clone());
+        Ok(generated)
+    }
+
+    /// Get the keys of the circuit identified by id with 2^k rows.
+    /// The id has to change whenever shape() builds a different circuit.
+    pub(crate) fn keys_for<C: Circuit<Fp>>(
+        &self,
+        id: &str,
+        k: u32,
+        shape: impl FnOnce() -> C,
+    ) -> Result<Arc<CircuitKeys>, Error> {
+        let slot = get_slot(&self.keys, (id.to_owned(), k))?;
+        // concurrent callers of the same circuit wait instead of generating twice
+        let mut keys = slot
+            .lock()
+            .map_err(|e| Error::Keygen(format!("key store poisoned: {}", e)))?;
+        if let Some(keys) = keys.as_ref() {
+            return Ok(keys.clone());
+        }
+        let start = Instant::now();
+        let params = self.params(k)?;
+        let circuit = shape();
+        let path = vk_path(&self.dir, id, k);
+        let vk = match read_vk::<C>(&path, k) {
+            Ok(vk) => {
+                debug!(message = "zk verifying key loaded", path = %path.to_string_lossy(), k);
+                vk
+            }
+            Err(e) => {
+                if path.exists() {
+                    warn!("{}, regenerating the verifying key", e);
+                }
+                let vk = keygen_vk(&params, &circuit).map_err(|e| Error::Keygen(e.to_string()))?;
+                match write_file(&self.dir, &path, |w| vk.write(w)) {
+                    Ok(()) => {
+                        debug!(message = "zk verifying key saved", path = %path.to_string_lossy(), k)
+                    }
+                    Err(e) => warn!("{}, verifying key not saved", e),
+                }
+                vk
+            }
+        };
+        // the circuit of a saved verifying key only changes with SETUP_VERSION
+        let pk = keygen_pk(&params, vk, &circuit).map_err(|e| Error::Keygen(e.to_string()))?;
+        info!(message = "zk keys ready", id, k, setup_time = ?start.elapsed());
+        let generated = Arc::new(CircuitKeys { k, params, pk });
+        *keys = Some(generated.clone());
+        Ok(generated)
+    }
+}
+
+/// Get the keys of the dot product circuit with 2^k rows, generating them on the first call.
+pub fn circuit_keys(k: u32) -> Result<Arc<CircuitKeys>, Error> {
+    keys_for("dot", k, || MyCircuit::<Fp>::shape(k))
+}
+
+/// Get the keys of the circuit identified by id with 2^k rows, from the store of the process.
+pub(crate) fn keys_for<C: Circuit<Fp>>(
+    id: &str,
+    k: u32,
+    shape: impl FnOnce() -> C,
+) -> Result<Arc<CircuitKeys>, Error> {
+    KEY_STORE.keys_for(id, k, shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_keys_cached() {
+        let k = crate::MIN_K;
+        let dir = std::env::temp_dir().join(format!("zkt-keys-{}", std::process::id()));
+        let store = KeyStore::new(dir.clone());
+        let shape = || MyCircuit::<Fp>::shape(k);
+        // concurrent callers share a single keygen
+        let (keys, again) = std::thread::scope(|s| {
+            let a = s.spawn(|| store.keys_for("dot", k, shape).unwrap());
+            let b = s.spawn(|| store.keys_for("dot", k, shape).unwrap());
+            (a.join().unwrap(), b.join().unwrap())
+        });
+        assert_eq!(keys.k, k);
+        assert!(Arc::ptr_eq(&keys, &again));
+        assert!(params_path(&dir, k).exists());
+        let params = read_params(&params_path(&dir, k), k).unwrap();
+        assert_eq!(params_bytes(&params), params_bytes(&keys.params));
+        assert_eq!(params_bytes(&params), params_bytes(&Params::new(k)));
+        let vk_bytes = |vk: &VerifyingKey<EqAffine>| {
+            let mut bytes = Vec::new();
+            vk.write(&mut bytes).unwrap();
+            bytes
+        };
+        let vk = read_vk::<MyCircuit<Fp>>(&vk_path(&dir, "dot", k), k).unwrap();
+        assert_eq!(vk_bytes(&vk), vk_bytes(keys.vk()));
+        assert!(read_vk::<MyCircuit<Fp>>(&vk_path(&dir, "dot", k), k + 1).is_err());
+        // another store loads the same verifying key, and the pk derived from it
+        let reloaded = KeyStore::new(dir.clone())
+            .keys_for("dot", k, shape)
+            .unwrap();
+        assert_eq!(vk_bytes(reloaded.vk()), vk_bytes(keys.vk()));
+        assert_eq!(
+            format!("{:?}", reloaded.vk().pinned()),
+            format!("{:?}", keys.vk().pinned())
+        );
+
+        // a params file that is not the one of Params::new(k) is replaced
+        let other = Params::<EqAffine>::new(k + 1);
+        write_file(&dir, &params_path(&dir, k), |w| other.write(w)).unwrap();
+        let store = KeyStore::new(dir.clone());
+        assert_eq!(
+            params_bytes(&store.params(k).unwrap()),
+            params_bytes(&params)
+        );
+        assert_eq!(
+            params_bytes(&read_params(&params_path(&dir, k), k).unwrap()),
+            params_bytes(&params)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use halo2_proofs::{
+    pasta::{group::ff::FromUniformBytes, EqAffine, Fp},
+    transcript::{EncodedChallenge, Transcript},
+};
+
+use crate::traits::Error;
+
+// blake2b personalization, at most 16 bytes
+const STATEMENT_DOMAIN: &[u8] = b"zkrpc-pod-stmt";
+
+/// What a PoD proof is about. Its digest is absorbed into the transcript before the proof,
+/// so that a valid proof does not verify for another remote, terminal, epoch, block range
+/// or config.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Statement {
+    pub remote: String,
+    // in proving order, a single one for the proof of a terminal
+    pub terminals: Vec<String>,
+    pub epoch: u64,
+    pub block_from: u64,
+    pub block_to: u64,
+    // fingerprint of the config the proof depends on
+    pub config: Vec<u8>,
+}
+
+impl Statement {
+    /// The same statement for the proof of other terminals.
+    pub fn for_terminals(&self, terminals: Vec<String>) -> Self {
+        Self {
+            terminals,
+            ..self.clone()
+        }
+    }
+
+    /// Every field is length prefixed, so that no two statements share an encoding.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut put = |field: &[u8]| {
+            bytes.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(field);
+        };
+        put(self.remote.as_bytes());
+        put(&(self.terminals.len() as u64).to_le_bytes());
+        self.terminals.iter().for_each(|t| put(t.as_bytes()));
+        put(&self.epoch.to_le_bytes());
+        put(&self.block_from.to_le_bytes());
+        put(&self.block_to.to_le_bytes());
+        put(&self.config);
+        bytes
+    }
+
+    pub fn digest(&self) -> Fp {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(64)
+            .personal(STATEMENT_DOMAIN)
+            .hash(&self.encode());
+        Fp::from_uniform_bytes(hash.as_array())
+    }
+
+    /// Absorbs the digest, the prover and the verifier have to call it at the same point.
+    pub(crate) fn bind<E, T>(&self, transcript: &mut T) -> Result<(), Error>
+    where
+        E: EncodedChallenge<EqAffine>,
+        T: Transcript<EqAffine, E>,
+    {
+        transcript
+            .common_scalar(self.digest())
+            .map_err(|e| Error::Io("transcript".to_owned(), e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statement_digest() {
+        let statement = Statement {
+            remote: "remote".to_owned(),
+            terminals: vec!["a".to_owned(), "b".to_owned()],
+            epoch: 7,
+            block_from: 100,
+            block_to: 200,
+            config: vec![1, 2, 3],
+        };
+        assert_eq!(statement.digest(), statement.clone().digest());
+
+        // every field is part of the digest, and the terminals are not concatenated
+        let others = [
+            Statement {
+                remote: "remotf".to_owned(),
+                ..statement.clone()
+            },
+            statement.for_terminals(vec!["ab".to_owned()]),
+            statement.for_terminals(vec!["b".to_owned(), "a".to_owned()]),
+            Statement {
+                epoch: 8,
+                ..statement.clone()
+            },
+            Statement {
+                block_from: 101,
+                ..statement.clone()
+            },
+            Statement {
+                block_to: 201,
+                ..statement.clone()
+            },
+            Statement {
+                config: vec![1, 2],
+                ..statement.clone()
+            },
+        ];
+        for other in others {
+            assert_ne!(statement.digest(), other.digest(), "{:?}", other);
+        }
+    }
+}
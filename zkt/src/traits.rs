@@ -1,25 +1,80 @@
-use std::fmt;
-
 use halo2_proofs::arithmetic::Field;
+use num_bigint::BigInt;
+use thiserror::Error;
+
+use crate::batch::{PoDBatchPublicOutput, PoDTerminal};
+use crate::kernel::{Neighbour, Point};
+use crate::pod::{PoDParams, PoDPublicOutput};
+use crate::statement::Statement;
 // To Mike:
 // Note: traits.rs is provided as an example and feel free for editing.
 // Need 3 functions: gen_proof, verify_proof, setup
 
-// Target of zk is: without disclosing the parameters of the terminal : coordinates and alpha
-// (not disclosing means not having to submit them to the chain)
-// it is able to correctly calculate the weight of each terminal.
+// What the PoD proofs give: the weight of each terminal is the one the circuit computes
+// from the coordinates and alpha of the commitment, for the statement. They are not kept from
+// the verifier: the commitment is binding, not hiding, and the verifier is trusted with the
+// snapshots of the remote, it recomputes the commitments, the plausibility and the anomalies
+// from them.
 
-//TODO: define error here
-
-#[derive(Debug)]
-pub struct Error {}
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("Invalid public output: {0}")]
+    InvalidPublicOutput(String),
+    #[error("Proof verification failed: {0}")]
+    Verification(String),
+    #[error("Error accessing {0}: {1}")]
+    Io(String, String),
+    #[error("Key generation failed: {0}")]
+    Keygen(String),
+    #[error("Circuit synthesis failed: {0}")]
+    Synthesis(String),
+    #[error("Proof generation failed: {0}")]
+    Proving(String),
+    #[error("Unsatisfied constraints: {}", join_failures(.0))]
+    Constraints(Vec<ConstraintFailure>),
+    #[error("Circuit too large: {len} pairs, at most {max_len} fit in 2^{max_k} rows")]
+    CircuitTooLarge {
+        len: usize,
+        max_len: usize,
+        max_k: u32,
+    },
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("Invalid kernel: {0}")]
+    InvalidKernel(String),
+    #[error("Input out of range: {0}")]
+    InputOutOfRange(String),
+}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "zkproof error")
+/// A constraint the MockProver found unsatisfied, in debug mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintFailure {
+    // the failing gate constraint, lookup or permutation
+    pub failure: String,
+    // None outside of any region
+    pub region: Option<String>,
+    // offset in the region, or absolute row outside of any region
+    pub row: Option<usize>,
+}
+impl std::fmt::Display for ConstraintFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.failure)?;
+        if let Some(region) = &self.region {
+            write!(f, " in {}", region)?;
+        }
+        if let Some(row) = self.row {
+            write!(f, " at row {}", row)?;
+        }
+        Ok(())
     }
 }
-impl std::error::Error for Error {}
+fn join_failures(failures: &[ConstraintFailure]) -> String {
+    failures
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
 
 pub trait ZkTraitHalo2: std::marker::Sync {
     type F: Field;
@@ -32,12 +87,52 @@ pub trait ZkTraitHalo2: std::marker::Sync {
         // e.g. setup parameters
     ) -> Result<(Vec<u8>, Vec<u8>), Error>;
 
-    // TODO: add verify_proof function
-    // TODO: add setup function
+    // out is the public output returned by gen_proof
+    fn verify_proof(out: Vec<u8>, proof: Vec<u8>) -> Result<(), Error>;
 
-    fn verify_proof(_out: Vec<u8>, _proof: Vec<u8>) -> bool {
-        true
-    }
+    // kernel sum -> diff -> linear penalty = weight, the proof only verifies for the
+    // commitment of the positions and rspr and for the same statement
+    fn gen_pod_proof(
+        &self,
+        params: &PoDParams,
+        statement: &Statement,
+        pos: &Point,
+        rspr: &BigInt,
+        neighbours: &[Neighbour],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error>;
+
+    // returns the public output of a valid proof made with params, in at most 2^max_k rows,
+    // from the inputs of the commitment (pod_input_commitment)
+    fn verify_pod_proof(
+        params: &PoDParams,
+        max_k: u32,
+        statement: &Statement,
+        commitment: &Self::F,
+        out: Vec<u8>,
+        proof: Vec<u8>,
+    ) -> Result<PoDPublicOutput, Error>;
 
-    fn setup() {}
+    // the same chain for all the terminals of a remote, in a single proof
+    fn gen_batch_pod_proof(
+        &self,
+        params: &PoDParams,
+        statement: &Statement,
+        terminals: &[PoDTerminal],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error>;
+
+    // returns the public output of a valid aggregated proof made with params, in at most
+    // 2^max_k rows, from the inputs of the commitments of the terminals of the statement
+    fn verify_batch_pod_proof(
+        params: &PoDParams,
+        max_k: u32,
+        statement: &Statement,
+        commitments: &[Self::F],
+        out: Vec<u8>,
+        proof: Vec<u8>,
+    ) -> Result<PoDBatchPublicOutput, Error>;
+
+    // load or generate the params and keys, so that the first proof does not pay for them
+    fn setup(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }